@@ -2,6 +2,20 @@
 
 pub mod prelude;
 
+pub mod api_token;
+pub mod audit_event;
+pub mod email_verification_token;
+pub mod invitation;
+pub mod login_throttle;
+pub mod magic_link_token;
+pub mod password_reset_token;
+pub mod permission;
+pub mod recovery_code;
+pub mod role;
+pub mod role_permission;
 pub mod session;
 pub mod user;
+pub mod user_identity;
 pub mod user_profile;
+pub mod user_role;
+pub mod webauthn_credential;
@@ -1,5 +1,19 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.14
 
+pub use super::api_token::Entity as ApiToken;
+pub use super::audit_event::Entity as AuditEvent;
+pub use super::email_verification_token::Entity as EmailVerificationToken;
+pub use super::invitation::Entity as Invitation;
+pub use super::login_throttle::Entity as LoginThrottle;
+pub use super::magic_link_token::Entity as MagicLinkToken;
+pub use super::password_reset_token::Entity as PasswordResetToken;
+pub use super::permission::Entity as Permission;
+pub use super::recovery_code::Entity as RecoveryCode;
+pub use super::role::Entity as Role;
+pub use super::role_permission::Entity as RolePermission;
 pub use super::session::Entity as Session;
 pub use super::user::Entity as User;
+pub use super::user_identity::Entity as UserIdentity;
 pub use super::user_profile::Entity as UserProfile;
+pub use super::user_role::Entity as UserRole;
+pub use super::webauthn_credential::Entity as WebauthnCredential;
@@ -9,6 +9,11 @@ pub struct Model {
     pub id: String,
     pub data: String,
     pub expiry: i32,
+    pub user_id: Option<i32>,
+    pub created_at: i64,
+    pub last_seen_at: i64,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -10,6 +10,12 @@ pub struct Model {
     #[sea_orm(unique)]
     pub email: String,
     pub password: String,
+    pub email_verified_at: Option<i64>,
+    pub totp_secret: Option<String>,
+    pub totp_last_step: Option<i64>,
+    pub deletion_scheduled_at: Option<i64>,
+    pub disabled_at: Option<i64>,
+    pub password_reset_required: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
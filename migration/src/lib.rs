@@ -3,6 +3,21 @@ pub use sea_orm_migration::prelude::*;
 mod m20220101_000001_create_user_table;
 mod m20240212_003118_create_session_table;
 mod m20240214_180047_create_profile_table;
+mod m20240220_212315_create_password_reset_token_table;
+mod m20240222_194402_add_email_verification;
+mod m20240225_101734_add_two_factor;
+mod m20240228_153020_create_webauthn_credential_table;
+mod m20240303_090512_create_user_identity_table;
+mod m20240306_184211_create_login_throttle_table;
+mod m20240309_141508_add_session_metadata;
+mod m20240312_201344_add_user_deletion_scheduled_at;
+mod m20240315_103927_create_role_tables;
+mod m20240318_091547_add_user_admin_fields;
+mod m20240321_160208_create_audit_event_table;
+mod m20240324_112406_create_magic_link_token_table;
+mod m20240327_143052_create_invitation_table;
+mod m20240330_101215_create_api_token_table;
+mod m20240402_083517_add_user_totp_last_step;
 
 pub struct Migrator;
 
@@ -13,6 +28,21 @@ impl MigratorTrait for Migrator {
             Box::new(m20220101_000001_create_user_table::Migration),
             Box::new(m20240212_003118_create_session_table::Migration),
             Box::new(m20240214_180047_create_profile_table::Migration),
+            Box::new(m20240220_212315_create_password_reset_token_table::Migration),
+            Box::new(m20240222_194402_add_email_verification::Migration),
+            Box::new(m20240225_101734_add_two_factor::Migration),
+            Box::new(m20240228_153020_create_webauthn_credential_table::Migration),
+            Box::new(m20240303_090512_create_user_identity_table::Migration),
+            Box::new(m20240306_184211_create_login_throttle_table::Migration),
+            Box::new(m20240309_141508_add_session_metadata::Migration),
+            Box::new(m20240312_201344_add_user_deletion_scheduled_at::Migration),
+            Box::new(m20240315_103927_create_role_tables::Migration),
+            Box::new(m20240318_091547_add_user_admin_fields::Migration),
+            Box::new(m20240321_160208_create_audit_event_table::Migration),
+            Box::new(m20240324_112406_create_magic_link_token_table::Migration),
+            Box::new(m20240327_143052_create_invitation_table::Migration),
+            Box::new(m20240330_101215_create_api_token_table::Migration),
+            Box::new(m20240402_083517_add_user_totp_last_step::Migration),
         ]
     }
 }
@@ -0,0 +1,107 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .add_column(ColumnDef::new(User::EmailVerifiedAt).big_integer())
+                    .to_owned(),
+            )
+            .await?;
+
+        // Accounts created before verification existed keep logging in.
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs() as i64);
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(User::Table)
+                    .value(User::EmailVerifiedAt, now)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(EmailVerificationToken::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(EmailVerificationToken::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(EmailVerificationToken::UserId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(EmailVerificationToken::Email)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(EmailVerificationToken::TokenHash)
+                            .string()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(EmailVerificationToken::ExpiresAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(EmailVerificationToken::UsedAt).big_integer())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(EmailVerificationToken::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .drop_column(User::EmailVerifiedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    EmailVerifiedAt,
+}
+
+#[derive(DeriveIden)]
+enum EmailVerificationToken {
+    Table,
+    Id,
+    UserId,
+    Email,
+    TokenHash,
+    ExpiresAt,
+    UsedAt,
+}
@@ -0,0 +1,72 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(WebauthnCredential::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(WebauthnCredential::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(WebauthnCredential::UserId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(WebauthnCredential::CredentialId)
+                            .string()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(WebauthnCredential::PublicKey)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(WebauthnCredential::SignCount)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(WebauthnCredential::Name).string().not_null())
+                    .col(
+                        ColumnDef::new(WebauthnCredential::CreatedAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(WebauthnCredential::LastUsedAt).big_integer())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(WebauthnCredential::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum WebauthnCredential {
+    Table,
+    Id,
+    UserId,
+    CredentialId,
+    PublicKey,
+    SignCount,
+    Name,
+    CreatedAt,
+    LastUsedAt,
+}
@@ -0,0 +1,63 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(UserIdentity::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(UserIdentity::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(UserIdentity::UserId).integer().not_null())
+                    .col(ColumnDef::new(UserIdentity::Provider).string().not_null())
+                    .col(ColumnDef::new(UserIdentity::Subject).string().not_null())
+                    .col(ColumnDef::new(UserIdentity::Email).string())
+                    .col(
+                        ColumnDef::new(UserIdentity::CreatedAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_user_identity_provider_subject")
+                    .table(UserIdentity::Table)
+                    .col(UserIdentity::Provider)
+                    .col(UserIdentity::Subject)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(UserIdentity::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum UserIdentity {
+    Table,
+    Id,
+    UserId,
+    Provider,
+    Subject,
+    Email,
+    CreatedAt,
+}
@@ -0,0 +1,63 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(LoginThrottle::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(LoginThrottle::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(LoginThrottle::Scope).string().not_null())
+                    .col(ColumnDef::new(LoginThrottle::Subject).string().not_null())
+                    .col(ColumnDef::new(LoginThrottle::Failures).integer().not_null())
+                    .col(
+                        ColumnDef::new(LoginThrottle::LastFailureAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(LoginThrottle::LockedUntil).big_integer())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_login_throttle_scope_subject")
+                    .table(LoginThrottle::Table)
+                    .col(LoginThrottle::Scope)
+                    .col(LoginThrottle::Subject)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(LoginThrottle::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum LoginThrottle {
+    Table,
+    Id,
+    Scope,
+    Subject,
+    Failures,
+    LastFailureAt,
+    LockedUntil,
+}
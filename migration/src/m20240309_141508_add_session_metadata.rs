@@ -0,0 +1,85 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite only supports adding one column per statement.
+        for mut column in [
+            ColumnDef::new(Session::UserId).integer().to_owned(),
+            ColumnDef::new(Session::CreatedAt)
+                .big_integer()
+                .not_null()
+                .default(0)
+                .to_owned(),
+            ColumnDef::new(Session::LastSeenAt)
+                .big_integer()
+                .not_null()
+                .default(0)
+                .to_owned(),
+            ColumnDef::new(Session::IpAddress).string().to_owned(),
+            ColumnDef::new(Session::UserAgent).string().to_owned(),
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Session::Table)
+                        .add_column(&mut column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_session_user_id")
+                    .table(Session::Table)
+                    .col(Session::UserId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_session_user_id")
+                    .table(Session::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        for column in [
+            Session::UserId,
+            Session::CreatedAt,
+            Session::LastSeenAt,
+            Session::IpAddress,
+            Session::UserAgent,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Session::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Session {
+    Table,
+    UserId,
+    CreatedAt,
+    LastSeenAt,
+    IpAddress,
+    UserAgent,
+}
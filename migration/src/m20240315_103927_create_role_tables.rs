@@ -0,0 +1,187 @@
+use sea_orm_migration::prelude::*;
+
+/// Permissions known to the application, with the roles granted them by
+/// default.
+const PERMISSIONS: [(&str, &str); 2] = [
+    ("users.view", "See user accounts"),
+    ("users.manage", "Change and disable user accounts"),
+];
+const ROLES: [(&str, &str, &[&str]); 1] =
+    [("admin", "Administrators", &["users.view", "users.manage"])];
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Role::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Role::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Role::Name).string().not_null().unique_key())
+                    .col(ColumnDef::new(Role::Description).string().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Permission::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Permission::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Permission::Name)
+                            .string()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(Permission::Description).string().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(RolePermission::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(RolePermission::RoleId).integer().not_null())
+                    .col(
+                        ColumnDef::new(RolePermission::PermissionId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(RolePermission::RoleId)
+                            .col(RolePermission::PermissionId),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(UserRole::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(UserRole::UserId).integer().not_null())
+                    .col(ColumnDef::new(UserRole::RoleId).integer().not_null())
+                    .primary_key(Index::create().col(UserRole::UserId).col(UserRole::RoleId))
+                    .to_owned(),
+            )
+            .await?;
+
+        for (name, description) in PERMISSIONS {
+            manager
+                .exec_stmt(
+                    Query::insert()
+                        .into_table(Permission::Table)
+                        .columns([Permission::Name, Permission::Description])
+                        .values_panic([name.into(), description.into()])
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        for (name, description, permissions) in ROLES {
+            manager
+                .exec_stmt(
+                    Query::insert()
+                        .into_table(Role::Table)
+                        .columns([Role::Name, Role::Description])
+                        .values_panic([name.into(), description.into()])
+                        .to_owned(),
+                )
+                .await?;
+
+            for permission in permissions {
+                manager
+                    .exec_stmt(
+                        Query::insert()
+                            .into_table(RolePermission::Table)
+                            .columns([RolePermission::RoleId, RolePermission::PermissionId])
+                            .select_from(
+                                Query::select()
+                                    .column((Role::Table, Role::Id))
+                                    .column((Permission::Table, Permission::Id))
+                                    .from(Role::Table)
+                                    .from(Permission::Table)
+                                    .and_where(Expr::col((Role::Table, Role::Name)).eq(name))
+                                    .and_where(
+                                        Expr::col((Permission::Table, Permission::Name))
+                                            .eq(*permission),
+                                    )
+                                    .to_owned(),
+                            )
+                            .map_err(|e| DbErr::Migration(e.to_string()))?
+                            .to_owned(),
+                    )
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(UserRole::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(RolePermission::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Permission::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Role::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Role {
+    Table,
+    Id,
+    Name,
+    Description,
+}
+
+#[derive(DeriveIden)]
+enum Permission {
+    Table,
+    Id,
+    Name,
+    Description,
+}
+
+#[derive(DeriveIden)]
+enum RolePermission {
+    Table,
+    RoleId,
+    PermissionId,
+}
+
+#[derive(DeriveIden)]
+enum UserRole {
+    Table,
+    UserId,
+    RoleId,
+}
@@ -0,0 +1,154 @@
+use sea_orm_migration::prelude::*;
+
+const PERMISSION: (&str, &str) = ("audit.view", "See the audit log");
+const GRANTED_TO: &str = "admin";
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuditEvent::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AuditEvent::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(AuditEvent::ActorId).integer())
+                    .col(ColumnDef::new(AuditEvent::Action).string().not_null())
+                    .col(ColumnDef::new(AuditEvent::TargetId).integer())
+                    .col(ColumnDef::new(AuditEvent::IpAddress).string())
+                    .col(ColumnDef::new(AuditEvent::UserAgent).string())
+                    .col(ColumnDef::new(AuditEvent::Details).string())
+                    .col(
+                        ColumnDef::new(AuditEvent::CreatedAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(AuditEvent::PreviousHash).string().not_null())
+                    .col(ColumnDef::new(AuditEvent::Hash).string().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        for column in [AuditEvent::ActorId, AuditEvent::TargetId] {
+            manager
+                .create_index(
+                    Index::create()
+                        .name(format!("idx_audit_event_{}", column.to_string()))
+                        .table(AuditEvent::Table)
+                        .col(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        let (name, description) = PERMISSION;
+        manager
+            .exec_stmt(
+                Query::insert()
+                    .into_table(Permission::Table)
+                    .columns([Permission::Name, Permission::Description])
+                    .values_panic([name.into(), description.into()])
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .exec_stmt(
+                Query::insert()
+                    .into_table(RolePermission::Table)
+                    .columns([RolePermission::RoleId, RolePermission::PermissionId])
+                    .select_from(
+                        Query::select()
+                            .column((Role::Table, Role::Id))
+                            .column((Permission::Table, Permission::Id))
+                            .from(Role::Table)
+                            .from(Permission::Table)
+                            .and_where(Expr::col((Role::Table, Role::Name)).eq(GRANTED_TO))
+                            .and_where(Expr::col((Permission::Table, Permission::Name)).eq(name))
+                            .to_owned(),
+                    )
+                    .map_err(|e| DbErr::Migration(e.to_string()))?
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let (name, _) = PERMISSION;
+        manager
+            .exec_stmt(
+                Query::delete()
+                    .from_table(RolePermission::Table)
+                    .and_where(
+                        Expr::col(RolePermission::PermissionId).in_subquery(
+                            Query::select()
+                                .column(Permission::Id)
+                                .from(Permission::Table)
+                                .and_where(Expr::col(Permission::Name).eq(name))
+                                .to_owned(),
+                        ),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .exec_stmt(
+                Query::delete()
+                    .from_table(Permission::Table)
+                    .and_where(Expr::col(Permission::Name).eq(name))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(AuditEvent::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum AuditEvent {
+    Table,
+    Id,
+    ActorId,
+    Action,
+    TargetId,
+    IpAddress,
+    UserAgent,
+    Details,
+    CreatedAt,
+    PreviousHash,
+    Hash,
+}
+
+#[derive(DeriveIden)]
+enum Role {
+    Table,
+    Id,
+    Name,
+}
+
+#[derive(DeriveIden)]
+enum Permission {
+    Table,
+    Id,
+    Name,
+    Description,
+}
+
+#[derive(DeriveIden)]
+enum RolePermission {
+    Table,
+    RoleId,
+    PermissionId,
+}
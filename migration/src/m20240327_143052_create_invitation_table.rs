@@ -0,0 +1,150 @@
+use sea_orm_migration::prelude::*;
+
+const PERMISSION: (&str, &str) = ("invitations.manage", "Invite people and revoke invitations");
+const GRANTED_TO: &str = "admin";
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Invitation::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Invitation::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Invitation::CodeHash)
+                            .string()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(Invitation::InviterId).integer().not_null())
+                    .col(ColumnDef::new(Invitation::Email).string())
+                    .col(ColumnDef::new(Invitation::ExpiresAt).big_integer())
+                    .col(ColumnDef::new(Invitation::MaxUses).integer())
+                    .col(
+                        ColumnDef::new(Invitation::Uses)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(Invitation::CreatedAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Invitation::RevokedAt).big_integer())
+                    .to_owned(),
+            )
+            .await?;
+
+        let (name, description) = PERMISSION;
+        manager
+            .exec_stmt(
+                Query::insert()
+                    .into_table(Permission::Table)
+                    .columns([Permission::Name, Permission::Description])
+                    .values_panic([name.into(), description.into()])
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .exec_stmt(
+                Query::insert()
+                    .into_table(RolePermission::Table)
+                    .columns([RolePermission::RoleId, RolePermission::PermissionId])
+                    .select_from(
+                        Query::select()
+                            .column((Role::Table, Role::Id))
+                            .column((Permission::Table, Permission::Id))
+                            .from(Role::Table)
+                            .from(Permission::Table)
+                            .and_where(Expr::col((Role::Table, Role::Name)).eq(GRANTED_TO))
+                            .and_where(Expr::col((Permission::Table, Permission::Name)).eq(name))
+                            .to_owned(),
+                    )
+                    .map_err(|e| DbErr::Migration(e.to_string()))?
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let (name, _) = PERMISSION;
+        manager
+            .exec_stmt(
+                Query::delete()
+                    .from_table(RolePermission::Table)
+                    .and_where(
+                        Expr::col(RolePermission::PermissionId).in_subquery(
+                            Query::select()
+                                .column(Permission::Id)
+                                .from(Permission::Table)
+                                .and_where(Expr::col(Permission::Name).eq(name))
+                                .to_owned(),
+                        ),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .exec_stmt(
+                Query::delete()
+                    .from_table(Permission::Table)
+                    .and_where(Expr::col(Permission::Name).eq(name))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(Invitation::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Invitation {
+    Table,
+    Id,
+    CodeHash,
+    InviterId,
+    Email,
+    ExpiresAt,
+    MaxUses,
+    Uses,
+    CreatedAt,
+    RevokedAt,
+}
+
+#[derive(DeriveIden)]
+enum Role {
+    Table,
+    Id,
+    Name,
+}
+
+#[derive(DeriveIden)]
+enum Permission {
+    Table,
+    Id,
+    Name,
+    Description,
+}
+
+#[derive(DeriveIden)]
+enum RolePermission {
+    Table,
+    RoleId,
+    PermissionId,
+}
@@ -0,0 +1,66 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ApiToken::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ApiToken::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ApiToken::UserId).integer().not_null())
+                    .col(ColumnDef::new(ApiToken::Name).string().not_null())
+                    .col(
+                        ColumnDef::new(ApiToken::TokenHash)
+                            .string()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(ApiToken::Scopes).string().not_null())
+                    .col(ColumnDef::new(ApiToken::CreatedAt).big_integer().not_null())
+                    .col(ColumnDef::new(ApiToken::ExpiresAt).big_integer())
+                    .col(ColumnDef::new(ApiToken::LastUsedAt).big_integer())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_api_token_user_id")
+                    .table(ApiToken::Table)
+                    .col(ApiToken::UserId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ApiToken::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum ApiToken {
+    Table,
+    Id,
+    UserId,
+    Name,
+    TokenHash,
+    Scopes,
+    CreatedAt,
+    ExpiresAt,
+    LastUsedAt,
+}
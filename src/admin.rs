@@ -0,0 +1,5 @@
+mod audit_page;
+mod db_admin_user;
+mod invitations_page;
+pub mod router;
+mod users_page;
@@ -0,0 +1,304 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Query, State},
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    response::{IntoResponse, Response},
+};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use tower_sessions::cookie::time::{
+    format_description::well_known::Rfc3339, Date, Month, OffsetDateTime,
+};
+
+use super::db_admin_user;
+use crate::{
+    app::AppState,
+    audit::{
+        action::Action,
+        db_audit_event::{self, AuditEventModel, AuditFilter},
+    },
+    auth::db_user,
+    error::AppError,
+    layout::template_response::TemplateResponse,
+    user::sessions_page::format_date_time,
+};
+
+#[derive(Deserialize, Serialize, Default)]
+pub struct AuditQuery {
+    #[serde(default)]
+    action: String,
+    #[serde(default)]
+    user: String,
+    #[serde(default)]
+    from: String,
+    #[serde(default)]
+    to: String,
+    #[serde(skip_serializing)]
+    page: Option<u64>,
+}
+
+impl AuditQuery {
+    /// The filters as a query string, to carry them over to other pages and
+    /// the export.
+    fn query_string(&self) -> String {
+        [
+            ("action", &self.action),
+            ("user", &self.user),
+            ("from", &self.from),
+            ("to", &self.to),
+        ]
+        .into_iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(name, value)| format!("{}={}", name, utf8_percent_encode(value, NON_ALPHANUMERIC)))
+        .collect::<Vec<_>>()
+        .join("&")
+    }
+}
+
+/// Parses a `YYYY-MM-DD` date, as sent by date inputs.
+fn parse_date(value: &str) -> Option<Date> {
+    let mut parts = value.splitn(3, '-');
+    let year = parts.next()?.parse().ok()?;
+    let month = Month::try_from(parts.next()?.parse::<u8>().ok()?).ok()?;
+    let day = parts.next()?.parse().ok()?;
+
+    Date::from_calendar_date(year, month, day).ok()
+}
+
+fn start_of_day(date: Date) -> i64 {
+    date.midnight().assume_utc().unix_timestamp()
+}
+
+/// Turns the query into a filter, or None if it names a user that doesn't
+/// exist, in which case nothing can match.
+async fn audit_filter(app: &AppState, query: &AuditQuery) -> Option<AuditFilter> {
+    let user_id = match query.user.trim() {
+        "" => None,
+        email => Some(
+            db_user::get_user_by_email(&app.database_connection, email)
+                .await?
+                .id,
+        ),
+    };
+
+    Some(AuditFilter {
+        action: Some(query.action.clone()).filter(|action| !action.is_empty()),
+        user_id,
+        from: parse_date(&query.from).map(start_of_day),
+        to: parse_date(&query.to)
+            .and_then(|date| date.next_day())
+            .map(start_of_day),
+    })
+}
+
+#[derive(Serialize)]
+pub struct ActionOption {
+    name: &'static str,
+    label: &'static str,
+    selected: bool,
+}
+
+#[derive(Serialize)]
+pub struct AuditRow {
+    id: i32,
+    occurred_at: String,
+    action: String,
+    actor: Option<String>,
+    target: Option<String>,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+    details: Option<String>,
+}
+
+#[derive(Serialize, Default)]
+pub struct AuditPage {
+    filter: AuditQuery,
+    actions: Vec<ActionOption>,
+    events: Vec<AuditRow>,
+    total: u64,
+    page: u64,
+    pages: u64,
+    previous_url: Option<String>,
+    next_url: Option<String>,
+    export_url: String,
+}
+
+fn action_label(name: &str) -> String {
+    Action::from_name(name)
+        .map(|action| action.label().to_string())
+        .unwrap_or_else(|| name.to_string())
+}
+
+/// Shows a user by email, or by id once they have been deleted.
+fn user_name(emails: &HashMap<i32, String>, user_id: Option<i32>) -> Option<String> {
+    user_id.map(|user_id| {
+        emails
+            .get(&user_id)
+            .cloned()
+            .unwrap_or_else(|| format!("Deleted user #{}", user_id))
+    })
+}
+
+async fn with_user_names(
+    app: &AppState,
+    events: Vec<AuditEventModel>,
+) -> Vec<(AuditEventModel, Option<String>, Option<String>)> {
+    let user_ids = events
+        .iter()
+        .flat_map(|event| [event.actor_id, event.target_id])
+        .flatten()
+        .collect();
+    let emails = match db_admin_user::get_user_emails(&app.database_connection, user_ids).await {
+        Ok(emails) => emails,
+        Err(e) => {
+            tracing::error!("Failed to load audit log users: {:?}", e);
+            Default::default()
+        }
+    };
+
+    events
+        .into_iter()
+        .map(|event| {
+            let actor = user_name(&emails, event.actor_id);
+            let target = user_name(&emails, event.target_id);
+            (event, actor, target)
+        })
+        .collect()
+}
+
+pub async fn get_audit_page(
+    State(app): State<AppState>,
+    Query(query): Query<AuditQuery>,
+) -> Response {
+    let response = TemplateResponse::new("admin/audit");
+    let page = query.page.unwrap_or(1).max(1);
+    let query_string = query.query_string();
+    let page_url = |page: u64| {
+        if query_string.is_empty() {
+            format!("/admin/audit?page={}", page)
+        } else {
+            format!("/admin/audit?{}&page={}", query_string, page)
+        }
+    };
+    let mut audit_page = AuditPage {
+        actions: Action::ALL
+            .into_iter()
+            .map(|action| ActionOption {
+                name: action.as_str(),
+                label: action.label(),
+                selected: action.as_str() == query.action,
+            })
+            .collect(),
+        page,
+        export_url: format!("/admin/audit/export?{}", query_string),
+        ..Default::default()
+    };
+
+    let Some(filter) = audit_filter(&app, &query).await else {
+        audit_page.filter = query;
+        return response.content(audit_page).into_response();
+    };
+    let list = match db_audit_event::list_events(&app.database_connection, &filter, page).await {
+        Ok(list) => list,
+        Err(e) => {
+            tracing::error!("Failed to list audit events: {:?}", e);
+            audit_page.filter = query;
+            return response
+                .content(audit_page)
+                .add_error_message("Failed to load the audit log, try again later")
+                .into_response();
+        }
+    };
+
+    audit_page.events = with_user_names(&app, list.events)
+        .await
+        .into_iter()
+        .map(|(event, actor, target)| AuditRow {
+            id: event.id,
+            occurred_at: format_date_time(event.created_at),
+            action: action_label(&event.action),
+            actor,
+            target,
+            ip_address: event.ip_address,
+            user_agent: event.user_agent,
+            details: event.details,
+        })
+        .collect();
+    audit_page.total = list.total;
+    audit_page.pages = list.pages;
+    audit_page.previous_url = (page > 1).then(|| page_url(page - 1));
+    audit_page.next_url = (page < list.pages).then(|| page_url(page + 1));
+    audit_page.filter = query;
+
+    response.content(audit_page).into_response()
+}
+
+fn format_timestamp(timestamp: i64) -> String {
+    OffsetDateTime::from_unix_timestamp(timestamp)
+        .ok()
+        .and_then(|date_time| date_time.format(&Rfc3339).ok())
+        .unwrap_or_default()
+}
+
+fn audit_csv(
+    events: Vec<(AuditEventModel, Option<String>, Option<String>)>,
+) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record([
+        "id",
+        "occurred_at",
+        "action",
+        "actor_id",
+        "actor",
+        "target_id",
+        "target",
+        "ip_address",
+        "user_agent",
+        "details",
+        "hash",
+    ])?;
+    for (event, actor, target) in events {
+        writer.write_record([
+            event.id.to_string(),
+            format_timestamp(event.created_at),
+            event.action,
+            event.actor_id.map(|id| id.to_string()).unwrap_or_default(),
+            actor.unwrap_or_default(),
+            event.target_id.map(|id| id.to_string()).unwrap_or_default(),
+            target.unwrap_or_default(),
+            event.ip_address.unwrap_or_default(),
+            event.user_agent.unwrap_or_default(),
+            event.details.unwrap_or_default(),
+            event.hash,
+        ])?;
+    }
+
+    writer
+        .into_inner()
+        .map_err(|e| csv::Error::from(e.into_error()))
+}
+
+pub async fn get_audit_export(
+    State(app): State<AppState>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Response, AppError> {
+    let events = match audit_filter(&app, &query).await {
+        Some(filter) => db_audit_event::all_events(&app.database_connection, &filter).await?,
+        None => Vec::new(),
+    };
+    let csv = audit_csv(with_user_names(&app, events).await)
+        .map_err(|e| AppError::Internal(format!("Failed to write audit log CSV: {:?}", e)))?;
+
+    Ok((
+        [
+            (CONTENT_TYPE, "text/csv; charset=utf-8"),
+            (
+                CONTENT_DISPOSITION,
+                "attachment; filename=\"audit-log.csv\"",
+            ),
+        ],
+        csv,
+    )
+        .into_response())
+}
@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+
+use entity::{user, user_profile};
+use sea_orm::{
+    sea_query::{Expr, LikeExpr, Query},
+    ColumnTrait, Condition, DatabaseConnection, DbErr, EntityTrait, PaginatorTrait, QueryFilter,
+    QueryOrder, TransactionTrait,
+};
+use tower_sessions::cookie::time::OffsetDateTime;
+
+use crate::user::db_user_session;
+
+pub const PAGE_SIZE: u64 = 20;
+
+pub type UserModel = user::Model;
+pub type UserProfileModel = user_profile::Model;
+
+pub struct UserListPage {
+    pub users: Vec<(UserModel, Option<UserProfileModel>)>,
+    pub total: u64,
+    pub pages: u64,
+}
+
+/// A LIKE pattern matching the search text anywhere, with the `%` and `_`
+/// the admin typed matched as themselves rather than as wildcards.
+fn contains_pattern(search: &str) -> LikeExpr {
+    let escaped = search
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+
+    LikeExpr::new(format!("%{}%", escaped)).escape('\\')
+}
+
+/// Lists users by id, optionally only those whose email or display name
+/// contains the search text. Pages are numbered from 1.
+pub async fn list_users(
+    db: &DatabaseConnection,
+    search: Option<&str>,
+    page: u64,
+) -> Result<UserListPage, DbErr> {
+    let mut query = user::Entity::find().order_by_asc(user::Column::Id);
+    if let Some(search) = search {
+        query = query.filter(
+            Condition::any()
+                .add(Expr::col((user::Entity, user::Column::Email)).like(contains_pattern(search)))
+                .add(
+                    user::Column::Id.in_subquery(
+                        Query::select()
+                            .column(user_profile::Column::Id)
+                            .from(user_profile::Entity)
+                            .and_where(
+                                Expr::col(user_profile::Column::DisplayName)
+                                    .like(contains_pattern(search)),
+                            )
+                            .to_owned(),
+                    ),
+                ),
+        );
+    }
+
+    let paginator = query.paginate(db, PAGE_SIZE);
+    let counts = paginator.num_items_and_pages().await?;
+    let users = paginator.fetch_page(page.saturating_sub(1)).await?;
+
+    let mut profiles = user_profile::Entity::find()
+        .filter(user_profile::Column::Id.is_in(users.iter().map(|user| user.id)))
+        .all(db)
+        .await?;
+    let users = users
+        .into_iter()
+        .map(|user| {
+            let profile = profiles
+                .iter()
+                .position(|profile| profile.id == user.id)
+                .map(|index| profiles.swap_remove(index));
+            (user, profile)
+        })
+        .collect();
+
+    Ok(UserListPage {
+        users,
+        total: counts.number_of_items,
+        pages: counts.number_of_pages,
+    })
+}
+
+pub async fn get_user(
+    db: &DatabaseConnection,
+    user_id: i32,
+) -> Result<Option<(UserModel, Option<UserProfileModel>)>, DbErr> {
+    let Some(user) = user::Entity::find_by_id(user_id).one(db).await? else {
+        return Ok(None);
+    };
+    let profile = user_profile::Entity::find_by_id(user_id).one(db).await?;
+
+    Ok(Some((user, profile)))
+}
+
+/// Disables or enables an account, returning false if the user doesn't
+/// exist. Disabling also signs the user out everywhere.
+pub async fn set_disabled(
+    db: &DatabaseConnection,
+    user_id: i32,
+    disabled: bool,
+) -> Result<bool, DbErr> {
+    let disabled_at = disabled.then(|| OffsetDateTime::now_utc().unix_timestamp());
+    let txn = db.begin().await?;
+
+    let result = user::Entity::update_many()
+        .col_expr(user::Column::DisabledAt, Expr::value(disabled_at))
+        .filter(user::Column::Id.eq(user_id))
+        .exec(&txn)
+        .await?;
+    if result.rows_affected == 0 {
+        return Ok(false);
+    }
+    if disabled {
+        db_user_session::delete_all_sessions(&txn, user_id).await?;
+    }
+
+    txn.commit().await?;
+
+    Ok(true)
+}
+
+/// Refuses password logins until the user sets a new password, and signs
+/// them out everywhere.
+pub async fn require_password_reset(db: &DatabaseConnection, user_id: i32) -> Result<(), DbErr> {
+    let txn = db.begin().await?;
+
+    user::Entity::update_many()
+        .col_expr(user::Column::PasswordResetRequired, Expr::value(true))
+        .filter(user::Column::Id.eq(user_id))
+        .exec(&txn)
+        .await?;
+    db_user_session::delete_all_sessions(&txn, user_id).await?;
+
+    txn.commit().await
+}
+
+/// Emails of the given users, for showing who is who in the audit log.
+/// Deleted users are missing from the result.
+pub async fn get_user_emails(
+    db: &DatabaseConnection,
+    user_ids: Vec<i32>,
+) -> Result<HashMap<i32, String>, DbErr> {
+    let users = user::Entity::find()
+        .filter(user::Column::Id.is_in(user_ids))
+        .all(db)
+        .await?;
+
+    Ok(users
+        .into_iter()
+        .map(|user| (user.id, user.email))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{auth::db_user, database::connect_in_memory};
+
+    async fn create_user(db: &DatabaseConnection, email: &str) -> i32 {
+        db_user::create_user(
+            db,
+            db_user::CreateUserData {
+                email: email.to_string(),
+                password: "correct horse battery staple".to_string(),
+                email_verified: true,
+            },
+        )
+        .await
+        .unwrap()
+        .id
+    }
+
+    async fn search(db: &DatabaseConnection, search: &str) -> Vec<String> {
+        list_users(db, Some(search), 1)
+            .await
+            .unwrap()
+            .users
+            .into_iter()
+            .map(|(user, _)| user.email)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn search_matches_wildcards_literally() {
+        let db = connect_in_memory().await;
+        create_user(&db, "first_last@example.com").await;
+        create_user(&db, "firstXlast@example.com").await;
+        create_user(&db, "100%@example.com").await;
+
+        assert_eq!(search(&db, "first_last").await, ["first_last@example.com"]);
+        assert_eq!(search(&db, "%").await, ["100%@example.com"]);
+        assert_eq!(search(&db, "_").await, ["first_last@example.com"]);
+        assert_eq!(search(&db, "\\").await, Vec::<String>::new());
+        assert_eq!(search(&db, "example").await.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn set_disabled_reports_a_missing_user() {
+        let db = connect_in_memory().await;
+        let user_id = create_user(&db, "user@example.com").await;
+
+        assert!(set_disabled(&db, user_id, true).await.unwrap());
+        assert!(!set_disabled(&db, user_id + 1, true).await.unwrap());
+
+        let (user, _) = get_user(&db, user_id).await.unwrap().unwrap();
+        assert!(user.disabled_at.is_some());
+    }
+}
@@ -0,0 +1,266 @@
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+    Form,
+};
+use axum_login::AuthUser;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use tower_sessions::cookie::time::{Duration, OffsetDateTime};
+use validator::{validate_email, ValidationError, ValidationErrors};
+
+use super::db_admin_user;
+use crate::{
+    app::AppState,
+    audit::{action::Action, context::AuditContext, event::AuditEvent},
+    auth::{
+        db_invitation::{self, NewInvitation},
+        layer::AuthSession,
+    },
+    config::RegistrationMode,
+    error::AppError,
+    layout::template_response::TemplateResponse,
+    user::sessions_page::format_date_time,
+};
+
+/// Every field is optional, leaving one empty lifts that restriction.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct InvitationForm {
+    email: String,
+    expires_in_days: String,
+    max_uses: String,
+}
+
+fn field_error(errors: &mut ValidationErrors, field: &'static str, message: &'static str) {
+    let mut error = ValidationError::new("invalid");
+    error.message = Some(message.into());
+    errors.add(field, error);
+}
+
+impl InvitationForm {
+    fn parse(&self, inviter_id: i32) -> Result<NewInvitation, ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+
+        let email = Some(self.email.trim().to_string()).filter(|email| !email.is_empty());
+        if email.as_ref().is_some_and(|email| !validate_email(email)) {
+            field_error(&mut errors, "email", "Invalid email address");
+        }
+
+        let expires_in_days = self.expires_in_days.trim();
+        let expires_at = match expires_in_days.parse::<i64>() {
+            _ if expires_in_days.is_empty() => None,
+            Ok(days @ 1..=365) => {
+                Some((OffsetDateTime::now_utc() + Duration::days(days)).unix_timestamp())
+            }
+            _ => {
+                field_error(
+                    &mut errors,
+                    "expires_in_days",
+                    "Enter a number of days between 1 and 365",
+                );
+                None
+            }
+        };
+
+        let max_uses = self.max_uses.trim();
+        let max_uses = match max_uses.parse::<i32>() {
+            _ if max_uses.is_empty() => None,
+            Ok(uses) if uses >= 1 => Some(uses),
+            _ => {
+                field_error(&mut errors, "max_uses", "Enter a number of at least 1");
+                None
+            }
+        };
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        Ok(NewInvitation {
+            inviter_id,
+            email,
+            expires_at,
+            max_uses,
+        })
+    }
+}
+
+#[derive(Serialize)]
+pub struct InvitationRow {
+    id: i32,
+    inviter: Option<String>,
+    email: Option<String>,
+    created_at: String,
+    expires_at: Option<String>,
+    uses: i32,
+    max_uses: Option<i32>,
+    status: &'static str,
+    active: bool,
+}
+
+#[derive(Serialize, Default)]
+pub struct InvitationsPage {
+    registration_mode: &'static str,
+    invitations: Vec<InvitationRow>,
+    form: InvitationForm,
+    errors: Option<ValidationErrors>,
+    /// Link of the invitation just created, only shown once.
+    invitation_url: Option<String>,
+}
+
+impl InvitationsPage {
+    async fn load(app: &AppState) -> Self {
+        let db = &app.database_connection;
+        let invitations = match db_invitation::list_invitations(db).await {
+            Ok(invitations) => invitations,
+            Err(e) => {
+                tracing::error!("Failed to list invitations: {:?}", e);
+                Vec::new()
+            }
+        };
+        let inviter_ids = invitations
+            .iter()
+            .map(|invitation| invitation.inviter_id)
+            .collect();
+        let inviters = match db_admin_user::get_user_emails(db, inviter_ids).await {
+            Ok(inviters) => inviters,
+            Err(e) => {
+                tracing::error!("Failed to load inviters: {:?}", e);
+                Default::default()
+            }
+        };
+
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        Self {
+            registration_mode: match app.config.registration_mode {
+                RegistrationMode::Open => "open",
+                RegistrationMode::InviteOnly => "invite-only",
+                RegistrationMode::Closed => "closed",
+            },
+            invitations: invitations
+                .into_iter()
+                .map(|invitation| {
+                    let status = if invitation.revoked_at.is_some() {
+                        "Revoked"
+                    } else if invitation
+                        .expires_at
+                        .is_some_and(|expires_at| expires_at <= now)
+                    {
+                        "Expired"
+                    } else if invitation
+                        .max_uses
+                        .is_some_and(|max_uses| invitation.uses >= max_uses)
+                    {
+                        "Used up"
+                    } else {
+                        "Active"
+                    };
+
+                    InvitationRow {
+                        id: invitation.id,
+                        inviter: inviters.get(&invitation.inviter_id).cloned(),
+                        email: invitation.email,
+                        created_at: format_date_time(invitation.created_at),
+                        expires_at: invitation.expires_at.map(format_date_time),
+                        uses: invitation.uses,
+                        max_uses: invitation.max_uses,
+                        status,
+                        active: status == "Active",
+                    }
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+}
+
+pub async fn get_invitations_page(State(app): State<AppState>) -> Response {
+    TemplateResponse::new("admin/invitations")
+        .content(InvitationsPage::load(&app).await)
+        .into_response()
+}
+
+pub async fn post_create_invitation(
+    State(app): State<AppState>,
+    auth_session: AuthSession,
+    audit: AuditContext,
+    Form(form): Form<InvitationForm>,
+) -> Result<Response, AppError> {
+    let current_user = auth_session.user.ok_or(AppError::Unauthenticated)?;
+    let response = TemplateResponse::new("admin/invitations");
+
+    let invitation = match form.parse(current_user.id()) {
+        Ok(invitation) => invitation,
+        Err(errors) => {
+            let mut page = InvitationsPage::load(&app).await;
+            page.form = form;
+            page.errors = Some(errors);
+            return Ok(response.content(page).into_response());
+        }
+    };
+    let details = match &invitation.email {
+        Some(email) => format!("for {}", email),
+        None => "for any email".to_string(),
+    };
+
+    match db_invitation::create_invitation(&app.database_connection, invitation).await {
+        Ok(code) => {
+            let event = AuditEvent::new(Action::InvitationCreated)
+                .actor(current_user.id())
+                .details(details);
+            audit.record(&app.database_connection, event).await;
+
+            let mut page = InvitationsPage::load(&app).await;
+            page.invitation_url = Some(app.config.url(&format!(
+                "/register?invite={}",
+                utf8_percent_encode(&code, NON_ALPHANUMERIC)
+            )));
+            Ok(response
+                .content(page)
+                .add_success_message("Invitation created, share the link below")
+                .into_response())
+        }
+        Err(e) => {
+            tracing::error!("Failed to create invitation: {:?}", e);
+            let mut page = InvitationsPage::load(&app).await;
+            page.form = form;
+            Ok(response
+                .content(page)
+                .add_error_message("Failed to create the invitation, try again later")
+                .into_response())
+        }
+    }
+}
+
+pub async fn post_revoke_invitation(
+    State(app): State<AppState>,
+    auth_session: AuthSession,
+    audit: AuditContext,
+    Path(invitation_id): Path<i32>,
+) -> Result<Response, AppError> {
+    let current_user = auth_session.user.ok_or(AppError::Unauthenticated)?;
+    let response = TemplateResponse::new("admin/invitations").hx_push_url("/admin/invitations");
+
+    let response =
+        match db_invitation::revoke_invitation(&app.database_connection, invitation_id).await {
+            Ok(true) => {
+                let event = AuditEvent::new(Action::InvitationRevoked)
+                    .actor(current_user.id())
+                    .details(format!("invitation {}", invitation_id));
+                audit.record(&app.database_connection, event).await;
+                response.add_success_message("Invitation revoked")
+            }
+            Ok(false) => {
+                response.add_error_message("The invitation doesn't exist or was already revoked")
+            }
+            Err(e) => {
+                tracing::error!("Failed to revoke invitation: {:?}", e);
+                response.add_error_message("Failed to revoke the invitation, try again later")
+            }
+        };
+
+    Ok(response
+        .content(InvitationsPage::load(&app).await)
+        .into_response())
+}
@@ -0,0 +1,65 @@
+use axum::{
+    routing::{get, post},
+    Router,
+};
+use axum_login::permission_required;
+
+use super::{audit_page, invitations_page, users_page};
+use crate::{
+    app::AppState,
+    auth::{
+        authz::{MANAGE_INVITATIONS, MANAGE_USERS, VIEW_AUDIT_LOG, VIEW_USERS},
+        layer::Backend,
+    },
+};
+
+pub fn router() -> Router<AppState> {
+    let users_router = Router::new()
+        .route(
+            "/admin/users/:id/profile",
+            post(users_page::post_user_profile),
+        )
+        .route(
+            "/admin/users/:id/disable",
+            post(users_page::post_disable_user),
+        )
+        .route(
+            "/admin/users/:id/enable",
+            post(users_page::post_enable_user),
+        )
+        .route(
+            "/admin/users/:id/password-reset",
+            post(users_page::post_require_password_reset),
+        )
+        .route(
+            "/admin/users/:id/sessions/revoke",
+            post(users_page::post_revoke_sessions),
+        )
+        .route_layer(permission_required!(Backend, MANAGE_USERS))
+        .route("/admin", get(users_page::get_admin))
+        .route("/admin/users", get(users_page::get_users_page))
+        .route("/admin/users/:id", get(users_page::get_user_page))
+        .route_layer(permission_required!(Backend, VIEW_USERS));
+
+    let audit_router = Router::new()
+        .route("/admin/audit", get(audit_page::get_audit_page))
+        .route("/admin/audit/export", get(audit_page::get_audit_export))
+        .route_layer(permission_required!(Backend, VIEW_AUDIT_LOG));
+
+    let invitations_router = Router::new()
+        .route(
+            "/admin/invitations",
+            get(invitations_page::get_invitations_page),
+        )
+        .route(
+            "/admin/invitations",
+            post(invitations_page::post_create_invitation),
+        )
+        .route(
+            "/admin/invitations/:id/revoke",
+            post(invitations_page::post_revoke_invitation),
+        )
+        .route_layer(permission_required!(Backend, MANAGE_INVITATIONS));
+
+    users_router.merge(audit_router).merge(invitations_router)
+}
@@ -0,0 +1,423 @@
+use axum::{
+    extract::{Path, Query, State},
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_login::AuthUser;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationErrors};
+
+use super::db_admin_user::{self, UserModel, UserProfileModel};
+use crate::{
+    app::AppState,
+    audit::{action::Action, context::AuditContext, event::AuditEvent},
+    auth::{db_authz, db_password_reset, layer::AuthSession},
+    error::AppError,
+    layout::template_response::TemplateResponse,
+    mailer::Email,
+    user::{db_user_profile, db_user_session, sessions_page::format_date_time},
+};
+
+pub async fn get_admin() -> Redirect {
+    Redirect::to("/admin/users")
+}
+
+#[derive(Deserialize)]
+pub struct UsersQuery {
+    q: Option<String>,
+    page: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct UserRow {
+    id: i32,
+    email: String,
+    display_name: Option<String>,
+    email_verified: bool,
+    disabled: bool,
+}
+
+#[derive(Serialize, Default)]
+pub struct UsersPage {
+    search: String,
+    users: Vec<UserRow>,
+    total: u64,
+    page: u64,
+    pages: u64,
+    previous_url: Option<String>,
+    next_url: Option<String>,
+}
+
+fn users_url(search: &str, page: u64) -> String {
+    if search.is_empty() {
+        format!("/admin/users?page={}", page)
+    } else {
+        format!(
+            "/admin/users?q={}&page={}",
+            utf8_percent_encode(search, NON_ALPHANUMERIC),
+            page
+        )
+    }
+}
+
+pub async fn get_users_page(
+    State(app): State<AppState>,
+    Query(query): Query<UsersQuery>,
+) -> Response {
+    let response = TemplateResponse::new("admin/users");
+    let search = query.q.unwrap_or_default().trim().to_string();
+    let page = query.page.unwrap_or(1).max(1);
+
+    let list = match db_admin_user::list_users(
+        &app.database_connection,
+        Some(search.as_str()).filter(|search| !search.is_empty()),
+        page,
+    )
+    .await
+    {
+        Ok(list) => list,
+        Err(e) => {
+            tracing::error!("Failed to list users: {:?}", e);
+            return response
+                .content(UsersPage {
+                    search,
+                    ..Default::default()
+                })
+                .add_error_message("Failed to load users, try again later")
+                .into_response();
+        }
+    };
+
+    response
+        .content(UsersPage {
+            users: list
+                .users
+                .into_iter()
+                .map(|(user, profile)| UserRow {
+                    id: user.id,
+                    email: user.email,
+                    display_name: profile.map(|profile| profile.display_name),
+                    email_verified: user.email_verified_at.is_some(),
+                    disabled: user.disabled_at.is_some(),
+                })
+                .collect(),
+            total: list.total,
+            page,
+            pages: list.pages,
+            previous_url: (page > 1).then(|| users_url(&search, page - 1)),
+            next_url: (page < list.pages).then(|| users_url(&search, page + 1)),
+            search,
+        })
+        .into_response()
+}
+
+#[derive(Serialize, Deserialize, Default, Validate)]
+pub struct ProfileForm {
+    #[validate(length(min = 1, message = "Display name is required"))]
+    display_name: String,
+}
+
+#[derive(Serialize)]
+pub struct UserSession {
+    signed_in_at: String,
+    last_seen_at: String,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct UserPage {
+    id: i32,
+    email: String,
+    email_verified: bool,
+    two_factor_enabled: bool,
+    disabled_since: Option<String>,
+    password_reset_required: bool,
+    deletion_scheduled_at: Option<String>,
+    is_current_user: bool,
+    roles: Vec<String>,
+    sessions: Vec<UserSession>,
+    form: ProfileForm,
+    errors: Option<ValidationErrors>,
+}
+
+impl UserPage {
+    async fn load(app: &AppState, user_id: i32, current_user_id: i32) -> Option<Self> {
+        let db = &app.database_connection;
+        let (user, profile): (UserModel, Option<UserProfileModel>) =
+            match db_admin_user::get_user(db, user_id).await {
+                Ok(user) => user?,
+                Err(e) => {
+                    tracing::error!("Failed to load user: {:?}", e);
+                    return None;
+                }
+            };
+        let roles = match db_authz::get_user_roles(db, user_id).await {
+            Ok(roles) => roles.into_iter().map(|role| role.name).collect(),
+            Err(e) => {
+                tracing::error!("Failed to load user roles: {:?}", e);
+                Vec::new()
+            }
+        };
+        let sessions = match db_user_session::list_sessions(db, user_id).await {
+            Ok(sessions) => sessions,
+            Err(e) => {
+                tracing::error!("Failed to list user sessions: {:?}", e);
+                Vec::new()
+            }
+        };
+
+        Some(Self {
+            id: user.id,
+            email: user.email,
+            email_verified: user.email_verified_at.is_some(),
+            two_factor_enabled: user.totp_secret.is_some(),
+            disabled_since: user.disabled_at.map(format_date_time),
+            password_reset_required: user.password_reset_required,
+            deletion_scheduled_at: user.deletion_scheduled_at.map(format_date_time),
+            is_current_user: user.id == current_user_id,
+            roles,
+            sessions: sessions
+                .into_iter()
+                .map(|session| UserSession {
+                    signed_in_at: format_date_time(session.created_at),
+                    last_seen_at: format_date_time(session.last_seen_at),
+                    ip_address: session.ip_address,
+                    user_agent: session.user_agent,
+                })
+                .collect(),
+            form: ProfileForm {
+                display_name: profile
+                    .map(|profile| profile.display_name)
+                    .unwrap_or_default(),
+            },
+            errors: None,
+        })
+    }
+}
+
+async fn user_page(
+    app: &AppState,
+    response: TemplateResponse,
+    user_id: i32,
+    current_user_id: i32,
+) -> Response {
+    match UserPage::load(app, user_id, current_user_id).await {
+        Some(page) => response.content(page).into_response(),
+        None => AppError::NotFound.into_response(),
+    }
+}
+
+pub async fn get_user_page(
+    State(app): State<AppState>,
+    auth_session: AuthSession,
+    Path(user_id): Path<i32>,
+) -> Result<Response, AppError> {
+    let current_user = auth_session.user.ok_or(AppError::Unauthenticated)?;
+
+    Ok(user_page(
+        &app,
+        TemplateResponse::new("admin/user"),
+        user_id,
+        current_user.id(),
+    )
+    .await)
+}
+
+pub async fn post_user_profile(
+    State(app): State<AppState>,
+    auth_session: AuthSession,
+    audit: AuditContext,
+    Path(user_id): Path<i32>,
+    Form(form): Form<ProfileForm>,
+) -> Result<Response, AppError> {
+    let current_user = auth_session.user.ok_or(AppError::Unauthenticated)?;
+    let response = TemplateResponse::new("admin/user");
+
+    if let Err(errors) = form.validate() {
+        let Some(mut page) = UserPage::load(&app, user_id, current_user.id()).await else {
+            return Err(AppError::NotFound);
+        };
+        page.form = form;
+        page.errors = Some(errors);
+        return Ok(response.content(page).into_response());
+    }
+
+    let data = db_user_profile::SaveUserProfileData {
+        display_name: form.display_name,
+    };
+    let response =
+        match db_user_profile::save_user_profile(&app.database_connection, user_id, data).await {
+            Ok(()) => {
+                let event = AuditEvent::new(Action::ProfileUpdated)
+                    .actor(current_user.id())
+                    .target(user_id);
+                audit.record(&app.database_connection, event).await;
+                response.add_success_message("Profile updated")
+            }
+            Err(e) => {
+                tracing::error!("Failed to save user profile: {:?}", e);
+                response.add_error_message("Failed to save the profile, try again later")
+            }
+        };
+
+    Ok(user_page(&app, response, user_id, current_user.id()).await)
+}
+
+async fn set_disabled(
+    app: &AppState,
+    auth_session: AuthSession,
+    audit: AuditContext,
+    user_id: i32,
+    disabled: bool,
+) -> Result<Response, AppError> {
+    let current_user = auth_session.user.ok_or(AppError::Unauthenticated)?;
+    let response = TemplateResponse::new("admin/user");
+
+    let response = if user_id == current_user.id() {
+        response.add_error_message("You can't disable your own account")
+    } else {
+        match db_admin_user::set_disabled(&app.database_connection, user_id, disabled).await {
+            Ok(true) => {
+                let action = if disabled {
+                    Action::UserDisabled
+                } else {
+                    Action::UserEnabled
+                };
+                let event = AuditEvent::new(action)
+                    .actor(current_user.id())
+                    .target(user_id);
+                audit.record(&app.database_connection, event).await;
+                response.add_success_message(action.label())
+            }
+            Ok(false) => return Err(AppError::NotFound),
+            Err(e) => {
+                tracing::error!("Failed to update account status: {:?}", e);
+                response.add_error_message("Failed to update the account, try again later")
+            }
+        }
+    };
+
+    Ok(user_page(app, response, user_id, current_user.id()).await)
+}
+
+pub async fn post_disable_user(
+    State(app): State<AppState>,
+    auth_session: AuthSession,
+    audit: AuditContext,
+    Path(user_id): Path<i32>,
+) -> Result<Response, AppError> {
+    set_disabled(&app, auth_session, audit, user_id, true).await
+}
+
+pub async fn post_enable_user(
+    State(app): State<AppState>,
+    auth_session: AuthSession,
+    audit: AuditContext,
+    Path(user_id): Path<i32>,
+) -> Result<Response, AppError> {
+    set_disabled(&app, auth_session, audit, user_id, false).await
+}
+
+/// Makes the user choose a new password before they can log in with one
+/// again, and emails them a reset link.
+pub async fn post_require_password_reset(
+    State(app): State<AppState>,
+    auth_session: AuthSession,
+    audit: AuditContext,
+    Path(user_id): Path<i32>,
+) -> Result<Response, AppError> {
+    let current_user = auth_session.user.ok_or(AppError::Unauthenticated)?;
+    let response = TemplateResponse::new("admin/user");
+    let db = &app.database_connection;
+
+    if user_id == current_user.id() {
+        let response = response
+            .add_error_message("Change your own password from your security settings instead");
+        return Ok(user_page(&app, response, user_id, current_user.id()).await);
+    }
+
+    let user = match db_admin_user::get_user(db, user_id).await {
+        Ok(Some((user, _))) => user,
+        Ok(None) => return Err(AppError::NotFound),
+        Err(e) => {
+            tracing::error!("Failed to load user: {:?}", e);
+            let response = response.add_error_message("Failed to load the user, try again later");
+            return Ok(user_page(&app, response, user_id, current_user.id()).await);
+        }
+    };
+
+    if let Err(e) = db_admin_user::require_password_reset(db, user_id).await {
+        tracing::error!("Failed to require password reset: {:?}", e);
+        let response =
+            response.add_error_message("Failed to require a password reset, try again later");
+        return Ok(user_page(&app, response, user_id, current_user.id()).await);
+    }
+
+    let event = AuditEvent::new(Action::PasswordResetRequired)
+        .actor(current_user.id())
+        .target(user_id);
+    audit.record(db, event).await;
+
+    let response = match db_password_reset::create_token(db, user_id).await {
+        Ok(token) => {
+            let email = Email {
+                to: user.email,
+                subject: "Choose a new password".to_string(),
+                body: format!(
+                    "An administrator has asked you to choose a new password for your account.\n\n\
+                    Open the link below within the next hour to set it, or ask for a new link \
+                    from the login page:\n\n{}",
+                    app.config.url(&format!("/reset-password/{}", token))
+                ),
+            };
+            match app.mailer.send(email).await {
+                Ok(()) => response.add_success_message("The user has been sent a reset link"),
+                Err(e) => {
+                    tracing::error!("Failed to send password reset email: {:?}", e);
+                    response.add_error_message(
+                        "A password reset is required, but the reset link could not be sent",
+                    )
+                }
+            }
+        }
+        Err(e) => {
+            tracing::error!("Failed to create password reset token: {:?}", e);
+            response.add_error_message(
+                "A password reset is required, but the reset link could not be sent",
+            )
+        }
+    };
+
+    Ok(user_page(&app, response, user_id, current_user.id()).await)
+}
+
+pub async fn post_revoke_sessions(
+    State(app): State<AppState>,
+    auth_session: AuthSession,
+    audit: AuditContext,
+    Path(user_id): Path<i32>,
+) -> Result<Response, AppError> {
+    let current_user = auth_session.user.ok_or(AppError::Unauthenticated)?;
+    let response = TemplateResponse::new("admin/user");
+
+    let response =
+        match db_user_session::delete_all_sessions(&app.database_connection, user_id).await {
+            Ok(0) => response.add_success_message("The user has no sessions"),
+            Ok(count) => {
+                let event = AuditEvent::new(Action::SessionsRevoked)
+                    .actor(current_user.id())
+                    .target(user_id)
+                    .details(format!("{} session(s)", count));
+                audit.record(&app.database_connection, event).await;
+                response.add_success_message(format!("Signed out {} session(s)", count))
+            }
+            Err(e) => {
+                tracing::error!("Failed to revoke user sessions: {:?}", e);
+                response.add_error_message("Failed to sign the user out, try again later")
+            }
+        };
+
+    Ok(user_page(&app, response, user_id, current_user.id()).await)
+}
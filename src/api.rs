@@ -0,0 +1,5 @@
+mod auth;
+pub mod openapi;
+pub mod problem;
+pub mod router;
+mod user;
@@ -0,0 +1,165 @@
+use axum::{
+    extract::{rejection::JsonRejection, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_login::AuthUser;
+use serde::Serialize;
+use tower_sessions::cookie::time::{Duration, OffsetDateTime};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use super::{problem::Problem, user::CurrentUser};
+use crate::{
+    app::AppState,
+    audit::{action::Action, context::AuditContext, event::AuditEvent},
+    auth::{
+        client_ip::ClientIp,
+        db_api_token::{self, NewApiToken},
+        layer::AuthSession,
+        login_page::{authenticate_password, LoginForm, PasswordLoginError},
+        register_page::{register, RegisterError, RegisterForm},
+        scope::{self, Scope},
+    },
+};
+
+/// How long the tokens handed out by the login endpoint last.
+const LOGIN_TOKEN_LIFETIME: Duration = Duration::days(30);
+
+/// A bearer token in the shape of an OAuth 2.0 token response.
+#[derive(Serialize, ToSchema)]
+pub struct LoginToken {
+    access_token: String,
+    token_type: &'static str,
+    expires_in: i64,
+    scope: String,
+}
+
+/// Signs up a new user, who has to verify their email address before logging
+/// in when the server requires it.
+#[utoipa::path(
+    post,
+    path = "/api/v1/register",
+    tag = "auth",
+    request_body = RegisterForm,
+    responses(
+        (status = 201, body = CurrentUser),
+        (status = 403, description = "Registration is closed or needs an invitation", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "The email address is taken", body = Problem, content_type = "application/problem+json"),
+        (status = 422, body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn post_register(
+    State(app): State<AppState>,
+    audit: AuditContext,
+    payload: Result<Json<RegisterForm>, JsonRejection>,
+) -> Result<Response, Problem> {
+    let Json(form) = payload?;
+
+    match register(&app, &audit, &form).await {
+        Ok(user) => Ok((
+            StatusCode::CREATED,
+            Json(CurrentUser {
+                id: user.id,
+                email: user.email,
+                email_verified: user.email_verified_at.is_some(),
+                display_name: None,
+            }),
+        )
+            .into_response()),
+        Err(RegisterError::Validation(errors)) => Err(Problem::validation(&errors)),
+        Err(e @ (RegisterError::Closed | RegisterError::InvitationRequired)) => {
+            Err(Problem::new(StatusCode::FORBIDDEN).detail(e.to_string()))
+        }
+        Err(e @ RegisterError::UserExists) => {
+            Err(Problem::new(StatusCode::CONFLICT).detail(e.to_string()))
+        }
+        Err(e @ (RegisterError::InvalidInvitation | RegisterError::EmailMismatch)) => {
+            Err(Problem::new(StatusCode::UNPROCESSABLE_ENTITY).detail(e.to_string()))
+        }
+        Err(e @ RegisterError::Internal) => Err(Problem::internal(e.to_string())),
+    }
+}
+
+/// Exchanges an email and password for a bearer token with every scope.
+/// Accounts with two-factor authentication have to create a token on the
+/// tokens page instead.
+#[utoipa::path(
+    post,
+    path = "/api/v1/login",
+    tag = "auth",
+    request_body = LoginForm,
+    responses(
+        (status = 200, body = LoginToken),
+        (status = 401, description = "Invalid email or password", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "The account can't log in through the API", body = Problem, content_type = "application/problem+json"),
+        (status = 422, body = Problem, content_type = "application/problem+json"),
+        (status = 429, description = "Too many failed attempts", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn post_login(
+    State(app): State<AppState>,
+    auth_session: AuthSession,
+    ClientIp(ip): ClientIp,
+    audit: AuditContext,
+    payload: Result<Json<LoginForm>, JsonRejection>,
+) -> Result<Json<LoginToken>, Problem> {
+    let Json(form) = payload?;
+    form.validate()
+        .map_err(|errors| Problem::validation(&errors))?;
+
+    let user = match authenticate_password(&app, &auth_session, ip, &audit, &form).await {
+        Ok(user) => user,
+        Err(PasswordLoginError::LockedOut(locked_until)) => {
+            let seconds = locked_until - OffsetDateTime::now_utc().unix_timestamp();
+            return Err(Problem::new(StatusCode::TOO_MANY_REQUESTS)
+                .detail("Too many failed login attempts")
+                .header(axum::http::header::RETRY_AFTER, &seconds.max(1).to_string()));
+        }
+        Err(e @ PasswordLoginError::InvalidCredentials) => {
+            return Err(Problem::new(StatusCode::UNAUTHORIZED).detail(e.to_string()))
+        }
+        Err(e @ PasswordLoginError::Refused(_)) => {
+            return Err(Problem::new(StatusCode::FORBIDDEN).detail(e.to_string()))
+        }
+        Err(e @ PasswordLoginError::Internal) => return Err(Problem::internal(e.to_string())),
+    };
+
+    if user.has_two_factor() {
+        return Err(Problem::new(StatusCode::FORBIDDEN).detail(
+            "Two-factor authentication is enabled, create a token on the API tokens page instead",
+        ));
+    }
+
+    let scopes = Scope::ALL.to_vec();
+    let new_token = NewApiToken {
+        user_id: user.id(),
+        name: "API login".to_string(),
+        scopes: scopes.clone(),
+        expires_at: Some((OffsetDateTime::now_utc() + LOGIN_TOKEN_LIFETIME).unix_timestamp()),
+    };
+    let access_token = match db_api_token::create_token(&app.database_connection, new_token).await {
+        Ok(access_token) => access_token,
+        Err(e) => {
+            tracing::error!("Failed to create API token: {:?}", e);
+            return Err(Problem::internal("Failed to login user"));
+        }
+    };
+
+    audit
+        .record(
+            &app.database_connection,
+            AuditEvent::new(Action::Login)
+                .by_user(user.id())
+                .details("api"),
+        )
+        .await;
+
+    Ok(Json(LoginToken {
+        access_token,
+        token_type: "Bearer",
+        expires_in: LOGIN_TOKEN_LIFETIME.whole_seconds(),
+        scope: scope::join(&scopes),
+    }))
+}
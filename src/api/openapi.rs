@@ -0,0 +1,82 @@
+use utoipa::{
+    openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+    Modify, OpenApi,
+};
+
+use super::{auth, problem, user};
+use crate::{
+    auth::{login_page::LoginForm, register_page::RegisterForm},
+    user::profile_page::ProfileForm,
+};
+
+/// The contract of the `/api/v1` routes, served at `/api/openapi.json`.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "rust-web API",
+        description = "JSON API authenticated with personal API tokens."
+    ),
+    paths(
+        auth::post_register,
+        auth::post_login,
+        user::get_current_user,
+        user::get_profile,
+        user::put_profile,
+    ),
+    components(schemas(
+        RegisterForm,
+        LoginForm,
+        ProfileForm,
+        auth::LoginToken,
+        user::CurrentUser,
+        user::Profile,
+        problem::Problem,
+        problem::FieldError,
+    )),
+    modifiers(&BearerSecurity),
+    tags(
+        (name = "auth", description = "Sign up and exchange credentials for a token"),
+        (name = "user", description = "The user a token belongs to"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Personal API tokens, sent in the `Authorization: Bearer` header.
+struct BearerSecurity;
+
+impl Modify for BearerSecurity {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use utoipa::OpenApi;
+
+    use super::ApiDoc;
+
+    const SNAPSHOT_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/openapi.json");
+
+    /// Fails when the API changes without updating the committed document.
+    /// Run with `UPDATE_OPENAPI_SNAPSHOT=1` to write the new one.
+    #[test]
+    fn openapi_matches_snapshot() {
+        let document = ApiDoc::openapi().to_pretty_json().unwrap() + "\n";
+
+        if std::env::var_os("UPDATE_OPENAPI_SNAPSHOT").is_some() {
+            std::fs::write(SNAPSHOT_PATH, &document).unwrap();
+            return;
+        }
+
+        let snapshot = std::fs::read_to_string(SNAPSHOT_PATH).unwrap_or_default();
+        assert!(
+            snapshot == document,
+            "openapi.json is out of date, run the tests with UPDATE_OPENAPI_SNAPSHOT=1 and commit the result"
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use axum::{
+    extract::rejection::JsonRejection,
+    http::{header::CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+use validator::ValidationErrors;
+
+/// A field that failed validation, as listed in a problem's `errors`.
+#[derive(Serialize, Debug, ToSchema)]
+pub struct FieldError {
+    field: String,
+    code: String,
+    message: Option<String>,
+}
+
+/// An API error in the RFC 9457 `application/problem+json` format.
+#[derive(Serialize, Debug, ToSchema)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    title: String,
+    status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<FieldError>,
+    #[serde(skip)]
+    headers: HeaderMap,
+}
+
+impl Problem {
+    pub fn new(status: StatusCode) -> Self {
+        Self {
+            kind: "about:blank",
+            title: status.canonical_reason().unwrap_or_default().to_string(),
+            status: status.as_u16(),
+            detail: None,
+            errors: Vec::new(),
+            headers: HeaderMap::new(),
+        }
+    }
+
+    pub fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    pub fn header(mut self, name: HeaderName, value: &str) -> Self {
+        if let Ok(value) = HeaderValue::from_str(value) {
+            self.headers.insert(name, value);
+        }
+        self
+    }
+
+    pub fn internal(detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR).detail(detail)
+    }
+
+    /// The errors of a form shared with the HTML pages, one entry per failed
+    /// validation sorted by field.
+    pub fn validation(errors: &ValidationErrors) -> Self {
+        let mut field_errors: Vec<FieldError> = errors
+            .field_errors()
+            .into_iter()
+            .flat_map(|(field, errors)| {
+                errors.iter().map(move |error| FieldError {
+                    field: field.to_string(),
+                    code: error.code.to_string(),
+                    message: error.message.as_ref().map(|message| message.to_string()),
+                })
+            })
+            .collect();
+        field_errors.sort_by(|a, b| a.field.cmp(&b.field));
+
+        Self {
+            kind: "/problems/validation",
+            title: "Validation failed".to_string(),
+            errors: field_errors,
+            ..Self::new(StatusCode::UNPROCESSABLE_ENTITY)
+        }
+    }
+}
+
+impl From<JsonRejection> for Problem {
+    fn from(rejection: JsonRejection) -> Self {
+        Problem::new(rejection.status()).detail(rejection.body_text())
+    }
+}
+
+impl IntoResponse for Problem {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let mut headers = self.headers.clone();
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/problem+json"),
+        );
+
+        (status, headers, Json(self)).into_response()
+    }
+}
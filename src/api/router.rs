@@ -0,0 +1,23 @@
+use axum::{
+    routing::{get, post},
+    Router,
+};
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
+
+use super::{auth, openapi::ApiDoc, user};
+use crate::app::AppState;
+
+/// Routes authenticated with personal API tokens rather than the session,
+/// along with their OpenAPI document and an explorer at `/api/docs`.
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/api/v1/register", post(auth::post_register))
+        .route("/api/v1/login", post(auth::post_login))
+        .route("/api/v1/user", get(user::get_current_user))
+        .route(
+            "/api/v1/user/profile",
+            get(user::get_profile).put(user::put_profile),
+        )
+        .merge(SwaggerUi::new("/api/docs").url("/api/openapi.json", ApiDoc::openapi()))
+}
@@ -0,0 +1,125 @@
+use axum::{extract::rejection::JsonRejection, extract::State, Json};
+use axum_login::AuthUser;
+use serde::Serialize;
+use utoipa::ToSchema;
+use validator::Validate;
+
+use super::problem::Problem;
+use crate::{
+    app::AppState,
+    audit::{action::Action, context::AuditContext, event::AuditEvent},
+    auth::{layer::ApiUser, scope::Scope},
+    user::{
+        db_user_profile::{self, get_user_profile},
+        profile_page::ProfileForm,
+    },
+};
+
+#[derive(Serialize, ToSchema)]
+pub struct CurrentUser {
+    pub id: i32,
+    pub email: String,
+    pub email_verified: bool,
+    pub display_name: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Profile {
+    display_name: String,
+}
+
+/// The user the token belongs to.
+#[utoipa::path(
+    get,
+    path = "/api/v1/user",
+    tag = "user",
+    security(("bearer" = ["profile:read"])),
+    responses(
+        (status = 200, body = CurrentUser),
+        (status = 401, body = Problem, content_type = "application/problem+json"),
+        (status = 403, body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn get_current_user(
+    State(app): State<AppState>,
+    api_user: ApiUser,
+) -> Result<Json<CurrentUser>, Problem> {
+    api_user.require(Scope::ProfileRead)?;
+    let user = api_user.user;
+    let profile = get_user_profile(&app.database_connection, user.id()).await;
+
+    Ok(Json(CurrentUser {
+        id: user.id(),
+        email: user.email().to_string(),
+        email_verified: user.is_email_verified(),
+        display_name: profile.map(|profile| profile.display_name),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/user/profile",
+    tag = "user",
+    security(("bearer" = ["profile:read"])),
+    responses(
+        (status = 200, body = Profile),
+        (status = 401, body = Problem, content_type = "application/problem+json"),
+        (status = 403, body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn get_profile(
+    State(app): State<AppState>,
+    api_user: ApiUser,
+) -> Result<Json<Profile>, Problem> {
+    api_user.require(Scope::ProfileRead)?;
+    let profile = get_user_profile(&app.database_connection, api_user.user.id()).await;
+
+    Ok(Json(Profile {
+        display_name: profile
+            .map(|profile| profile.display_name)
+            .unwrap_or_default(),
+    }))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/user/profile",
+    tag = "user",
+    security(("bearer" = ["profile:write"])),
+    request_body = ProfileForm,
+    responses(
+        (status = 200, body = Profile),
+        (status = 401, body = Problem, content_type = "application/problem+json"),
+        (status = 403, body = Problem, content_type = "application/problem+json"),
+        (status = 422, body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn put_profile(
+    State(app): State<AppState>,
+    api_user: ApiUser,
+    audit: AuditContext,
+    payload: Result<Json<ProfileForm>, JsonRejection>,
+) -> Result<Json<Profile>, Problem> {
+    api_user.require(Scope::ProfileWrite)?;
+    let Json(form) = payload?;
+    form.validate()
+        .map_err(|errors| Problem::validation(&errors))?;
+
+    let user_id = api_user.user.id();
+    let data: db_user_profile::SaveUserProfileData = form.into();
+    let display_name = data.display_name.clone();
+    if let Err(e) =
+        db_user_profile::save_user_profile(&app.database_connection, user_id, data).await
+    {
+        tracing::error!("Failed to save user profile: {:?}", e);
+        return Err(Problem::internal("Failed to save user profile"));
+    }
+    audit
+        .record(
+            &app.database_connection,
+            AuditEvent::new(Action::ProfileUpdated).by_user(user_id),
+        )
+        .await;
+
+    Ok(Json(Profile { display_name }))
+}
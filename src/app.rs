@@ -10,8 +10,12 @@ use sea_orm::DatabaseConnection;
 use tower_http::trace::TraceLayer;
 
 use crate::{
-    auth,
+    admin, api,
+    auth::{self, oidc::OidcProviders, webauthn::Webauthn},
+    config::Config,
+    error,
     layout::template_response::{with_template_response, TemplateResponse},
+    mailer::SharedMailer,
     templates::TemplateEngine,
     user,
 };
@@ -20,33 +24,59 @@ use crate::{
 pub struct AppState {
     pub template_engine: TemplateEngine,
     pub database_connection: DatabaseConnection,
+    pub config: Config,
+    pub mailer: SharedMailer,
+    pub oidc_providers: OidcProviders,
+    pub webauthn: Webauthn,
 }
 
 pub fn create_app(
     template_engine: TemplateEngine,
     database_connection: DatabaseConnection,
+    config: Config,
+    mailer: SharedMailer,
 ) -> Router {
-    let auth_layer = auth::layer::create_auth_layer(database_connection.clone());
+    let webauthn = Webauthn::new(&config);
+    let auth_layer =
+        auth::layer::create_auth_layer(database_connection.clone(), &config, webauthn.clone());
     let auth_router = auth::router::router();
     let user_router = user::router::router();
+    let admin_router = admin::router::router();
+    let api_router = api::router::router();
 
+    let oidc_providers = OidcProviders::from_config(&config);
     let app_state = AppState {
         template_engine,
         database_connection,
+        config,
+        mailer,
+        oidc_providers,
+        webauthn,
     };
 
     Router::new()
         .route("/protected", get(get_protected))
         .merge(user_router)
+        .merge(admin_router)
         .route_layer(login_required!(auth::layer::Backend, login_url = "/login"))
         .merge(auth_router)
         .route("/public", get(get_public))
         .route("/", get(get_root))
+        .layer(middleware::from_fn(auth::csrf::verify_csrf))
+        // API routes only accept bearer tokens, never the session cookie, so
+        // they are out of reach of cross-site requests.
+        .merge(api_router)
+        .fallback(error::fallback)
+        .layer(middleware::map_response(error::with_error_pages))
         .layer(middleware::map_response_with_state(
             app_state.clone(),
             with_template_response,
         ))
         .layer(MessagesManagerLayer)
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            auth::session_activity::track_session_activity,
+        ))
         .layer(auth_layer)
         .layer(TraceLayer::new_for_http())
         .with_state(app_state)
@@ -0,0 +1,4 @@
+pub mod action;
+pub mod context;
+pub mod db_audit_event;
+pub mod event;
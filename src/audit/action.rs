@@ -0,0 +1,78 @@
+/// What happened in an audit event. Stored by name, so variants can be added
+/// but existing names must not change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Login,
+    LoginFailed,
+    Logout,
+    Register,
+    ProfileUpdated,
+    UserDisabled,
+    UserEnabled,
+    PasswordResetRequired,
+    SessionsRevoked,
+    InvitationCreated,
+    InvitationRevoked,
+    ApiTokenCreated,
+    ApiTokenRevoked,
+}
+
+impl Action {
+    pub const ALL: [Action; 13] = [
+        Action::Login,
+        Action::LoginFailed,
+        Action::Logout,
+        Action::Register,
+        Action::ProfileUpdated,
+        Action::UserDisabled,
+        Action::UserEnabled,
+        Action::PasswordResetRequired,
+        Action::SessionsRevoked,
+        Action::InvitationCreated,
+        Action::InvitationRevoked,
+        Action::ApiTokenCreated,
+        Action::ApiTokenRevoked,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Action::Login => "login",
+            Action::LoginFailed => "login_failed",
+            Action::Logout => "logout",
+            Action::Register => "register",
+            Action::ProfileUpdated => "profile_updated",
+            Action::UserDisabled => "user_disabled",
+            Action::UserEnabled => "user_enabled",
+            Action::PasswordResetRequired => "password_reset_required",
+            Action::SessionsRevoked => "sessions_revoked",
+            Action::InvitationCreated => "invitation_created",
+            Action::InvitationRevoked => "invitation_revoked",
+            Action::ApiTokenCreated => "api_token_created",
+            Action::ApiTokenRevoked => "api_token_revoked",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::Login => "Logged in",
+            Action::LoginFailed => "Failed login",
+            Action::Logout => "Logged out",
+            Action::Register => "Signed up",
+            Action::ProfileUpdated => "Profile updated",
+            Action::UserDisabled => "Account disabled",
+            Action::UserEnabled => "Account enabled",
+            Action::PasswordResetRequired => "Password reset required",
+            Action::SessionsRevoked => "Signed out everywhere",
+            Action::InvitationCreated => "Invitation created",
+            Action::InvitationRevoked => "Invitation revoked",
+            Action::ApiTokenCreated => "API token created",
+            Action::ApiTokenRevoked => "API token revoked",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL
+            .into_iter()
+            .find(|action| action.as_str() == name)
+    }
+}
@@ -0,0 +1,61 @@
+use async_trait::async_trait;
+use axum::{
+    extract::FromRequestParts,
+    http::{header::USER_AGENT, request::Parts, StatusCode},
+};
+use sea_orm::DatabaseConnection;
+
+use super::{db_audit_event, event::AuditEvent};
+use crate::{app::AppState, auth::client_ip::ClientIp};
+
+/// Where the current request comes from, recorded with every audit event.
+#[derive(Debug, Clone)]
+pub struct AuditContext {
+    ip_address: String,
+    user_agent: Option<String>,
+}
+
+impl AuditContext {
+    /// Records the event. Failing to do so is logged rather than returned,
+    /// so it never stops the action being audited.
+    pub async fn record(&self, db: &DatabaseConnection, event: AuditEvent) {
+        let result = db_audit_event::insert_event(
+            db,
+            db_audit_event::NewAuditEvent {
+                action: event.action.as_str().to_string(),
+                actor_id: event.actor_id,
+                target_id: event.target_id,
+                ip_address: Some(self.ip_address.clone()),
+                user_agent: self.user_agent.clone(),
+                details: event.details,
+            },
+        )
+        .await;
+
+        if let Err(e) = result {
+            tracing::error!("Failed to record audit event {:?}: {:?}", event.action, e);
+        }
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for AuditContext {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        app: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let ClientIp(ip) = ClientIp::from_request_parts(parts, app).await?;
+        let user_agent = parts
+            .headers
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+
+        Ok(Self {
+            ip_address: ip.to_string(),
+            user_agent,
+        })
+    }
+}
@@ -0,0 +1,190 @@
+use entity::audit_event;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, DbErr, EntityTrait,
+    PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, Select, Set,
+};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+use tower_sessions::cookie::time::OffsetDateTime;
+
+pub type AuditEventModel = audit_event::Model;
+
+pub const PAGE_SIZE: u64 = 50;
+/// The `previous_hash` of the first event in the log.
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Events are chained by hash, so only one can be appended at a time.
+static APPEND_LOCK: Mutex<()> = Mutex::const_new(());
+
+pub struct NewAuditEvent {
+    pub action: String,
+    pub actor_id: Option<i32>,
+    pub target_id: Option<i32>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub details: Option<String>,
+}
+
+impl From<AuditEventModel> for NewAuditEvent {
+    fn from(event: AuditEventModel) -> Self {
+        Self {
+            action: event.action,
+            actor_id: event.actor_id,
+            target_id: event.target_id,
+            ip_address: event.ip_address,
+            user_agent: event.user_agent,
+            details: event.details,
+        }
+    }
+}
+
+/// Each event's hash covers its contents and the hash of the event before
+/// it, so editing or deleting a recorded event breaks every hash after it.
+fn event_hash(previous_hash: &str, created_at: i64, event: &NewAuditEvent) -> String {
+    let contents = json!([
+        previous_hash,
+        created_at,
+        event.action,
+        event.actor_id,
+        event.target_id,
+        event.ip_address,
+        event.user_agent,
+        event.details,
+    ]);
+
+    hex::encode(Sha256::digest(contents.to_string().as_bytes()))
+}
+
+pub async fn insert_event(
+    db: &DatabaseConnection,
+    event: NewAuditEvent,
+) -> Result<AuditEventModel, DbErr> {
+    let _guard = APPEND_LOCK.lock().await;
+
+    let previous_hash = audit_event::Entity::find()
+        .order_by_desc(audit_event::Column::Id)
+        .one(db)
+        .await?
+        .map(|event| event.hash)
+        .unwrap_or_else(|| GENESIS_HASH.to_string());
+    let created_at = OffsetDateTime::now_utc().unix_timestamp();
+    let hash = event_hash(&previous_hash, created_at, &event);
+
+    audit_event::ActiveModel {
+        action: Set(event.action),
+        actor_id: Set(event.actor_id),
+        target_id: Set(event.target_id),
+        ip_address: Set(event.ip_address),
+        user_agent: Set(event.user_agent),
+        details: Set(event.details),
+        created_at: Set(created_at),
+        previous_hash: Set(previous_hash),
+        hash: Set(hash),
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+}
+
+/// Narrows the audit log down. The user matches events they did or that were
+/// done to them; the time range is in unix seconds, `to` exclusive.
+#[derive(Debug, Default, Clone)]
+pub struct AuditFilter {
+    pub action: Option<String>,
+    pub user_id: Option<i32>,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+}
+
+fn filtered(filter: &AuditFilter) -> Select<audit_event::Entity> {
+    let mut query = audit_event::Entity::find().order_by_desc(audit_event::Column::Id);
+    if let Some(action) = &filter.action {
+        query = query.filter(audit_event::Column::Action.eq(action.as_str()));
+    }
+    if let Some(user_id) = filter.user_id {
+        query = query.filter(
+            Condition::any()
+                .add(audit_event::Column::ActorId.eq(user_id))
+                .add(audit_event::Column::TargetId.eq(user_id)),
+        );
+    }
+    if let Some(from) = filter.from {
+        query = query.filter(audit_event::Column::CreatedAt.gte(from));
+    }
+    if let Some(to) = filter.to {
+        query = query.filter(audit_event::Column::CreatedAt.lt(to));
+    }
+
+    query
+}
+
+pub struct AuditEventPage {
+    pub events: Vec<AuditEventModel>,
+    pub total: u64,
+    pub pages: u64,
+}
+
+/// Lists matching events, newest first. Pages are numbered from 1.
+pub async fn list_events(
+    db: &DatabaseConnection,
+    filter: &AuditFilter,
+    page: u64,
+) -> Result<AuditEventPage, DbErr> {
+    let paginator = filtered(filter).paginate(db, PAGE_SIZE);
+    let counts = paginator.num_items_and_pages().await?;
+    let events = paginator.fetch_page(page.saturating_sub(1)).await?;
+
+    Ok(AuditEventPage {
+        events,
+        total: counts.number_of_items,
+        pages: counts.number_of_pages,
+    })
+}
+
+/// Every matching event, newest first.
+pub async fn all_events(
+    db: &DatabaseConnection,
+    filter: &AuditFilter,
+) -> Result<Vec<AuditEventModel>, DbErr> {
+    filtered(filter).all(db).await
+}
+
+/// The most recent events of a user's account, newest first.
+pub async fn recent_user_events(
+    db: &DatabaseConnection,
+    user_id: i32,
+    limit: u64,
+) -> Result<Vec<AuditEventModel>, DbErr> {
+    filtered(&AuditFilter {
+        user_id: Some(user_id),
+        ..Default::default()
+    })
+    .limit(limit)
+    .all(db)
+    .await
+}
+
+/// Recomputes the hash chain, returning the id of the first event that
+/// doesn't match it, if any.
+pub async fn verify_chain(db: &DatabaseConnection) -> Result<Option<i32>, DbErr> {
+    let mut events = audit_event::Entity::find()
+        .order_by_asc(audit_event::Column::Id)
+        .paginate(db, 500);
+    let mut previous_hash = GENESIS_HASH.to_string();
+
+    while let Some(page) = events.fetch_and_next().await? {
+        for event in page {
+            let id = event.id;
+            let hash = event.hash.clone();
+            if event.previous_hash != previous_hash
+                || event_hash(&previous_hash, event.created_at, &event.clone().into()) != hash
+            {
+                return Ok(Some(id));
+            }
+            previous_hash = hash;
+        }
+    }
+
+    Ok(None)
+}
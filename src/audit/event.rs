@@ -0,0 +1,47 @@
+use super::action::Action;
+
+/// An audit event about to be recorded. The actor is who did it, the target
+/// the account it was done to; both are the same user for their own actions.
+#[derive(Debug, Clone)]
+pub struct AuditEvent {
+    pub action: Action,
+    pub actor_id: Option<i32>,
+    pub target_id: Option<i32>,
+    pub details: Option<String>,
+}
+
+impl AuditEvent {
+    pub fn new(action: Action) -> Self {
+        Self {
+            action,
+            actor_id: None,
+            target_id: None,
+            details: None,
+        }
+    }
+
+    pub fn actor(mut self, user_id: i32) -> Self {
+        self.actor_id = Some(user_id);
+        self
+    }
+
+    pub fn target(mut self, user_id: i32) -> Self {
+        self.target_id = Some(user_id);
+        self
+    }
+
+    /// Shorthand for an action users take on their own account.
+    pub fn by_user(self, user_id: i32) -> Self {
+        self.actor(user_id).target(user_id)
+    }
+
+    pub fn maybe_target(mut self, user_id: Option<i32>) -> Self {
+        self.target_id = user_id;
+        self
+    }
+
+    pub fn details(mut self, details: impl Into<String>) -> Self {
+        self.details = Some(details.into());
+        self
+    }
+}
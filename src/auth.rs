@@ -1,7 +1,33 @@
+pub mod authz;
+pub mod client_ip;
+pub mod csrf;
+pub mod db_api_token;
+pub mod db_authz;
+pub mod db_email_verification;
+pub mod db_invitation;
+pub mod db_login_throttle;
+mod db_magic_link;
+pub mod db_password_reset;
 mod db_session_store;
-mod db_user;
+pub mod db_two_factor;
+pub mod db_user;
+pub mod db_user_identity;
+pub mod db_webauthn;
+pub mod email_verification_page;
 pub mod layer;
-mod login_page;
+pub mod login_page;
+mod magic_link_page;
+pub mod oidc;
+mod oidc_page;
+mod passkey_login;
 mod password;
-mod register_page;
+mod password_reset_page;
+pub mod redirect;
+pub mod register_page;
 pub mod router;
+pub mod scope;
+pub mod session_activity;
+pub mod token;
+pub mod totp;
+mod two_factor_page;
+pub mod webauthn;
@@ -0,0 +1,70 @@
+use std::collections::HashSet;
+
+use async_trait::async_trait;
+use axum::{
+    extract::FromRequestParts,
+    http::{request::Parts, StatusCode},
+};
+use axum_login::AuthzBackend;
+use serde::Serialize;
+
+use super::layer::AuthSession;
+
+pub const VIEW_USERS: &str = "users.view";
+pub const MANAGE_USERS: &str = "users.manage";
+pub const VIEW_AUDIT_LOG: &str = "audit.view";
+pub const MANAGE_INVITATIONS: &str = "invitations.manage";
+
+/// A permission granted through the user's roles, named like `users.manage`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct Permission(pub String);
+
+impl From<&str> for Permission {
+    fn from(name: &str) -> Self {
+        Permission(name.to_string())
+    }
+}
+
+/// The permissions of the current user, empty for anonymous visitors. Routes
+/// are guarded with `permission_required!`, this extractor is for handlers
+/// that show or allow more depending on the user.
+#[derive(Debug, Clone, Default)]
+pub struct Permissions(HashSet<Permission>);
+
+impl Permissions {
+    pub async fn load(auth_session: &AuthSession) -> Self {
+        let Some(user) = &auth_session.user else {
+            return Self::default();
+        };
+
+        match auth_session.backend.get_all_permissions(user).await {
+            Ok(permissions) => Self(permissions),
+            Err(e) => {
+                tracing::error!("Failed to load permissions: {:?}", e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Permission names, sorted, for templates.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .0
+            .iter()
+            .map(|permission| permission.0.clone())
+            .collect();
+        names.sort();
+        names
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Permissions {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let auth_session = AuthSession::from_request_parts(parts, state).await?;
+
+        Ok(Self::load(&auth_session).await)
+    }
+}
@@ -0,0 +1,45 @@
+use std::net::{IpAddr, SocketAddr};
+
+use async_trait::async_trait;
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::{request::Parts, StatusCode},
+};
+
+use crate::app::AppState;
+
+/// Address of the client making the request. When the application runs
+/// behind a reverse proxy that sets `X-Forwarded-For`, the proxy has to be
+/// trusted through `TRUST_FORWARDED_FOR`, otherwise every request would
+/// appear to come from the proxy.
+#[derive(Clone, Copy, Debug)]
+pub struct ClientIp(pub IpAddr);
+
+#[async_trait]
+impl FromRequestParts<AppState> for ClientIp {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        app: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        if app.config.trust_forwarded_for {
+            // The proxy appends the address it saw to the end of the list.
+            let forwarded = parts
+                .headers
+                .get("x-forwarded-for")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.rsplit(',').next())
+                .and_then(|value| value.trim().parse().ok());
+            if let Some(ip) = forwarded {
+                return Ok(ClientIp(ip));
+            }
+        }
+
+        parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(address)| ClientIp(address.ip()))
+            .ok_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
@@ -0,0 +1,239 @@
+use axum::{
+    body::{self, Body},
+    extract::Request,
+    http::{header::CONTENT_TYPE, HeaderMap},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tower_sessions::{session, Session};
+
+use super::token;
+use crate::error::AppError;
+
+const CSRF_TOKEN_KEY: &str = "auth.csrf_token";
+/// Header checked first, set by htmx and `fetch` calls.
+const CSRF_HEADER: &str = "x-csrf-token";
+/// Form field checked otherwise, rendered by the `form/csrf` partial.
+const CSRF_FIELD: &str = "csrf_token";
+/// Largest form body read while looking for the token.
+const MAX_FORM_SIZE: usize = 1024 * 1024;
+
+/// Returns the anti-forgery token of the session, if it was issued one.
+pub async fn existing_token(session: &Session) -> Result<Option<String>, session::Error> {
+    session.get::<String>(CSRF_TOKEN_KEY).await
+}
+
+/// Returns the anti-forgery token of the session, creating it on first use.
+/// Issuing one stores the session, so it is only done for pages with forms.
+pub async fn session_token(session: &Session) -> Result<String, session::Error> {
+    if let Some(token) = existing_token(session).await? {
+        return Ok(token);
+    }
+
+    let token = token::generate();
+    session.insert(CSRF_TOKEN_KEY, &token).await?;
+
+    Ok(token)
+}
+
+fn is_form(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/x-www-form-urlencoded"))
+}
+
+fn form_token(body: &[u8]) -> Option<String> {
+    url::form_urlencoded::parse(body)
+        .find(|(name, _)| name == CSRF_FIELD)
+        .map(|(_, value)| value.into_owned())
+}
+
+fn forbidden() -> Response {
+    AppError::Forbidden(
+        "This form has expired or was not sent from this site. \
+            Go back, reload the page and try again.",
+    )
+    .into_response()
+}
+
+/// Rejects requests with unsafe methods unless they carry the session's
+/// token, either in the `X-CSRF-Token` header or in the `csrf_token` field
+/// of a form. Sessions that never rendered a form have no token and can't
+/// submit one.
+pub async fn verify_csrf(session: Session, request: Request, next: Next) -> Response {
+    if request.method().is_safe() {
+        return next.run(request).await;
+    }
+
+    let expected = match existing_token(&session).await {
+        Ok(token) => token,
+        Err(e) => return AppError::Session(e).into_response(),
+    };
+
+    let (parts, body) = request.into_parts();
+    let header_token = parts
+        .headers
+        .get(CSRF_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let (submitted, body) = match header_token {
+        Some(token) => (Some(token), body),
+        None if is_form(&parts.headers) => match body::to_bytes(body, MAX_FORM_SIZE).await {
+            Ok(bytes) => (form_token(&bytes), Body::from(bytes)),
+            Err(_) => return forbidden(),
+        },
+        None => (None, body),
+    };
+
+    // Comparing hashes keeps the comparison time independent of the token.
+    match (submitted, expected) {
+        (Some(submitted), Some(expected)) if token::hash(&submitted) == token::hash(&expected) => {
+            next.run(Request::from_parts(parts, body)).await
+        }
+        _ => {
+            tracing::warn!(
+                "Rejected {} {} without a valid CSRF token",
+                parts.method,
+                parts.uri
+            );
+            forbidden()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::template_response::TemplateResponse;
+    use axum::{
+        http::{header, Method, StatusCode},
+        middleware,
+        routing::get,
+        Router,
+    };
+    use tower::ServiceExt;
+    use tower_sessions::{MemoryStore, SessionManagerLayer};
+
+    fn app() -> Router {
+        let issue_token = |session: Session| async move { session_token(&session).await.unwrap() };
+
+        Router::new()
+            .route("/", get(issue_token).post(|| async { "saved" }))
+            .layer(middleware::from_fn(verify_csrf))
+            .layer(SessionManagerLayer::new(MemoryStore::default()))
+    }
+
+    /// Error pages get their status from the template layer, which isn't
+    /// part of these routes.
+    fn status(response: &Response) -> StatusCode {
+        response
+            .extensions()
+            .get::<TemplateResponse>()
+            .map_or(response.status(), TemplateResponse::status_code)
+    }
+
+    /// Renders a page with a form, returning the session cookie and its token.
+    async fn start_session(app: &Router) -> (String, String) {
+        let response = app
+            .clone()
+            .oneshot(Request::new(Body::empty()))
+            .await
+            .unwrap();
+        let cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+        let cookie = cookie.split(';').next().unwrap().to_string();
+        let token = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        (cookie, String::from_utf8(token.to_vec()).unwrap())
+    }
+
+    fn post(cookie: &str) -> axum::http::request::Builder {
+        Request::builder()
+            .method(Method::POST)
+            .uri("/")
+            .header(header::COOKIE, cookie)
+    }
+
+    #[tokio::test]
+    async fn safe_methods_are_not_checked() {
+        let response = app().oneshot(Request::new(Body::empty())).await.unwrap();
+
+        assert_eq!(status(&response), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn post_without_a_token_is_forbidden() {
+        let app = app();
+        let (cookie, _) = start_session(&app).await;
+
+        let response = app
+            .oneshot(post(&cookie).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(status(&response), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn post_with_a_wrong_token_is_forbidden() {
+        let app = app();
+        let (cookie, _) = start_session(&app).await;
+
+        let request = post(&cookie)
+            .header(CSRF_HEADER, "not-the-token")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(status(&response), StatusCode::FORBIDDEN);
+
+        let request = post(&cookie)
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from("csrf_token=not-the-token"))
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(status(&response), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn post_without_a_session_is_forbidden() {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/")
+            .header(CSRF_HEADER, "any-token")
+            .body(Body::empty())
+            .unwrap();
+        let response = app().oneshot(request).await.unwrap();
+
+        assert_eq!(status(&response), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn header_token_is_accepted() {
+        let app = app();
+        let (cookie, token) = start_session(&app).await;
+
+        let request = post(&cookie)
+            .header(CSRF_HEADER, token)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+
+        assert_eq!(status(&response), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn form_token_is_accepted() {
+        let app = app();
+        let (cookie, token) = start_session(&app).await;
+
+        let request = post(&cookie)
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(format!("name=ci&csrf_token={}", token)))
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+
+        assert_eq!(status(&response), StatusCode::OK);
+    }
+}
@@ -0,0 +1,105 @@
+use super::{
+    scope::{self, Scope},
+    token,
+};
+use entity::api_token;
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, DbErr,
+    EntityTrait, QueryFilter, QueryOrder, Set,
+};
+use tower_sessions::cookie::time::OffsetDateTime;
+
+pub type ApiTokenModel = api_token::Model;
+
+/// How often the last use is written back, so that a busy script doesn't
+/// update the token on every request.
+const LAST_USED_RESOLUTION_SECONDS: i64 = 60;
+
+pub struct NewApiToken {
+    pub user_id: i32,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub expires_at: Option<i64>,
+}
+
+/// Stores a new token and returns the plain secret, which is shown to the
+/// user once and never persisted.
+pub async fn create_token(
+    db: &DatabaseConnection,
+    new_token: NewApiToken,
+) -> Result<String, DbErr> {
+    let secret = token::generate();
+
+    api_token::ActiveModel {
+        user_id: Set(new_token.user_id),
+        name: Set(new_token.name),
+        token_hash: Set(token::hash(&secret)),
+        scopes: Set(scope::join(&new_token.scopes)),
+        created_at: Set(OffsetDateTime::now_utc().unix_timestamp()),
+        expires_at: Set(new_token.expires_at),
+        last_used_at: Set(None),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    Ok(secret)
+}
+
+pub async fn list_tokens(
+    db: &DatabaseConnection,
+    user_id: i32,
+) -> Result<Vec<ApiTokenModel>, DbErr> {
+    api_token::Entity::find()
+        .filter(api_token::Column::UserId.eq(user_id))
+        .order_by_desc(api_token::Column::CreatedAt)
+        .order_by_desc(api_token::Column::Id)
+        .all(db)
+        .await
+}
+
+/// Deletes one of the user's tokens, returning false if they have no such
+/// token.
+pub async fn delete_token(db: &DatabaseConnection, user_id: i32, id: i32) -> Result<bool, DbErr> {
+    let result = api_token::Entity::delete_many()
+        .filter(api_token::Column::Id.eq(id))
+        .filter(api_token::Column::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+
+    Ok(result.rows_affected > 0)
+}
+
+/// Finds the unexpired token with the given secret and records that it was
+/// used.
+pub async fn use_token(
+    db: &DatabaseConnection,
+    secret: &str,
+) -> Result<Option<ApiTokenModel>, DbErr> {
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let Some(api_token) = api_token::Entity::find()
+        .filter(api_token::Column::TokenHash.eq(token::hash(secret)))
+        .filter(
+            Condition::any()
+                .add(api_token::Column::ExpiresAt.is_null())
+                .add(api_token::Column::ExpiresAt.gt(now)),
+        )
+        .one(db)
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    let stale = api_token
+        .last_used_at
+        .is_none_or(|last_used_at| last_used_at + LAST_USED_RESOLUTION_SECONDS <= now);
+    if stale {
+        api_token::Entity::update_many()
+            .col_expr(api_token::Column::LastUsedAt, Expr::value(now))
+            .filter(api_token::Column::Id.eq(api_token.id))
+            .exec(db)
+            .await?;
+    }
+
+    Ok(Some(api_token))
+}
@@ -0,0 +1,111 @@
+use entity::{permission, role, role_permission, user_role};
+use sea_orm::{
+    sea_query::{OnConflict, Query},
+    ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    Set,
+};
+
+pub type RoleModel = role::Model;
+
+/// Names of the permissions granted to the user through their roles.
+pub async fn get_user_permissions(
+    db: &DatabaseConnection,
+    user_id: i32,
+) -> Result<Vec<String>, DbErr> {
+    let permissions = permission::Entity::find()
+        .filter(
+            permission::Column::Id.in_subquery(
+                Query::select()
+                    .column(role_permission::Column::PermissionId)
+                    .from(role_permission::Entity)
+                    .and_where(
+                        role_permission::Column::RoleId.in_subquery(
+                            Query::select()
+                                .column(user_role::Column::RoleId)
+                                .from(user_role::Entity)
+                                .and_where(user_role::Column::UserId.eq(user_id))
+                                .to_owned(),
+                        ),
+                    )
+                    .to_owned(),
+            ),
+        )
+        .all(db)
+        .await?;
+
+    Ok(permissions
+        .into_iter()
+        .map(|permission| permission.name)
+        .collect())
+}
+
+pub async fn get_user_roles(
+    db: &DatabaseConnection,
+    user_id: i32,
+) -> Result<Vec<RoleModel>, DbErr> {
+    role::Entity::find()
+        .filter(
+            role::Column::Id.in_subquery(
+                Query::select()
+                    .column(user_role::Column::RoleId)
+                    .from(user_role::Entity)
+                    .and_where(user_role::Column::UserId.eq(user_id))
+                    .to_owned(),
+            ),
+        )
+        .order_by_asc(role::Column::Name)
+        .all(db)
+        .await
+}
+
+async fn find_role<C: ConnectionTrait>(db: &C, name: &str) -> Result<Option<RoleModel>, DbErr> {
+    role::Entity::find()
+        .filter(role::Column::Name.eq(name))
+        .one(db)
+        .await
+}
+
+/// Grants a role to the user, returning false if there is no such role.
+pub async fn assign_role(
+    db: &DatabaseConnection,
+    user_id: i32,
+    role_name: &str,
+) -> Result<bool, DbErr> {
+    let Some(role) = find_role(db, role_name).await? else {
+        return Ok(false);
+    };
+
+    user_role::Entity::insert(user_role::ActiveModel {
+        user_id: Set(user_id),
+        role_id: Set(role.id),
+    })
+    .on_conflict(
+        OnConflict::columns([user_role::Column::UserId, user_role::Column::RoleId])
+            .do_nothing()
+            .to_owned(),
+    )
+    .do_nothing()
+    .exec(db)
+    .await?;
+
+    Ok(true)
+}
+
+/// Takes a role away from the user, returning false if they didn't have it.
+pub async fn remove_role(
+    db: &DatabaseConnection,
+    user_id: i32,
+    role_name: &str,
+) -> Result<bool, DbErr> {
+    let Some(role) = find_role(db, role_name).await? else {
+        return Ok(false);
+    };
+
+    let result = user_role::Entity::delete_many()
+        .filter(user_role::Column::UserId.eq(user_id))
+        .filter(user_role::Column::RoleId.eq(role.id))
+        .exec(db)
+        .await?;
+
+    Ok(result.rows_affected == 1)
+}
@@ -0,0 +1,136 @@
+use super::token;
+use entity::{email_verification_token, user};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait,
+    QueryFilter, QueryOrder, Set, TransactionTrait,
+};
+use thiserror::Error;
+use tower_sessions::cookie::time::{Duration, OffsetDateTime};
+
+const TOKEN_LIFETIME: Duration = Duration::hours(24);
+
+/// Stores a new verification token for the given address of the user and
+/// returns the plain token to be sent to that address.
+pub async fn create_token(
+    db: &DatabaseConnection,
+    user_id: i32,
+    email: &str,
+) -> Result<String, DbErr> {
+    let token = token::generate();
+    let expires_at = OffsetDateTime::now_utc() + TOKEN_LIFETIME;
+
+    email_verification_token::ActiveModel {
+        user_id: Set(user_id),
+        email: Set(email.to_string()),
+        token_hash: Set(token::hash(&token)),
+        expires_at: Set(expires_at.unix_timestamp()),
+        used_at: Set(None),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    Ok(token)
+}
+
+/// Returns the address the user most recently asked to change to, while its
+/// verification link is still valid.
+pub async fn pending_email_change(
+    db: &DatabaseConnection,
+    user_id: i32,
+    current_email: &str,
+) -> Result<Option<String>, DbErr> {
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let pending = email_verification_token::Entity::find()
+        .filter(email_verification_token::Column::UserId.eq(user_id))
+        .filter(email_verification_token::Column::Email.ne(current_email))
+        .filter(email_verification_token::Column::UsedAt.is_null())
+        .filter(email_verification_token::Column::ExpiresAt.gt(now))
+        .order_by_desc(email_verification_token::Column::ExpiresAt)
+        .one(db)
+        .await?;
+
+    Ok(pending.map(|token| token.email))
+}
+
+#[derive(Error, Debug)]
+pub enum VerifyEmailError {
+    #[error("Invalid or expired token")]
+    InvalidToken,
+    #[error("The email address belongs to another account")]
+    EmailTaken,
+    #[error("Failed to verify email")]
+    Database(#[from] DbErr),
+}
+
+/// The outcome of a successful verification.
+pub struct VerifiedEmail {
+    pub user_id: i32,
+    /// Whether the address replaced the user's previous one.
+    pub email_changed: bool,
+}
+
+/// Consumes the token and marks the address it was sent to as verified. If
+/// the user asked to change to that address, it becomes their email and
+/// every other pending link is discarded, so an old one can't change it back.
+pub async fn verify_email(
+    db: &DatabaseConnection,
+    token: &str,
+) -> Result<VerifiedEmail, VerifyEmailError> {
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let verification_token = email_verification_token::Entity::find()
+        .filter(email_verification_token::Column::TokenHash.eq(token::hash(token)))
+        .filter(email_verification_token::Column::UsedAt.is_null())
+        .filter(email_verification_token::Column::ExpiresAt.gt(now))
+        .one(db)
+        .await?
+        .ok_or(VerifyEmailError::InvalidToken)?;
+
+    let txn = db.begin().await?;
+
+    let consumed = email_verification_token::Entity::update_many()
+        .col_expr(email_verification_token::Column::UsedAt, Expr::value(now))
+        .filter(email_verification_token::Column::Id.eq(verification_token.id))
+        .filter(email_verification_token::Column::UsedAt.is_null())
+        .exec(&txn)
+        .await?;
+    if consumed.rows_affected != 1 {
+        return Err(VerifyEmailError::InvalidToken);
+    }
+
+    let user = user::Entity::find_by_id(verification_token.user_id)
+        .one(&txn)
+        .await?
+        .ok_or(VerifyEmailError::InvalidToken)?;
+    let email_changed = user.email != verification_token.email;
+
+    if email_changed {
+        let taken = user::Entity::find()
+            .filter(user::Column::Email.eq(&verification_token.email))
+            .one(&txn)
+            .await?
+            .is_some();
+        if taken {
+            return Err(VerifyEmailError::EmailTaken);
+        }
+
+        email_verification_token::Entity::update_many()
+            .col_expr(email_verification_token::Column::UsedAt, Expr::value(now))
+            .filter(email_verification_token::Column::UserId.eq(user.id))
+            .filter(email_verification_token::Column::UsedAt.is_null())
+            .exec(&txn)
+            .await?;
+    }
+
+    let mut user: user::ActiveModel = user.into();
+    user.email = Set(verification_token.email);
+    user.email_verified_at = Set(Some(now));
+    let user = user.update(&txn).await?;
+
+    txn.commit().await?;
+
+    Ok(VerifiedEmail {
+        user_id: user.id,
+        email_changed,
+    })
+}
@@ -0,0 +1,147 @@
+use super::{db_user, token};
+use entity::invitation;
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, DbErr,
+    EntityTrait, QueryFilter, QueryOrder, Set, TransactionTrait,
+};
+use thiserror::Error;
+use tower_sessions::cookie::time::OffsetDateTime;
+
+pub type InvitationModel = invitation::Model;
+
+pub struct NewInvitation {
+    pub inviter_id: i32,
+    /// Only this address can sign up with the invitation when set.
+    pub email: Option<String>,
+    pub expires_at: Option<i64>,
+    pub max_uses: Option<i32>,
+}
+
+/// Stores a new invitation and returns its plain code, which is only shown
+/// to the inviter and never persisted.
+pub async fn create_invitation(
+    db: &DatabaseConnection,
+    invitation: NewInvitation,
+) -> Result<String, DbErr> {
+    let code = token::generate();
+
+    invitation::ActiveModel {
+        code_hash: Set(token::hash(&code)),
+        inviter_id: Set(invitation.inviter_id),
+        email: Set(invitation.email),
+        expires_at: Set(invitation.expires_at),
+        max_uses: Set(invitation.max_uses),
+        uses: Set(0),
+        created_at: Set(OffsetDateTime::now_utc().unix_timestamp()),
+        revoked_at: Set(None),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    Ok(code)
+}
+
+/// Lists every invitation, newest first.
+pub async fn list_invitations(db: &DatabaseConnection) -> Result<Vec<InvitationModel>, DbErr> {
+    invitation::Entity::find()
+        .order_by_desc(invitation::Column::CreatedAt)
+        .order_by_desc(invitation::Column::Id)
+        .all(db)
+        .await
+}
+
+/// Stops the invitation from being used, returning false if it doesn't
+/// exist or was already revoked.
+pub async fn revoke_invitation(db: &DatabaseConnection, id: i32) -> Result<bool, DbErr> {
+    let result = invitation::Entity::update_many()
+        .col_expr(
+            invitation::Column::RevokedAt,
+            Expr::value(OffsetDateTime::now_utc().unix_timestamp()),
+        )
+        .filter(invitation::Column::Id.eq(id))
+        .filter(invitation::Column::RevokedAt.is_null())
+        .exec(db)
+        .await?;
+
+    Ok(result.rows_affected > 0)
+}
+
+fn usable(now: i64) -> Condition {
+    Condition::all()
+        .add(invitation::Column::RevokedAt.is_null())
+        .add(
+            Condition::any()
+                .add(invitation::Column::ExpiresAt.is_null())
+                .add(invitation::Column::ExpiresAt.gt(now)),
+        )
+        .add(
+            Condition::any()
+                .add(invitation::Column::MaxUses.is_null())
+                .add(
+                    Expr::col(invitation::Column::Uses).lt(Expr::col(invitation::Column::MaxUses)),
+                ),
+        )
+}
+
+/// Finds the invitation with the given code if it can still be used.
+pub async fn find_usable_invitation(
+    db: &DatabaseConnection,
+    code: &str,
+) -> Result<Option<InvitationModel>, DbErr> {
+    invitation::Entity::find()
+        .filter(invitation::Column::CodeHash.eq(token::hash(code)))
+        .filter(usable(OffsetDateTime::now_utc().unix_timestamp()))
+        .one(db)
+        .await
+}
+
+#[derive(Error, Debug)]
+pub enum RegisterWithInvitationError {
+    #[error("Invalid, expired or used up invitation")]
+    InvalidInvitation,
+    #[error("The invitation is for another email address")]
+    EmailMismatch,
+    #[error("Failed to create user")]
+    CreateUser(#[from] db_user::CreateUserError),
+    #[error("Failed to use the invitation")]
+    Database(#[from] DbErr),
+}
+
+/// Creates the user and counts one use of the invitation, in a single
+/// transaction so that an invitation is never used more than allowed.
+pub async fn register_with_invitation(
+    db: &DatabaseConnection,
+    code: &str,
+    data: db_user::CreateUserData,
+) -> Result<db_user::UserModel, RegisterWithInvitationError> {
+    let invitation = find_usable_invitation(db, code)
+        .await?
+        .ok_or(RegisterWithInvitationError::InvalidInvitation)?;
+    if let Some(email) = &invitation.email {
+        if !email.eq_ignore_ascii_case(data.email.trim()) {
+            return Err(RegisterWithInvitationError::EmailMismatch);
+        }
+    }
+
+    let txn = db.begin().await?;
+
+    let used = invitation::Entity::update_many()
+        .col_expr(
+            invitation::Column::Uses,
+            Expr::col(invitation::Column::Uses).add(1),
+        )
+        .filter(invitation::Column::Id.eq(invitation.id))
+        .filter(usable(OffsetDateTime::now_utc().unix_timestamp()))
+        .exec(&txn)
+        .await?;
+    if used.rows_affected != 1 {
+        return Err(RegisterWithInvitationError::InvalidInvitation);
+    }
+
+    let user = db_user::create_user(&txn, data).await?;
+
+    txn.commit().await?;
+
+    Ok(user)
+}
@@ -0,0 +1,255 @@
+use entity::login_throttle;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait,
+    QueryFilter, Set, TransactionTrait,
+};
+use tower_sessions::cookie::time::OffsetDateTime;
+
+/// Failures older than this are forgotten before counting a new one.
+const FAILURE_WINDOW_SECONDS: i64 = 24 * 60 * 60;
+/// Lockouts start at this length and double with every further failure.
+const BASE_LOCKOUT_SECONDS: i64 = 60;
+const MAX_LOCKOUT_SECONDS: i64 = 60 * 60;
+
+/// What failed attempts are counted against. Accounts are keyed by the email
+/// typed in the form, so unknown addresses are throttled the same way.
+#[derive(Clone, Copy, Debug)]
+enum Scope {
+    Account,
+    Ip,
+}
+
+impl Scope {
+    fn as_str(self) -> &'static str {
+        match self {
+            Scope::Account => "account",
+            Scope::Ip => "ip",
+        }
+    }
+
+    /// Failures allowed before the first lockout. Several people can share an
+    /// address, so it gets more room than a single account.
+    fn max_failures(self) -> i32 {
+        match self {
+            Scope::Account => 5,
+            Scope::Ip => 20,
+        }
+    }
+}
+
+fn account_subject(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+fn lockout_seconds(scope: Scope, failures: i32) -> Option<i64> {
+    let excess = failures - scope.max_failures();
+    if excess < 0 {
+        return None;
+    }
+
+    Some(
+        BASE_LOCKOUT_SECONDS
+            .saturating_mul(1 << excess.min(16))
+            .min(MAX_LOCKOUT_SECONDS),
+    )
+}
+
+async fn find<C: ConnectionTrait>(
+    db: &C,
+    scope: Scope,
+    subject: &str,
+) -> Result<Option<login_throttle::Model>, DbErr> {
+    login_throttle::Entity::find()
+        .filter(login_throttle::Column::Scope.eq(scope.as_str()))
+        .filter(login_throttle::Column::Subject.eq(subject))
+        .one(db)
+        .await
+}
+
+async fn locked_until_for(
+    db: &DatabaseConnection,
+    scope: Scope,
+    subject: &str,
+    now: i64,
+) -> Result<Option<i64>, DbErr> {
+    Ok(find(db, scope, subject)
+        .await?
+        .and_then(|throttle| throttle.locked_until)
+        .filter(|locked_until| *locked_until > now))
+}
+
+/// Returns when the login will be allowed again if either the account or the
+/// client address is currently locked out.
+pub async fn locked_until(
+    db: &DatabaseConnection,
+    email: &str,
+    ip: &str,
+) -> Result<Option<i64>, DbErr> {
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let account = locked_until_for(db, Scope::Account, &account_subject(email), now).await?;
+    let ip = locked_until_for(db, Scope::Ip, ip, now).await?;
+
+    Ok(account.max(ip))
+}
+
+async fn record_failure_for(
+    db: &DatabaseConnection,
+    scope: Scope,
+    subject: &str,
+    now: i64,
+) -> Result<Option<i64>, DbErr> {
+    let txn = db.begin().await?;
+
+    let locked_until = match find(&txn, scope, subject).await? {
+        Some(throttle) => {
+            let failures = if throttle.last_failure_at + FAILURE_WINDOW_SECONDS < now {
+                1
+            } else {
+                throttle.failures + 1
+            };
+            let locked_until = lockout_seconds(scope, failures).map(|seconds| now + seconds);
+
+            let mut throttle: login_throttle::ActiveModel = throttle.into();
+            throttle.failures = Set(failures);
+            throttle.last_failure_at = Set(now);
+            throttle.locked_until = Set(locked_until);
+            throttle.update(&txn).await?;
+
+            locked_until
+        }
+        None => {
+            let locked_until = lockout_seconds(scope, 1).map(|seconds| now + seconds);
+
+            login_throttle::ActiveModel {
+                scope: Set(scope.as_str().to_string()),
+                subject: Set(subject.to_string()),
+                failures: Set(1),
+                last_failure_at: Set(now),
+                locked_until: Set(locked_until),
+                ..Default::default()
+            }
+            .insert(&txn)
+            .await?;
+
+            locked_until
+        }
+    };
+
+    txn.commit().await?;
+
+    Ok(locked_until)
+}
+
+/// Counts a failed login against the account and the client address,
+/// returning when the login will be allowed again if this failure caused a
+/// lockout.
+pub async fn record_failure(
+    db: &DatabaseConnection,
+    email: &str,
+    ip: &str,
+) -> Result<Option<i64>, DbErr> {
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let account = record_failure_for(db, Scope::Account, &account_subject(email), now).await?;
+    let ip = record_failure_for(db, Scope::Ip, ip, now).await?;
+
+    Ok(account.max(ip))
+}
+
+/// Forgets the failures of an account after a successful login. The client
+/// address keeps its count, otherwise an attacker could reset it by logging
+/// into an account of their own.
+pub async fn record_success(db: &DatabaseConnection, email: &str) -> Result<(), DbErr> {
+    unlock_account(db, email).await?;
+
+    Ok(())
+}
+
+/// Clears the failures and any lockout of an account, returning false if
+/// there was nothing to clear.
+pub async fn unlock_account<C: ConnectionTrait>(db: &C, email: &str) -> Result<bool, DbErr> {
+    let result = login_throttle::Entity::delete_many()
+        .filter(login_throttle::Column::Scope.eq(Scope::Account.as_str()))
+        .filter(login_throttle::Column::Subject.eq(account_subject(email)))
+        .exec(db)
+        .await?;
+
+    Ok(result.rows_affected > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::connect_in_memory;
+
+    const NOW: i64 = 1_700_000_000;
+
+    async fn fail_times(db: &DatabaseConnection, scope: Scope, times: i32) -> Option<i64> {
+        let mut locked_until = None;
+        for _ in 0..times {
+            locked_until = record_failure_for(db, scope, "subject", NOW).await.unwrap();
+        }
+        locked_until
+    }
+
+    #[test]
+    fn locks_out_after_the_allowed_failures() {
+        assert_eq!(lockout_seconds(Scope::Account, 4), None);
+        assert_eq!(lockout_seconds(Scope::Account, 5), Some(60));
+        assert_eq!(lockout_seconds(Scope::Ip, 19), None);
+        assert_eq!(lockout_seconds(Scope::Ip, 20), Some(60));
+    }
+
+    #[test]
+    fn lockout_doubles_up_to_an_hour() {
+        assert_eq!(lockout_seconds(Scope::Account, 6), Some(120));
+        assert_eq!(lockout_seconds(Scope::Account, 7), Some(240));
+        assert_eq!(lockout_seconds(Scope::Account, 10), Some(1920));
+        assert_eq!(
+            lockout_seconds(Scope::Account, 11),
+            Some(MAX_LOCKOUT_SECONDS)
+        );
+        assert_eq!(
+            lockout_seconds(Scope::Account, 1000),
+            Some(MAX_LOCKOUT_SECONDS)
+        );
+    }
+
+    #[tokio::test]
+    async fn record_failure_locks_out_at_the_threshold() {
+        let db = connect_in_memory().await;
+
+        assert_eq!(fail_times(&db, Scope::Account, 4).await, None);
+        assert_eq!(fail_times(&db, Scope::Account, 1).await, Some(NOW + 60));
+        assert_eq!(fail_times(&db, Scope::Account, 1).await, Some(NOW + 120));
+        assert_eq!(
+            locked_until_for(&db, Scope::Account, "subject", NOW)
+                .await
+                .unwrap(),
+            Some(NOW + 120)
+        );
+        assert_eq!(
+            locked_until_for(&db, Scope::Account, "subject", NOW + 120)
+                .await
+                .unwrap(),
+            None
+        );
+
+        assert_eq!(fail_times(&db, Scope::Ip, 19).await, None);
+        assert_eq!(fail_times(&db, Scope::Ip, 1).await, Some(NOW + 60));
+    }
+
+    #[tokio::test]
+    async fn failures_are_forgotten_after_the_window() {
+        let db = connect_in_memory().await;
+        fail_times(&db, Scope::Account, 5).await;
+
+        let later = NOW + FAILURE_WINDOW_SECONDS + 1;
+        let locked_until = record_failure_for(&db, Scope::Account, "subject", later)
+            .await
+            .unwrap();
+        assert_eq!(locked_until, None);
+
+        let throttle = find(&db, Scope::Account, "subject").await.unwrap().unwrap();
+        assert_eq!(throttle.failures, 1);
+    }
+}
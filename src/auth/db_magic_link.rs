@@ -0,0 +1,110 @@
+use super::token;
+use entity::magic_link_token;
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait,
+    PaginatorTrait, QueryFilter, Set,
+};
+use tower_sessions::cookie::time::{Duration, OffsetDateTime};
+
+/// Sign-in links are meant to be used right away.
+const TOKEN_LIFETIME: Duration = Duration::minutes(15);
+/// Links sent to the same account are limited to this many per window, so
+/// the form can't be used to flood someone's inbox.
+const MAX_LINKS_PER_WINDOW: u64 = 3;
+const RATE_LIMIT_WINDOW: Duration = Duration::hours(1);
+
+/// Stores a new sign-in token for the user and returns the plain token, or
+/// `None` if too many links were sent to the user recently.
+pub async fn create_token(db: &DatabaseConnection, user_id: i32) -> Result<Option<String>, DbErr> {
+    let now = OffsetDateTime::now_utc();
+    let recent = magic_link_token::Entity::find()
+        .filter(magic_link_token::Column::UserId.eq(user_id))
+        .filter(magic_link_token::Column::CreatedAt.gt((now - RATE_LIMIT_WINDOW).unix_timestamp()))
+        .count(db)
+        .await?;
+    if recent >= MAX_LINKS_PER_WINDOW {
+        return Ok(None);
+    }
+
+    let token = token::generate();
+    magic_link_token::ActiveModel {
+        user_id: Set(user_id),
+        token_hash: Set(token::hash(&token)),
+        created_at: Set(now.unix_timestamp()),
+        expires_at: Set((now + TOKEN_LIFETIME).unix_timestamp()),
+        used_at: Set(None),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    Ok(Some(token))
+}
+
+/// Marks the token as used and returns its user, or `None` if the token is
+/// unknown, expired or was already used. Other pending links of the user stop
+/// working too.
+pub async fn consume_token(db: &DatabaseConnection, token: &str) -> Result<Option<i32>, DbErr> {
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let Some(magic_link) = magic_link_token::Entity::find()
+        .filter(magic_link_token::Column::TokenHash.eq(token::hash(token)))
+        .filter(magic_link_token::Column::UsedAt.is_null())
+        .filter(magic_link_token::Column::ExpiresAt.gt(now))
+        .one(db)
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    let consumed = magic_link_token::Entity::update_many()
+        .col_expr(magic_link_token::Column::UsedAt, Expr::value(now))
+        .filter(magic_link_token::Column::Id.eq(magic_link.id))
+        .filter(magic_link_token::Column::UsedAt.is_null())
+        .exec(db)
+        .await?;
+    // Another request used the link in the meantime.
+    if consumed.rows_affected == 0 {
+        return Ok(None);
+    }
+
+    magic_link_token::Entity::update_many()
+        .col_expr(magic_link_token::Column::UsedAt, Expr::value(now))
+        .filter(magic_link_token::Column::UserId.eq(magic_link.user_id))
+        .filter(magic_link_token::Column::UsedAt.is_null())
+        .exec(db)
+        .await?;
+
+    Ok(Some(magic_link.user_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{auth::db_user, database::connect_in_memory};
+
+    async fn create_user(db: &DatabaseConnection) -> i32 {
+        db_user::create_user(
+            db,
+            db_user::CreateUserData {
+                email: "user@example.com".to_string(),
+                password: "correct horse battery staple".to_string(),
+                email_verified: true,
+            },
+        )
+        .await
+        .unwrap()
+        .id
+    }
+
+    #[tokio::test]
+    async fn token_works_once_and_ends_other_links() {
+        let db = connect_in_memory().await;
+        let user_id = create_user(&db).await;
+        let token = create_token(&db, user_id).await.unwrap().unwrap();
+        let other_token = create_token(&db, user_id).await.unwrap().unwrap();
+
+        assert_eq!(consume_token(&db, &token).await.unwrap(), Some(user_id));
+        assert_eq!(consume_token(&db, &token).await.unwrap(), None);
+        assert_eq!(consume_token(&db, &other_token).await.unwrap(), None);
+    }
+}
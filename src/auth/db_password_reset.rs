@@ -0,0 +1,93 @@
+use super::{db_user, token};
+use entity::password_reset_token;
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait,
+    QueryFilter, Set, TransactionTrait,
+};
+use thiserror::Error;
+use tower_sessions::cookie::time::{Duration, OffsetDateTime};
+
+pub type PasswordResetTokenModel = password_reset_token::Model;
+
+const TOKEN_LIFETIME: Duration = Duration::hours(1);
+
+/// Stores a new reset token for the user and returns the plain token, which
+/// is only ever sent to the user and never persisted.
+pub async fn create_token(db: &DatabaseConnection, user_id: i32) -> Result<String, DbErr> {
+    let token = token::generate();
+    let expires_at = OffsetDateTime::now_utc() + TOKEN_LIFETIME;
+
+    password_reset_token::ActiveModel {
+        user_id: Set(user_id),
+        token_hash: Set(token::hash(&token)),
+        expires_at: Set(expires_at.unix_timestamp()),
+        used_at: Set(None),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    Ok(token)
+}
+
+pub async fn find_valid_token(
+    db: &DatabaseConnection,
+    token: &str,
+) -> Result<Option<PasswordResetTokenModel>, DbErr> {
+    password_reset_token::Entity::find()
+        .filter(password_reset_token::Column::TokenHash.eq(token::hash(token)))
+        .filter(password_reset_token::Column::UsedAt.is_null())
+        .filter(
+            password_reset_token::Column::ExpiresAt.gt(OffsetDateTime::now_utc().unix_timestamp()),
+        )
+        .one(db)
+        .await
+}
+
+#[derive(Error, Debug)]
+pub enum ResetPasswordError {
+    #[error("Invalid or expired token")]
+    InvalidToken,
+    #[error("Failed to update password")]
+    UpdatePassword(#[from] db_user::UpdatePasswordError),
+    #[error("Failed to consume token")]
+    Database(#[from] DbErr),
+}
+
+/// Sets a new password for the owner of the token and marks every pending
+/// token of that user as used, all in a single transaction.
+pub async fn reset_password(
+    db: &DatabaseConnection,
+    token: &str,
+    new_password: &str,
+) -> Result<(), ResetPasswordError> {
+    let reset_token = find_valid_token(db, token)
+        .await?
+        .ok_or(ResetPasswordError::InvalidToken)?;
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+
+    let txn = db.begin().await?;
+
+    let consumed = password_reset_token::Entity::update_many()
+        .col_expr(password_reset_token::Column::UsedAt, Expr::value(now))
+        .filter(password_reset_token::Column::Id.eq(reset_token.id))
+        .filter(password_reset_token::Column::UsedAt.is_null())
+        .exec(&txn)
+        .await?;
+    if consumed.rows_affected != 1 {
+        return Err(ResetPasswordError::InvalidToken);
+    }
+
+    password_reset_token::Entity::update_many()
+        .col_expr(password_reset_token::Column::UsedAt, Expr::value(now))
+        .filter(password_reset_token::Column::UserId.eq(reset_token.user_id))
+        .filter(password_reset_token::Column::UsedAt.is_null())
+        .exec(&txn)
+        .await?;
+
+    db_user::update_password(&txn, reset_token.user_id, new_password).await?;
+
+    txn.commit().await?;
+
+    Ok(())
+}
@@ -1,15 +1,24 @@
-use std::time::SystemTime;
+use std::collections::HashMap;
 
 use async_trait::async_trait;
 use entity::session;
-use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
-use serde_json::json;
+use sea_orm::{ColumnTrait, DatabaseConnection, DbErr, EntityTrait, NotSet, QueryFilter, Set};
+use serde_json::{json, Value};
 use tower_sessions::{
     cookie::time::OffsetDateTime,
     session::{Id, Record},
     session_store, ExpiredDeletion, SessionStore,
 };
 
+use super::session_activity::{SessionActivity, SESSION_ACTIVITY_KEY};
+
+/// Key under which axum-login keeps the authenticated user in the session.
+const AUTH_DATA_KEY: &str = "axum-login.data";
+
+/// Key under which `load` marks the id a record was read with. It is never
+/// written to the database.
+const LOADED_ID_KEY: &str = "db_session_store.loaded_id";
+
 #[derive(Debug, Clone)]
 pub struct DatabaseSessionStore {
     db: DatabaseConnection,
@@ -21,19 +30,20 @@ impl DatabaseSessionStore {
     }
 }
 
+fn backend_error(e: DbErr) -> session_store::Error {
+    session_store::Error::Backend(e.to_string())
+}
+
 #[async_trait]
 impl ExpiredDeletion for DatabaseSessionStore {
     async fn delete_expired(&self) -> session_store::Result<()> {
-        let now = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap()
-            .as_secs() as i32;
+        let now = OffsetDateTime::now_utc().unix_timestamp();
 
         session::Entity::delete_many()
             .filter(session::Column::Expiry.lt(now))
             .exec(&self.db)
             .await
-            .unwrap();
+            .map_err(backend_error)?;
 
         Ok(())
     }
@@ -42,47 +52,90 @@ impl ExpiredDeletion for DatabaseSessionStore {
 #[async_trait]
 impl SessionStore for DatabaseSessionStore {
     async fn save(&self, record: &Record) -> session_store::Result<()> {
-        let new_session = session::ActiveModel {
+        let mut data = record.data.clone();
+        // Only a record loaded under its current id has a row to update; new
+        // sessions and cycled ids get a fresh one.
+        let is_loaded = data.remove(LOADED_ID_KEY).as_ref().and_then(Value::as_str)
+            == Some(record.id.to_string().as_str());
+
+        let user_id = data
+            .get(AUTH_DATA_KEY)
+            .and_then(|data| data.get("user_id"))
+            .and_then(Value::as_i64)
+            .and_then(|user_id| user_id.try_into().ok());
+        let activity: Option<SessionActivity> = data
+            .get(SESSION_ACTIVITY_KEY)
+            .and_then(|activity| serde_json::from_value(activity.clone()).ok());
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let created_at = match (&activity, is_loaded) {
+            (Some(activity), _) => Set(activity.created_at),
+            (None, true) => NotSet,
+            (None, false) => Set(now),
+        };
+
+        let expiry = record
+            .expiry_date
+            .unix_timestamp()
+            .try_into()
+            .map_err(|e| session_store::Error::Encode(format!("Invalid expiry date: {}", e)))?;
+
+        let session = session::ActiveModel {
             id: Set(record.id.to_string()),
-            data: Set(json!(record.data).to_string()),
-            expiry: Set(record.expiry_date.unix_timestamp().try_into().unwrap()),
+            data: Set(json!(data).to_string()),
+            expiry: Set(expiry),
+            user_id: Set(user_id),
+            created_at,
+            last_seen_at: Set(activity
+                .as_ref()
+                .map_or(now, |activity| activity.last_seen_at)),
+            ip_address: Set(activity
+                .as_ref()
+                .map(|activity| activity.ip_address.clone())),
+            user_agent: Set(activity.and_then(|activity| activity.user_agent)),
         };
 
-        match session::Entity::find_by_id(record.id.to_string())
-            .one(&self.db)
+        if !is_loaded {
+            session::Entity::insert(session)
+                .exec(&self.db)
+                .await
+                .map_err(backend_error)?;
+            return Ok(());
+        }
+
+        let result = session::Entity::update_many()
+            .set(session)
+            .filter(session::Column::Id.eq(record.id.to_string()))
+            .exec(&self.db)
             .await
-            .unwrap()
-        {
-            Some(_) => {
-                session::Entity::update(new_session)
-                    .filter(session::Column::Id.eq(record.id.to_string()))
-                    .exec(&self.db)
-                    .await
-                    .unwrap();
-            }
-            None => {
-                session::Entity::insert(new_session)
-                    .exec(&self.db)
-                    .await
-                    .unwrap();
-            }
+            .map_err(backend_error)?;
+        // Revoked while the request was running, which must not bring the
+        // session back.
+        if result.rows_affected == 0 {
+            tracing::warn!("Session {} was deleted before it could be saved", record.id);
         }
 
         Ok(())
     }
 
     async fn load(&self, session_id: &Id) -> session_store::Result<Option<Record>> {
-        let session = session::Entity::find()
+        // The session may have been revoked from another device.
+        let Some(session) = session::Entity::find()
             .filter(session::Column::Id.eq(session_id.to_string()))
             .one(&self.db)
             .await
-            .unwrap()
-            .unwrap();
-        let data = serde_json::from_str(&session.data).unwrap();
+            .map_err(backend_error)?
+        else {
+            return Ok(None);
+        };
+        let mut data: HashMap<String, Value> = serde_json::from_str(&session.data)
+            .map_err(|e| session_store::Error::Decode(e.to_string()))?;
+        data.insert(LOADED_ID_KEY.to_string(), json!(session.id));
+        let expiry_date = OffsetDateTime::from_unix_timestamp(session.expiry as i64)
+            .map_err(|e| session_store::Error::Decode(e.to_string()))?;
         let record = Record {
             id: *session_id,
             data,
-            expiry_date: OffsetDateTime::from_unix_timestamp(session.expiry as i64).unwrap(),
+            expiry_date,
         };
         Ok(Some(record))
     }
@@ -91,7 +144,68 @@ impl SessionStore for DatabaseSessionStore {
         session::Entity::delete_by_id(session_id.to_string())
             .exec(&self.db)
             .await
-            .unwrap();
+            .map_err(backend_error)?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tower_sessions::cookie::time::Duration;
+
+    use super::*;
+    use crate::database::connect_in_memory;
+
+    async fn store() -> DatabaseSessionStore {
+        DatabaseSessionStore::new(connect_in_memory().await)
+    }
+
+    fn new_record() -> Record {
+        Record {
+            id: Id::default(),
+            data: HashMap::from([("key".to_string(), json!("value"))]),
+            expiry_date: OffsetDateTime::now_utc() + Duration::hours(1),
+        }
+    }
+
+    #[tokio::test]
+    async fn saves_new_and_loaded_sessions() {
+        let store = store().await;
+        let record = new_record();
+        store.save(&record).await.unwrap();
+
+        let mut loaded = store.load(&record.id).await.unwrap().unwrap();
+        loaded.data.insert("key".to_string(), json!("changed"));
+        store.save(&loaded).await.unwrap();
+
+        let saved = store.load(&record.id).await.unwrap().unwrap();
+        assert_eq!(saved.data["key"], json!("changed"));
+    }
+
+    #[tokio::test]
+    async fn revoked_session_is_not_saved_again() {
+        let store = store().await;
+        let record = new_record();
+        store.save(&record).await.unwrap();
+
+        let loaded = store.load(&record.id).await.unwrap().unwrap();
+        store.delete(&record.id).await.unwrap();
+        store.save(&loaded).await.unwrap();
+
+        assert!(store.load(&record.id).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn cycled_id_is_saved_as_new_session() {
+        let store = store().await;
+        let record = new_record();
+        store.save(&record).await.unwrap();
+
+        let mut loaded = store.load(&record.id).await.unwrap().unwrap();
+        store.delete(&record.id).await.unwrap();
+        loaded.id = Id::default();
+        store.save(&loaded).await.unwrap();
+
+        assert!(store.load(&loaded.id).await.unwrap().is_some());
+    }
+}
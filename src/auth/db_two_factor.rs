@@ -0,0 +1,189 @@
+use super::{token, totp};
+use entity::{recovery_code, user};
+use rand::{rngs::OsRng, Rng};
+use sea_orm::{
+    sea_query::Expr, ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, DbErr,
+    EntityTrait, PaginatorTrait, QueryFilter, Set, TransactionTrait,
+};
+use tower_sessions::cookie::time::OffsetDateTime;
+
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+fn generate_recovery_code() -> String {
+    let chars: String = (0..10)
+        .map(|_| RECOVERY_CODE_ALPHABET[OsRng.gen_range(0..RECOVERY_CODE_ALPHABET.len())] as char)
+        .collect();
+
+    format!("{}-{}", &chars[..5], &chars[5..])
+}
+
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+async fn replace_recovery_codes<C: ConnectionTrait>(
+    db: &C,
+    user_id: i32,
+) -> Result<Vec<String>, DbErr> {
+    recovery_code::Entity::delete_many()
+        .filter(recovery_code::Column::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| generate_recovery_code())
+        .collect();
+    let models = codes.iter().map(|code| recovery_code::ActiveModel {
+        user_id: Set(user_id),
+        code_hash: Set(token::hash(&normalize_recovery_code(code))),
+        used_at: Set(None),
+        ..Default::default()
+    });
+    recovery_code::Entity::insert_many(models).exec(db).await?;
+
+    Ok(codes)
+}
+
+pub async fn get_totp_secret(db: &DatabaseConnection, user_id: i32) -> Option<String> {
+    user::Entity::find_by_id(user_id)
+        .one(db)
+        .await
+        .ok()
+        .flatten()
+        .and_then(|user| user.totp_secret)
+}
+
+/// Turns on two-factor authentication with a confirmed secret and returns a
+/// fresh set of recovery codes, which are only ever shown once.
+/// The code that confirmed the secret, of time step `step`, can't be used
+/// again to log in.
+pub async fn enable_totp(
+    db: &DatabaseConnection,
+    user_id: i32,
+    secret: &str,
+    step: i64,
+) -> Result<Vec<String>, DbErr> {
+    let txn = db.begin().await?;
+
+    user::Entity::update_many()
+        .col_expr(user::Column::TotpSecret, Expr::value(secret))
+        .col_expr(user::Column::TotpLastStep, Expr::value(step))
+        .filter(user::Column::Id.eq(user_id))
+        .exec(&txn)
+        .await?;
+    let codes = replace_recovery_codes(&txn, user_id).await?;
+
+    txn.commit().await?;
+
+    Ok(codes)
+}
+
+pub async fn disable_totp(db: &DatabaseConnection, user_id: i32) -> Result<(), DbErr> {
+    let txn = db.begin().await?;
+
+    user::Entity::update_many()
+        .col_expr(
+            user::Column::TotpSecret,
+            Expr::value(Option::<String>::None),
+        )
+        .col_expr(user::Column::TotpLastStep, Expr::value(Option::<i64>::None))
+        .filter(user::Column::Id.eq(user_id))
+        .exec(&txn)
+        .await?;
+    recovery_code::Entity::delete_many()
+        .filter(recovery_code::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await?;
+
+    txn.commit().await
+}
+
+pub async fn regenerate_recovery_codes(
+    db: &DatabaseConnection,
+    user_id: i32,
+) -> Result<Vec<String>, DbErr> {
+    let txn = db.begin().await?;
+    let codes = replace_recovery_codes(&txn, user_id).await?;
+    txn.commit().await?;
+
+    Ok(codes)
+}
+
+pub async fn count_unused_recovery_codes(db: &DatabaseConnection, user_id: i32) -> u64 {
+    recovery_code::Entity::find()
+        .filter(recovery_code::Column::UserId.eq(user_id))
+        .filter(recovery_code::Column::UsedAt.is_null())
+        .count(db)
+        .await
+        .unwrap_or(0)
+}
+
+async fn use_recovery_code(db: &DatabaseConnection, user_id: i32, code: &str) -> bool {
+    let result = recovery_code::Entity::update_many()
+        .col_expr(
+            recovery_code::Column::UsedAt,
+            Expr::value(OffsetDateTime::now_utc().unix_timestamp()),
+        )
+        .filter(recovery_code::Column::UserId.eq(user_id))
+        .filter(recovery_code::Column::CodeHash.eq(token::hash(&normalize_recovery_code(code))))
+        .filter(recovery_code::Column::UsedAt.is_null())
+        .exec(db)
+        .await;
+
+    match result {
+        Ok(result) => result.rows_affected == 1,
+        Err(e) => {
+            tracing::error!("Failed to use recovery code: {:?}", e);
+            false
+        }
+    }
+}
+
+/// Records `step` as the last one used by the user, failing when this or a
+/// later step was already used, possibly by a concurrent request.
+async fn use_totp_step(db: &DatabaseConnection, user_id: i32, step: i64) -> bool {
+    let result = user::Entity::update_many()
+        .col_expr(user::Column::TotpLastStep, Expr::value(step))
+        .filter(user::Column::Id.eq(user_id))
+        .filter(
+            Condition::any()
+                .add(user::Column::TotpLastStep.is_null())
+                .add(user::Column::TotpLastStep.lt(step)),
+        )
+        .exec(db)
+        .await;
+
+    match result {
+        Ok(result) => result.rows_affected == 1,
+        Err(e) => {
+            tracing::error!("Failed to record TOTP code: {:?}", e);
+            false
+        }
+    }
+}
+
+/// Checks the second factor of a user, which is either a code from the
+/// authenticator app that wasn't used before or one of the unused recovery
+/// codes.
+pub async fn verify_code(db: &DatabaseConnection, user_id: i32, code: &str) -> bool {
+    let user = match user::Entity::find_by_id(user_id).one(db).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return false,
+        Err(e) => {
+            tracing::error!("Failed to load user: {:?}", e);
+            return false;
+        }
+    };
+    let Some(secret) = user.totp_secret else {
+        return false;
+    };
+
+    match totp::verify(&secret, code, user.totp_last_step) {
+        Some(step) => use_totp_step(db, user_id, step).await,
+        None => use_recovery_code(db, user_id, code).await,
+    }
+}
@@ -1,7 +1,11 @@
 use super::password;
 use entity::user;
-use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait, QueryFilter,
+    Set,
+};
 use thiserror::Error;
+use tower_sessions::cookie::time::OffsetDateTime;
 
 pub type UserModel = user::Model;
 
@@ -39,20 +43,52 @@ pub enum CreateUserError {
 pub struct CreateUserData {
     pub email: String,
     pub password: String,
+    pub email_verified: bool,
 }
 
-pub async fn create_user(
-    db: &DatabaseConnection,
+pub async fn create_user<C: ConnectionTrait>(
+    db: &C,
     data: CreateUserData,
-) -> Result<user::ActiveModel, CreateUserError> {
+) -> Result<UserModel, CreateUserError> {
     let hashed_password = password::hash(&data.password).map_err(CreateUserError::HashPassword)?;
 
+    let email_verified_at = data
+        .email_verified
+        .then(|| OffsetDateTime::now_utc().unix_timestamp());
+
     user::ActiveModel {
         email: Set(data.email),
         password: Set(hashed_password),
+        email_verified_at: Set(email_verified_at),
         ..Default::default()
     }
-    .save(db)
+    .insert(db)
     .await
     .map_err(CreateUserError::SaveUser)
 }
+
+#[derive(Error, Debug)]
+pub enum UpdatePasswordError {
+    #[error("Failed to hash password")]
+    HashPassword(password::HashError),
+    #[error("Failed to save password")]
+    SaveUser(#[from] sea_orm::error::DbErr),
+}
+
+pub async fn update_password<C: ConnectionTrait>(
+    db: &C,
+    user_id: i32,
+    password: &str,
+) -> Result<UserModel, UpdatePasswordError> {
+    let hashed_password = password::hash(password).map_err(UpdatePasswordError::HashPassword)?;
+
+    user::ActiveModel {
+        id: Set(user_id),
+        password: Set(hashed_password),
+        password_reset_required: Set(false),
+        ..Default::default()
+    }
+    .update(db)
+    .await
+    .map_err(UpdatePasswordError::from)
+}
@@ -0,0 +1,93 @@
+use super::{db_user, token};
+use entity::user_identity;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait,
+    QueryFilter, Set, TransactionTrait,
+};
+use tower_sessions::cookie::time::OffsetDateTime;
+
+pub type UserIdentityModel = user_identity::Model;
+
+pub async fn get_identity(
+    db: &DatabaseConnection,
+    provider: &str,
+    subject: &str,
+) -> Result<Option<UserIdentityModel>, DbErr> {
+    user_identity::Entity::find()
+        .filter(user_identity::Column::Provider.eq(provider))
+        .filter(user_identity::Column::Subject.eq(subject))
+        .one(db)
+        .await
+}
+
+pub async fn list_identities(
+    db: &DatabaseConnection,
+    user_id: i32,
+) -> Result<Vec<UserIdentityModel>, DbErr> {
+    user_identity::Entity::find()
+        .filter(user_identity::Column::UserId.eq(user_id))
+        .all(db)
+        .await
+}
+
+pub async fn create_identity<C: ConnectionTrait>(
+    db: &C,
+    user_id: i32,
+    provider: &str,
+    subject: &str,
+    email: Option<String>,
+) -> Result<UserIdentityModel, DbErr> {
+    user_identity::ActiveModel {
+        user_id: Set(user_id),
+        provider: Set(provider.to_string()),
+        subject: Set(subject.to_string()),
+        email: Set(email),
+        created_at: Set(OffsetDateTime::now_utc().unix_timestamp()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+}
+
+/// Unlinks one of the user's identities, returning false if it doesn't
+/// exist or belongs to someone else.
+pub async fn delete_identity(
+    db: &DatabaseConnection,
+    user_id: i32,
+    id: i32,
+) -> Result<bool, DbErr> {
+    let result = user_identity::Entity::delete_many()
+        .filter(user_identity::Column::Id.eq(id))
+        .filter(user_identity::Column::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+
+    Ok(result.rows_affected == 1)
+}
+
+/// Signs up a new user from an external identity. The account gets a random
+/// password, which the user can replace through the password reset flow.
+pub async fn create_user_with_identity(
+    db: &DatabaseConnection,
+    email: &str,
+    email_verified: bool,
+    provider: &str,
+    subject: &str,
+) -> Result<db_user::UserModel, db_user::CreateUserError> {
+    let txn = db.begin().await?;
+
+    let user = db_user::create_user(
+        &txn,
+        db_user::CreateUserData {
+            email: email.to_string(),
+            password: token::generate(),
+            email_verified,
+        },
+    )
+    .await?;
+    create_identity(&txn, user.id, provider, subject, Some(email.to_string())).await?;
+
+    txn.commit().await?;
+
+    Ok(user)
+}
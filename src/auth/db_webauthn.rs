@@ -0,0 +1,79 @@
+use super::webauthn::VerifiedCredential;
+use entity::webauthn_credential;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    Set,
+};
+use tower_sessions::cookie::time::OffsetDateTime;
+
+pub type WebauthnCredentialModel = webauthn_credential::Model;
+
+pub async fn list_credentials(
+    db: &DatabaseConnection,
+    user_id: i32,
+) -> Result<Vec<WebauthnCredentialModel>, DbErr> {
+    webauthn_credential::Entity::find()
+        .filter(webauthn_credential::Column::UserId.eq(user_id))
+        .order_by_asc(webauthn_credential::Column::CreatedAt)
+        .all(db)
+        .await
+}
+
+pub async fn get_credential(
+    db: &DatabaseConnection,
+    credential_id: &str,
+) -> Result<Option<WebauthnCredentialModel>, DbErr> {
+    webauthn_credential::Entity::find()
+        .filter(webauthn_credential::Column::CredentialId.eq(credential_id))
+        .one(db)
+        .await
+}
+
+pub async fn create_credential(
+    db: &DatabaseConnection,
+    user_id: i32,
+    name: &str,
+    credential: VerifiedCredential,
+) -> Result<WebauthnCredentialModel, DbErr> {
+    webauthn_credential::ActiveModel {
+        user_id: Set(user_id),
+        credential_id: Set(credential.credential_id),
+        public_key: Set(credential.public_key),
+        sign_count: Set(credential.sign_count.into()),
+        name: Set(name.to_string()),
+        created_at: Set(OffsetDateTime::now_utc().unix_timestamp()),
+        last_used_at: Set(None),
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+}
+
+pub async fn record_usage(db: &DatabaseConnection, id: i32, sign_count: u32) -> Result<(), DbErr> {
+    webauthn_credential::ActiveModel {
+        id: Set(id),
+        sign_count: Set(sign_count.into()),
+        last_used_at: Set(Some(OffsetDateTime::now_utc().unix_timestamp())),
+        ..Default::default()
+    }
+    .update(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Deletes one of the user's credentials, returning false if it doesn't
+/// exist or belongs to someone else.
+pub async fn delete_credential(
+    db: &DatabaseConnection,
+    user_id: i32,
+    id: i32,
+) -> Result<bool, DbErr> {
+    let result = webauthn_credential::Entity::delete_many()
+        .filter(webauthn_credential::Column::Id.eq(id))
+        .filter(webauthn_credential::Column::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+
+    Ok(result.rows_affected == 1)
+}
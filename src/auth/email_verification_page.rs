@@ -0,0 +1,179 @@
+use super::{db_email_verification, db_user};
+use crate::{
+    app::AppState, layout::template_response::TemplateResponse, mailer::Email,
+    user::db_user_session,
+};
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+    Form,
+};
+use serde::{Deserialize, Serialize};
+use tower_sessions::Session;
+use validator::{Validate, ValidationErrors};
+
+/// Creates a verification token for the address and emails the link to it.
+/// Failures are logged, the user can always ask for another link.
+pub async fn send_verification_email(app: &AppState, user_id: i32, email: &str) {
+    send_verification_link(
+        app,
+        user_id,
+        email,
+        "If you didn't create an account, you can safely ignore this email.",
+    )
+    .await
+}
+
+/// Emails a verification link to the address the user wants to change to.
+/// The change only happens once the link is opened.
+pub async fn send_email_change_verification(app: &AppState, user_id: i32, new_email: &str) {
+    send_verification_link(
+        app,
+        user_id,
+        new_email,
+        "Your email address will be changed once you open the link. If you didn't ask for \
+        this change, you can safely ignore this email.",
+    )
+    .await
+}
+
+async fn send_verification_link(app: &AppState, user_id: i32, email: &str, notice: &str) {
+    let token =
+        match db_email_verification::create_token(&app.database_connection, user_id, email).await {
+            Ok(token) => token,
+            Err(e) => {
+                tracing::error!("Failed to create email verification token: {:?}", e);
+                return;
+            }
+        };
+
+    let email = Email {
+        to: email.to_string(),
+        subject: "Verify your email address".to_string(),
+        body: format!(
+            "Please confirm that this is your email address by opening the link below:\n\n{}\n\n{}",
+            app.config.url(&format!("/verify-email/{}", token)),
+            notice
+        ),
+    };
+    if let Err(e) = app.mailer.send(email).await {
+        tracing::error!("Failed to send verification email: {:?}", e);
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, Validate)]
+pub struct ResendVerificationForm {
+    #[validate(email(message = "Invalid email address"))]
+    email: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct VerifyEmailPageData {
+    form: ResendVerificationForm,
+    errors: Option<ValidationErrors>,
+    verified: bool,
+    invalid_token: bool,
+    email_taken: bool,
+    sent: bool,
+}
+
+pub async fn get_verify_email(
+    State(app): State<AppState>,
+    session: Session,
+    Path(token): Path<String>,
+) -> Response {
+    let response = TemplateResponse::new("auth/verify_email");
+
+    match db_email_verification::verify_email(&app.database_connection, &token).await {
+        Ok(verified) if verified.email_changed => {
+            // Whoever knew the old address may have sessions open, only the
+            // browser that opened the link stays logged in.
+            let current_session_id = session.id().map(|id| id.to_string()).unwrap_or_default();
+            if let Err(e) = db_user_session::delete_other_sessions(
+                &app.database_connection,
+                verified.user_id,
+                &current_session_id,
+            )
+            .await
+            {
+                tracing::error!("Failed to revoke sessions after email change: {:?}", e);
+            }
+
+            response
+                .content(VerifyEmailPageData {
+                    verified: true,
+                    ..Default::default()
+                })
+                .add_success_message("Your email address has been changed")
+                .into_response()
+        }
+        Ok(_) => response
+            .content(VerifyEmailPageData {
+                verified: true,
+                ..Default::default()
+            })
+            .add_success_message("Your email address has been verified")
+            .into_response(),
+        Err(db_email_verification::VerifyEmailError::InvalidToken) => response
+            .content(VerifyEmailPageData {
+                invalid_token: true,
+                ..Default::default()
+            })
+            .add_error_message("This verification link is invalid or has expired")
+            .into_response(),
+        Err(db_email_verification::VerifyEmailError::EmailTaken) => response
+            .content(VerifyEmailPageData {
+                email_taken: true,
+                ..Default::default()
+            })
+            .add_error_message("This email address is already used by another account")
+            .into_response(),
+        Err(e) => {
+            tracing::error!("Failed to verify email: {:?}", e);
+
+            response
+                .content(VerifyEmailPageData::default())
+                .add_error_message("Failed to verify email, try again later")
+                .into_response()
+        }
+    }
+}
+
+pub async fn get_resend_verification() -> Response {
+    TemplateResponse::new("auth/verify_email")
+        .content(VerifyEmailPageData::default())
+        .into_response()
+}
+
+pub async fn post_resend_verification(
+    State(app): State<AppState>,
+    Form(form): Form<ResendVerificationForm>,
+) -> Response {
+    let response = TemplateResponse::new("auth/verify_email");
+    if let Err(errors) = form.validate() {
+        return response
+            .content(VerifyEmailPageData {
+                form,
+                errors: Some(errors),
+                ..Default::default()
+            })
+            .into_response();
+    }
+
+    if let Some(user) = db_user::get_user_by_email(&app.database_connection, &form.email).await {
+        if user.email_verified_at.is_none() {
+            send_verification_email(&app, user.id, &user.email).await;
+        }
+    }
+
+    response
+        .content(VerifyEmailPageData {
+            form,
+            sent: true,
+            ..Default::default()
+        })
+        .add_success_message(
+            "If this address is waiting for verification, a new link is on its way",
+        )
+        .into_response()
+}
@@ -1,14 +1,59 @@
+use std::collections::HashSet;
+
 use async_trait::async_trait;
-use axum_login::{AuthManagerLayer, AuthManagerLayerBuilder, AuthUser, AuthnBackend, UserId};
-use sea_orm::DatabaseConnection;
+use axum::{
+    extract::FromRequestParts,
+    http::{
+        header::{AUTHORIZATION, WWW_AUTHENTICATE},
+        request::Parts,
+        HeaderMap, StatusCode,
+    },
+    response::{IntoResponse, Response},
+};
+use axum_login::{
+    AuthManagerLayer, AuthManagerLayerBuilder, AuthUser, AuthnBackend, AuthzBackend, UserId,
+};
+use sea_orm::{DatabaseConnection, DbErr};
+use thiserror::Error;
 use tower_sessions::{cookie::time::Duration, Expiry, SessionManagerLayer};
 
-use super::{db_session_store::DatabaseSessionStore, db_user, password};
+use super::{
+    authz::Permission,
+    db_api_token, db_authz, db_magic_link,
+    db_session_store::DatabaseSessionStore,
+    db_user, db_user_identity, db_webauthn, password,
+    scope::{self, Scope},
+    webauthn::{AssertionResponse, Webauthn},
+};
+use crate::{api::problem::Problem, config::Config};
 
 #[derive(Debug, Clone)]
 pub struct User {
     id: i32,
+    email: String,
     pw_hash: Vec<u8>,
+    email_verified: bool,
+    two_factor_enabled: bool,
+    disabled: bool,
+    password_reset_required: bool,
+}
+
+impl User {
+    pub fn email(&self) -> &str {
+        &self.email
+    }
+
+    pub fn is_email_verified(&self) -> bool {
+        self.email_verified
+    }
+
+    pub fn has_two_factor(&self) -> bool {
+        self.two_factor_enabled
+    }
+
+    pub fn verify_password(&self, password: &str) -> bool {
+        password::verify(password, &String::from_utf8_lossy(&self.pw_hash))
+    }
 }
 
 impl AuthUser for User {
@@ -27,7 +72,12 @@ impl From<db_user::UserModel> for User {
     fn from(user: db_user::UserModel) -> Self {
         Self {
             id: user.id,
+            email: user.email,
             pw_hash: user.password.as_bytes().to_vec(),
+            email_verified: user.email_verified_at.is_some(),
+            two_factor_enabled: user.totp_secret.is_some(),
+            disabled: user.disabled_at.is_some(),
+            password_reset_required: user.password_reset_required,
         }
     }
 }
@@ -35,56 +85,448 @@ impl From<db_user::UserModel> for User {
 #[derive(Clone)]
 pub struct Backend {
     db: DatabaseConnection,
+    webauthn: Webauthn,
+    require_verified_email: bool,
 }
 
 impl Backend {
-    pub fn new(db: DatabaseConnection) -> Self {
-        Self { db }
+    pub fn new(db: DatabaseConnection, webauthn: Webauthn) -> Self {
+        Self {
+            db,
+            webauthn,
+            require_verified_email: false,
+        }
+    }
+
+    /// Refuses to authenticate users who haven't verified their email address yet.
+    pub fn require_verified_email(mut self, require_verified_email: bool) -> Self {
+        self.require_verified_email = require_verified_email;
+        self
     }
 }
 
-pub struct Credentials {
-    pub email: String,
-    pub password: String,
+#[derive(Error, Debug)]
+pub enum BackendError {
+    #[error("Email address is not verified")]
+    EmailNotVerified,
+    #[error("Account is disabled")]
+    AccountDisabled,
+    #[error("Password must be reset before logging in")]
+    PasswordResetRequired,
+    #[error("Failed to query the database")]
+    Database(#[from] DbErr),
+}
+
+pub enum Credentials {
+    Password {
+        email: String,
+        password: String,
+    },
+    /// An answer to a WebAuthn challenge previously stored in the session.
+    WebAuthn {
+        challenge: String,
+        response: AssertionResponse,
+    },
+    /// An identity asserted by an OpenID Connect provider, whose ID token was
+    /// already validated.
+    Oidc {
+        provider: String,
+        subject: String,
+    },
+    /// A sign-in link emailed to the user, which is used up by logging in.
+    MagicLink {
+        token: String,
+    },
+}
+
+impl Backend {
+    async fn authenticate_password(&self, email: &str, password: &str) -> Option<User> {
+        db_user::get_user_by_email(&self.db, email)
+            .await
+            .filter(|user| password::verify(password, &user.password))
+            .map(User::from)
+    }
+
+    async fn authenticate_webauthn(
+        &self,
+        challenge: &str,
+        response: &AssertionResponse,
+    ) -> Result<Option<User>, BackendError> {
+        let Some(credential) = db_webauthn::get_credential(&self.db, &response.id).await? else {
+            return Ok(None);
+        };
+
+        let stored_sign_count = u32::try_from(credential.sign_count).unwrap_or(u32::MAX);
+        match self.webauthn.verify_assertion(
+            challenge,
+            response,
+            &credential.public_key,
+            stored_sign_count,
+        ) {
+            Ok(sign_count) => {
+                db_webauthn::record_usage(&self.db, credential.id, sign_count).await?;
+            }
+            Err(e) => {
+                tracing::warn!("Rejected passkey assertion: {}", e);
+                return Ok(None);
+            }
+        }
+
+        Ok(db_user::get_user_by_id(&self.db, credential.user_id)
+            .await
+            .map(User::from))
+    }
 }
 
 #[async_trait]
 impl AuthnBackend for Backend {
     type User = User;
     type Credentials = Credentials;
-    type Error = std::convert::Infallible;
+    type Error = BackendError;
 
     async fn authenticate(
         &self,
         credentials: Self::Credentials,
     ) -> Result<Option<Self::User>, Self::Error> {
-        let user = db_user::get_user_by_email(&self.db, &credentials.email)
-            .await
-            .filter(|user| password::verify(&credentials.password, &user.password))
-            .map(User::from);
+        let user = match credentials {
+            Credentials::Password { email, password } => {
+                self.authenticate_password(&email, &password).await
+            }
+            Credentials::WebAuthn {
+                challenge,
+                response,
+            } => self.authenticate_webauthn(&challenge, &response).await?,
+            Credentials::Oidc { provider, subject } => {
+                match db_user_identity::get_identity(&self.db, &provider, &subject).await? {
+                    Some(identity) => db_user::get_user_by_id(&self.db, identity.user_id)
+                        .await
+                        .map(User::from),
+                    None => None,
+                }
+            }
+            Credentials::MagicLink { token } => {
+                match db_magic_link::consume_token(&self.db, &token).await? {
+                    Some(user_id) => db_user::get_user_by_id(&self.db, user_id)
+                        .await
+                        .map(User::from),
+                    None => None,
+                }
+            }
+        };
 
-        Ok(user)
+        match user {
+            Some(user) if user.disabled => Err(BackendError::AccountDisabled),
+            // A reset is required when the account may be compromised, which
+            // no other way of logging in should get around.
+            Some(user) if user.password_reset_required => Err(BackendError::PasswordResetRequired),
+            Some(user) if self.require_verified_email && !user.is_email_verified() => {
+                Err(BackendError::EmailNotVerified)
+            }
+            user => Ok(user),
+        }
     }
 
     async fn get_user(&self, user_id: &UserId<Self>) -> Result<Option<Self::User>, Self::Error> {
+        // Disabled users are logged out of the sessions they still have.
         let user = db_user::get_user_by_id(&self.db, *user_id)
             .await
-            .map(User::from);
+            .map(User::from)
+            .filter(|user| !user.disabled);
 
         Ok(user)
     }
 }
 
+/// Permissions come from the roles assigned to the user, there are no
+/// per-user grants.
+#[async_trait]
+impl AuthzBackend for Backend {
+    type Permission = Permission;
+
+    async fn get_group_permissions(
+        &self,
+        user: &Self::User,
+    ) -> Result<HashSet<Self::Permission>, Self::Error> {
+        let permissions = db_authz::get_user_permissions(&self.db, user.id).await?;
+
+        Ok(permissions.into_iter().map(Permission).collect())
+    }
+}
+
 pub type AuthSession = axum_login::AuthSession<Backend>;
 
+/// A user authenticated with a personal API token from the
+/// `Authorization: Bearer` header. Nothing is stored in the session, every
+/// request has to carry the token.
+#[derive(Debug, Clone)]
+pub struct ApiUser {
+    pub user: User,
+    scopes: Vec<Scope>,
+}
+
+impl ApiUser {
+    /// Fails unless the token was granted the scope.
+    pub fn require(&self, scope: Scope) -> Result<(), ApiAuthError> {
+        if self.scopes.contains(&scope) {
+            Ok(())
+        } else {
+            Err(ApiAuthError::InsufficientScope(scope))
+        }
+    }
+}
+
+impl Backend {
+    /// Checks a personal API token the same way a login is checked, without
+    /// touching the session.
+    pub async fn authenticate_api_token(
+        &self,
+        secret: &str,
+    ) -> Result<Option<ApiUser>, BackendError> {
+        let Some(api_token) = db_api_token::use_token(&self.db, secret).await? else {
+            return Ok(None);
+        };
+        let Some(user) = db_user::get_user_by_id(&self.db, api_token.user_id)
+            .await
+            .map(User::from)
+        else {
+            return Ok(None);
+        };
+
+        if user.disabled {
+            Err(BackendError::AccountDisabled)
+        } else if user.password_reset_required {
+            Err(BackendError::PasswordResetRequired)
+        } else if self.require_verified_email && !user.is_email_verified() {
+            Err(BackendError::EmailNotVerified)
+        } else {
+            Ok(Some(ApiUser {
+                user,
+                scopes: scope::split(&api_token.scopes),
+            }))
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ApiAuthError {
+    #[error("An API token is required")]
+    MissingToken,
+    #[error("The API token is invalid or has expired")]
+    InvalidToken,
+    #[error("{0}")]
+    Refused(BackendError),
+    #[error("The API token doesn't have the {} scope", .0.as_str())]
+    InsufficientScope(Scope),
+    #[error("Failed to authenticate the API token")]
+    Internal,
+}
+
+impl From<ApiAuthError> for Problem {
+    fn from(error: ApiAuthError) -> Self {
+        let (status, challenge) = match &error {
+            ApiAuthError::MissingToken => (StatusCode::UNAUTHORIZED, "Bearer".to_string()),
+            ApiAuthError::InvalidToken => (
+                StatusCode::UNAUTHORIZED,
+                "Bearer error=\"invalid_token\"".to_string(),
+            ),
+            ApiAuthError::Refused(_) => (StatusCode::FORBIDDEN, "Bearer".to_string()),
+            ApiAuthError::InsufficientScope(scope) => (
+                StatusCode::FORBIDDEN,
+                format!(
+                    "Bearer error=\"insufficient_scope\", scope=\"{}\"",
+                    scope.as_str()
+                ),
+            ),
+            ApiAuthError::Internal => return Problem::internal(error.to_string()),
+        };
+
+        Problem::new(status)
+            .detail(error.to_string())
+            .header(WWW_AUTHENTICATE, &challenge)
+    }
+}
+
+impl IntoResponse for ApiAuthError {
+    fn into_response(self) -> Response {
+        Problem::from(self).into_response()
+    }
+}
+
+/// Reads the token of an `Authorization: Bearer` header.
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ApiUser {
+    type Rejection = ApiAuthError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let secret = bearer_token(&parts.headers)
+            .ok_or(ApiAuthError::MissingToken)?
+            .to_string();
+        let auth_session = AuthSession::from_request_parts(parts, state)
+            .await
+            .map_err(|_| ApiAuthError::Internal)?;
+
+        match auth_session.backend.authenticate_api_token(&secret).await {
+            Ok(Some(api_user)) => Ok(api_user),
+            Ok(None) => Err(ApiAuthError::InvalidToken),
+            Err(BackendError::Database(e)) => {
+                tracing::error!("Failed to authenticate API token: {:?}", e);
+                Err(ApiAuthError::Internal)
+            }
+            Err(e) => Err(ApiAuthError::Refused(e)),
+        }
+    }
+}
+
 pub fn create_auth_layer(
     db: DatabaseConnection,
+    config: &Config,
+    webauthn: Webauthn,
 ) -> AuthManagerLayer<Backend, DatabaseSessionStore> {
     let session_store = DatabaseSessionStore::new(db.clone());
     let session_layer = SessionManagerLayer::new(session_store)
-        .with_secure(false)
+        .with_secure(config.secure_cookies())
         .with_expiry(Expiry::OnInactivity(Duration::minutes(30)));
-    let backend = Backend::new(db);
+    let backend = Backend::new(db, webauthn).require_verified_email(config.require_verified_email);
 
     AuthManagerLayerBuilder::new(backend, session_layer).build()
 }
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::{sea_query::Expr, EntityTrait};
+
+    use super::*;
+    use crate::{
+        auth::webauthn::tests::{relying_party, SoftwareAuthenticator},
+        database,
+    };
+
+    /// A backend whose only user registered the authenticator as a passkey.
+    async fn backend_with_passkey(authenticator: &mut SoftwareAuthenticator) -> Backend {
+        let db = database::connect_in_memory().await;
+        let user = db_user::create_user(
+            &db,
+            db_user::CreateUserData {
+                email: "user@example.com".to_string(),
+                password: "correct horse battery staple".to_string(),
+                email_verified: true,
+            },
+        )
+        .await
+        .unwrap();
+        let credential = relying_party()
+            .verify_registration("registration", &authenticator.register("registration"))
+            .unwrap();
+        db_webauthn::create_credential(&db, user.id, "Test key", credential)
+            .await
+            .unwrap();
+
+        Backend::new(db, relying_party())
+    }
+
+    fn passkey(challenge: &str, response: AssertionResponse) -> Credentials {
+        Credentials::WebAuthn {
+            challenge: challenge.to_string(),
+            response,
+        }
+    }
+
+    #[tokio::test]
+    async fn passkey_logs_in_its_user() {
+        let mut authenticator = SoftwareAuthenticator::new();
+        let backend = backend_with_passkey(&mut authenticator).await;
+
+        let user = backend
+            .authenticate(passkey("login", authenticator.assert("login")))
+            .await
+            .unwrap();
+        assert_eq!(
+            user.map(|user| user.email),
+            Some("user@example.com".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_passkey_is_rejected() {
+        let mut authenticator = SoftwareAuthenticator::new();
+        let backend = backend_with_passkey(&mut authenticator).await;
+        let mut unknown = SoftwareAuthenticator::new();
+
+        let user = backend
+            .authenticate(passkey("login", unknown.assert("login")))
+            .await
+            .unwrap();
+        assert!(user.is_none());
+    }
+
+    #[tokio::test]
+    async fn replayed_assertion_is_rejected() {
+        let mut authenticator = SoftwareAuthenticator::new();
+        let backend = backend_with_passkey(&mut authenticator).await;
+        let response = authenticator.assert("login");
+
+        let first = backend
+            .authenticate(passkey("login", response.clone()))
+            .await
+            .unwrap();
+        assert!(first.is_some());
+
+        // The stored counter moved on, so the same signature counts as a
+        // cloned authenticator.
+        let replayed = backend
+            .authenticate(passkey("login", response))
+            .await
+            .unwrap();
+        assert!(replayed.is_none());
+    }
+
+    #[tokio::test]
+    async fn passkey_is_refused_while_password_reset_is_required() {
+        let mut authenticator = SoftwareAuthenticator::new();
+        let backend = backend_with_passkey(&mut authenticator).await;
+        entity::user::Entity::update_many()
+            .col_expr(
+                entity::user::Column::PasswordResetRequired,
+                Expr::value(true),
+            )
+            .exec(&backend.db)
+            .await
+            .unwrap();
+
+        let result = backend
+            .authenticate(passkey("login", authenticator.assert("login")))
+            .await;
+        assert!(matches!(result, Err(BackendError::PasswordResetRequired)));
+    }
+
+    /// A touch alone must not stand in for both factors of an account with
+    /// two-factor authentication.
+    #[tokio::test]
+    async fn passkey_without_user_verification_is_rejected() {
+        let mut authenticator = SoftwareAuthenticator::new();
+        let backend = backend_with_passkey(&mut authenticator).await;
+        entity::user::Entity::update_many()
+            .col_expr(
+                entity::user::Column::TotpSecret,
+                Expr::value("JBSWY3DPEHPK3PXP"),
+            )
+            .exec(&backend.db)
+            .await
+            .unwrap();
+        authenticator.user_verified = false;
+
+        let user = backend
+            .authenticate(passkey("login", authenticator.assert("login")))
+            .await
+            .unwrap();
+        assert!(user.is_none());
+    }
+}
@@ -1,55 +1,191 @@
+use crate::app::AppState;
+use crate::audit::{action::Action, context::AuditContext, event::AuditEvent};
 use crate::auth;
+use crate::auth::client_ip::ClientIp;
+use crate::auth::redirect::{redirect_to_next, NextUrl};
+use crate::auth::two_factor_page::start_pending_login;
+use crate::auth::{db_login_throttle, db_user};
+use crate::error::AppError;
 use crate::layout::template_response::TemplateResponse;
-use axum::extract::Query;
-use axum::http::StatusCode;
+use axum::extract::State;
 use axum::{
+    http::HeaderMap,
     response::{IntoResponse, Redirect, Response},
     Form,
 };
+use axum_login::AuthUser;
+use axum_messages::Messages;
 use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use thiserror::Error;
+use tower_sessions::{cookie::time::OffsetDateTime, Session};
+use utoipa::ToSchema;
 use validator::{Validate, ValidationErrors};
 
-#[derive(Deserialize, Serialize, Default, Debug, Clone, Validate)]
+#[derive(Deserialize, Default, Debug, Clone, Validate, ToSchema)]
 pub struct LoginForm {
     #[validate(email(message = "Invalid email address"))]
     email: String,
     #[validate(length(min = 1, message = "Password is required"))]
+    #[schema(format = Password, required = true)]
     password: String,
 }
 
 impl From<LoginForm> for auth::layer::Credentials {
     fn from(form: LoginForm) -> Self {
-        auth::layer::Credentials {
+        auth::layer::Credentials::Password {
             email: form.email,
             password: form.password,
         }
     }
 }
 
+/// What the page shows back of a submitted login form, never the password.
+#[derive(Serialize, Default, Debug)]
+pub struct LoginFormValues {
+    email: String,
+}
+
+impl From<LoginForm> for LoginFormValues {
+    fn from(form: LoginForm) -> Self {
+        LoginFormValues { email: form.email }
+    }
+}
+
 #[derive(Serialize, Default, Debug)]
 pub struct LoginPageData {
-    form: LoginForm,
+    form: LoginFormValues,
     errors: Option<ValidationErrors>,
     next_url: Option<String>,
+    email_not_verified: bool,
+    oidc_providers: Vec<OidcProviderLink>,
+    retry_after_minutes: Option<i64>,
 }
 
-#[derive(Deserialize)]
-pub struct NextUrl {
-    next: Option<String>,
+#[derive(Serialize, Debug)]
+pub struct OidcProviderLink {
+    slug: String,
+    name: String,
 }
 
-pub async fn get_login(Query(query): Query<NextUrl>) -> impl IntoResponse {
-    TemplateResponse::new("auth/login")
+fn oidc_provider_links(app: &AppState) -> Vec<OidcProviderLink> {
+    app.oidc_providers
+        .iter()
+        .map(|provider| OidcProviderLink {
+            slug: provider.slug().to_string(),
+            name: provider.name().to_string(),
+        })
+        .collect()
+}
+
+fn locked_out(
+    app: &AppState,
+    template: TemplateResponse,
+    form: LoginForm,
+    next_url: Option<String>,
+    locked_until: i64,
+) -> Response {
+    let seconds = locked_until - OffsetDateTime::now_utc().unix_timestamp();
+
+    template
+        .add_error_message("Too many failed login attempts")
         .content(LoginPageData {
-            form: LoginForm::default(),
+            form: form.into(),
             errors: None,
-            next_url: query.next,
+            next_url,
+            email_not_verified: false,
+            oidc_providers: oidc_provider_links(app),
+            retry_after_minutes: Some((seconds + 59) / 60),
         })
+        .into_response()
+}
+
+#[derive(Error, Debug)]
+pub enum PasswordLoginError {
+    #[error("Too many failed login attempts")]
+    LockedOut(i64),
+    #[error("Invalid email or password")]
+    InvalidCredentials,
+    #[error("{0}")]
+    Refused(auth::layer::BackendError),
+    #[error("Failed to authenticate user")]
+    Internal,
+}
+
+/// Checks the email and password against the login throttling, recording
+/// failed attempts. The user isn't logged in, that is up to the caller.
+pub async fn authenticate_password(
+    app: &AppState,
+    auth_session: &auth::layer::AuthSession,
+    ip: IpAddr,
+    audit: &AuditContext,
+    form: &LoginForm,
+) -> Result<auth::layer::User, PasswordLoginError> {
+    let db = &app.database_connection;
+    let ip = ip.to_string();
+    match db_login_throttle::locked_until(db, &form.email, &ip).await {
+        Ok(Some(locked_until)) => return Err(PasswordLoginError::LockedOut(locked_until)),
+        Ok(None) => {}
+        Err(e) => tracing::error!("Failed to check login throttling: {:?}", e),
+    }
+
+    match auth_session.authenticate(form.clone().into()).await {
+        Ok(Some(user)) => {
+            // With two-factor the login only succeeds once the code is
+            // checked, which counts against the same throttling.
+            if !user.has_two_factor() {
+                if let Err(e) = db_login_throttle::record_success(db, &form.email).await {
+                    tracing::error!("Failed to reset login throttling: {:?}", e);
+                }
+            }
+            Ok(user)
+        }
+        Ok(None) => {
+            let target = db_user::get_user_by_email(db, &form.email).await;
+            let event = AuditEvent::new(Action::LoginFailed)
+                .maybe_target(target.map(|user| user.id))
+                .details("password");
+            audit.record(db, event).await;
+
+            match db_login_throttle::record_failure(db, &form.email, &ip).await {
+                Ok(Some(locked_until)) => return Err(PasswordLoginError::LockedOut(locked_until)),
+                Ok(None) => {}
+                Err(e) => tracing::error!("Failed to record failed login: {:?}", e),
+            }
+            Err(PasswordLoginError::InvalidCredentials)
+        }
+        Err(axum_login::Error::Backend(auth::layer::BackendError::Database(e))) => {
+            tracing::error!("Failed to authenticate user: {:?}", e);
+            Err(PasswordLoginError::Internal)
+        }
+        Err(axum_login::Error::Backend(e)) => Err(PasswordLoginError::Refused(e)),
+        Err(e) => {
+            tracing::error!("Failed to authenticate user: {:?}", e);
+            Err(PasswordLoginError::Internal)
+        }
+    }
+}
+
+pub async fn get_login(State(app): State<AppState>, NextUrl(next): NextUrl) -> impl IntoResponse {
+    TemplateResponse::new("auth/login").content(LoginPageData {
+        form: LoginFormValues::default(),
+        errors: None,
+        next_url: next,
+        email_not_verified: false,
+        oidc_providers: oidc_provider_links(&app),
+        retry_after_minutes: None,
+    })
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn post_login(
+    State(app): State<AppState>,
     mut auth_session: auth::layer::AuthSession,
-    Query(NextUrl { next }): Query<NextUrl>,
+    session: Session,
+    ClientIp(ip): ClientIp,
+    audit: AuditContext,
+    NextUrl(next): NextUrl,
+    headers: HeaderMap,
     Form(form): Form<LoginForm>,
 ) -> Response {
     let template = TemplateResponse::new("auth/login");
@@ -57,62 +193,155 @@ pub async fn post_login(
         return template
             .add_error_message("Please fix the errors above")
             .content(LoginPageData {
-                form,
+                form: form.into(),
                 errors: Some(errors),
                 next_url: next,
+                email_not_verified: false,
+                oidc_providers: oidc_provider_links(&app),
+                retry_after_minutes: None,
             })
             .into_response();
     }
-    let user = match auth_session.authenticate(form.clone().into()).await {
-        Ok(Some(user)) => user,
-        Ok(None) => {
+
+    let user = match authenticate_password(&app, &auth_session, ip, &audit, &form).await {
+        Ok(user) => user,
+        Err(PasswordLoginError::LockedOut(locked_until)) => {
+            return locked_out(&app, template, form, next, locked_until)
+        }
+        Err(PasswordLoginError::InvalidCredentials) => {
             return template
                 .add_error_message("Invalid email or password")
                 .content(LoginPageData {
-                    form,
+                    form: form.into(),
                     errors: None,
                     next_url: next,
+                    email_not_verified: false,
+                    oidc_providers: oidc_provider_links(&app),
+                    retry_after_minutes: None,
                 })
                 .into_response();
         }
-        Err(e) => {
-            tracing::error!("Failed to authenticate user: {:?}", e);
+        Err(PasswordLoginError::Refused(auth::layer::BackendError::EmailNotVerified)) => {
+            return template
+                .add_error_message("Please verify your email address before logging in")
+                .content(LoginPageData {
+                    form: form.into(),
+                    errors: None,
+                    next_url: next,
+                    email_not_verified: true,
+                    oidc_providers: oidc_provider_links(&app),
+                    retry_after_minutes: None,
+                })
+                .into_response();
+        }
+        Err(PasswordLoginError::Refused(auth::layer::BackendError::AccountDisabled)) => {
+            return template
+                .add_error_message("This account has been disabled")
+                .content(LoginPageData {
+                    form: form.into(),
+                    errors: None,
+                    next_url: next,
+                    email_not_verified: false,
+                    oidc_providers: oidc_provider_links(&app),
+                    retry_after_minutes: None,
+                })
+                .into_response();
+        }
+        Err(PasswordLoginError::Refused(auth::layer::BackendError::PasswordResetRequired)) => {
+            return template
+                .add_error_message(
+                    "You need to choose a new password, use the reset link we emailed you or request a new one",
+                )
+                .content(LoginPageData {
+                    form: form.into(),
+                    errors: None,
+                    next_url: next,
+                    email_not_verified: false,
+                    oidc_providers: oidc_provider_links(&app),
+                    retry_after_minutes: None,
+                })
+                .into_response();
+        }
+        Err(_) => {
             return template
                 .add_error_message("Internal Error: Failed to authenticate user, try again later")
                 .content(LoginPageData {
-                    form,
+                    form: form.into(),
                     errors: None,
                     next_url: next,
+                    email_not_verified: false,
+                    oidc_providers: oidc_provider_links(&app),
+                    retry_after_minutes: None,
                 })
                 .into_response();
         }
     };
 
+    if user.has_two_factor() {
+        if let Err(e) = start_pending_login(&session, user.id(), next.clone()).await {
+            tracing::error!("Failed to start two-factor login: {:?}", e);
+            return template
+                .add_error_message("Internal Error: Failed to login user, try again later")
+                .content(LoginPageData {
+                    form: form.into(),
+                    errors: None,
+                    next_url: next,
+                    email_not_verified: false,
+                    oidc_providers: oidc_provider_links(&app),
+                    retry_after_minutes: None,
+                })
+                .into_response();
+        }
+
+        return Redirect::to("/login/2fa").into_response();
+    }
+
     if auth_session.login(&user).await.is_err() {
         tracing::error!("Failed to login user: {:?}", user);
         return template
             .add_error_message("Internal Error: Failed to login user, try again later")
             .content(LoginPageData {
-                form,
+                form: form.into(),
                 errors: None,
                 next_url: next,
+                email_not_verified: false,
+                oidc_providers: oidc_provider_links(&app),
+                retry_after_minutes: None,
             })
             .into_response();
     }
 
-    if let Some(next) = next {
-        return Redirect::to(&next).into_response();
-    }
+    audit
+        .record(
+            &app.database_connection,
+            AuditEvent::new(Action::Login)
+                .by_user(user.id())
+                .details("password"),
+        )
+        .await;
 
-    Redirect::to("/").into_response()
+    redirect_to_next(&headers, next.as_deref().unwrap_or("/"))
 }
 
-pub async fn get_logout(mut auth_session: auth::layer::AuthSession) -> Response {
-    match auth_session.logout().await {
-        Ok(_) => Redirect::to("/login").into_response(),
-        Err(e) => {
-            tracing::error!("Failed to logout: {:?}", e);
-            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to logout").into_response()
-        }
+pub async fn get_logout(
+    State(app): State<AppState>,
+    mut auth_session: auth::layer::AuthSession,
+    audit: AuditContext,
+    messages: Messages,
+) -> Result<Redirect, AppError> {
+    let user = auth_session
+        .logout()
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to logout: {:?}", e)))?;
+    if let Some(user) = user {
+        audit
+            .record(
+                &app.database_connection,
+                AuditEvent::new(Action::Logout).by_user(user.id()),
+            )
+            .await;
+        messages.info("You have been logged out");
     }
+
+    Ok(Redirect::to("/login"))
 }
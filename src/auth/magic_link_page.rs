@@ -0,0 +1,166 @@
+use super::{
+    db_magic_link, db_user,
+    layer::{AuthSession, BackendError, Credentials},
+    redirect::NextUrl,
+    two_factor_page::start_pending_login,
+};
+use crate::{
+    app::AppState,
+    audit::{action::Action, context::AuditContext, event::AuditEvent},
+    layout::template_response::TemplateResponse,
+    mailer::Email,
+};
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_login::AuthUser;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use tower_sessions::Session;
+use validator::{Validate, ValidationErrors};
+
+#[derive(Debug, Deserialize, Serialize, Default, Validate)]
+pub struct MagicLinkForm {
+    #[validate(email(message = "Invalid email address"))]
+    email: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct MagicLinkPageData {
+    form: MagicLinkForm,
+    errors: Option<ValidationErrors>,
+    next_url: Option<String>,
+    submitted: bool,
+}
+
+fn magic_link_error(message: &str) -> Response {
+    TemplateResponse::new("auth/magic_link")
+        .content(MagicLinkPageData::default())
+        .add_error_message(message)
+        .into_response()
+}
+
+pub async fn get_magic_link(NextUrl(next): NextUrl) -> Response {
+    TemplateResponse::new("auth/magic_link")
+        .content(MagicLinkPageData {
+            next_url: next,
+            ..Default::default()
+        })
+        .into_response()
+}
+
+pub async fn post_magic_link(
+    State(app): State<AppState>,
+    NextUrl(next): NextUrl,
+    Form(form): Form<MagicLinkForm>,
+) -> Response {
+    let response = TemplateResponse::new("auth/magic_link");
+    if let Err(errors) = form.validate() {
+        return response
+            .content(MagicLinkPageData {
+                form,
+                errors: Some(errors),
+                next_url: next,
+                submitted: false,
+            })
+            .into_response();
+    }
+
+    let db = &app.database_connection;
+    if let Some(user) = db_user::get_user_by_email(db, &form.email).await {
+        match db_magic_link::create_token(db, user.id).await {
+            Ok(Some(token)) => {
+                let mut path = format!("/login/magic/{}", token);
+                if let Some(next) = &next {
+                    path = format!(
+                        "{}?next={}",
+                        path,
+                        utf8_percent_encode(next, NON_ALPHANUMERIC)
+                    );
+                }
+                let email = Email {
+                    to: user.email,
+                    subject: "Your sign-in link".to_string(),
+                    body: format!(
+                        "Open the link below within the next 15 minutes to log in:\n\n{}\n\n\
+                        The link can only be used once. If you didn't ask for it, you can \
+                        safely ignore this email.",
+                        app.config.url(&path)
+                    ),
+                };
+                if let Err(e) = app.mailer.send(email).await {
+                    tracing::error!("Failed to send sign-in link: {:?}", e);
+                }
+            }
+            Ok(None) => tracing::warn!("Too many sign-in links requested for {}", user.email),
+            Err(e) => tracing::error!("Failed to create sign-in link: {:?}", e),
+        }
+    }
+
+    // As with password resets, the answer doesn't tell whether the account
+    // exists.
+    response
+        .content(MagicLinkPageData {
+            form,
+            errors: None,
+            next_url: next,
+            submitted: true,
+        })
+        .add_success_message("If an account exists for this address, a sign-in link is on its way")
+        .into_response()
+}
+
+pub async fn get_magic_link_login(
+    State(app): State<AppState>,
+    mut auth_session: AuthSession,
+    session: Session,
+    audit: AuditContext,
+    NextUrl(next): NextUrl,
+    Path(token): Path<String>,
+) -> Response {
+    let user = match auth_session
+        .authenticate(Credentials::MagicLink { token })
+        .await
+    {
+        Ok(Some(user)) => user,
+        Ok(None) => return magic_link_error("This sign-in link is invalid or has expired"),
+        Err(axum_login::Error::Backend(BackendError::EmailNotVerified)) => {
+            return magic_link_error("Please verify your email address before logging in")
+        }
+        Err(axum_login::Error::Backend(BackendError::AccountDisabled)) => {
+            return magic_link_error("This account has been disabled")
+        }
+        Err(axum_login::Error::Backend(BackendError::PasswordResetRequired)) => {
+            return magic_link_error("You need to choose a new password, use the reset link we emailed you or request a new one")
+        }
+        Err(e) => {
+            tracing::error!("Failed to authenticate user: {:?}", e);
+            return magic_link_error(
+                "Internal Error: Failed to authenticate user, try again later",
+            );
+        }
+    };
+
+    if user.has_two_factor() {
+        if let Err(e) = start_pending_login(&session, user.id(), next).await {
+            tracing::error!("Failed to start two-factor login: {:?}", e);
+            return magic_link_error("Internal Error: Failed to login user, try again later");
+        }
+
+        return Redirect::to("/login/2fa").into_response();
+    }
+
+    if auth_session.login(&user).await.is_err() {
+        tracing::error!("Failed to login user: {:?}", user);
+        return magic_link_error("Internal Error: Failed to login user, try again later");
+    }
+
+    let event = AuditEvent::new(Action::Login)
+        .by_user(user.id())
+        .details("magic_link");
+    audit.record(&app.database_connection, event).await;
+
+    Redirect::to(next.as_deref().unwrap_or("/")).into_response()
+}
@@ -0,0 +1,620 @@
+use std::sync::Arc;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::{rngs::OsRng, RngCore};
+use ring::signature::{
+    RsaPublicKeyComponents, UnparsedPublicKey, ECDSA_P256_SHA256_FIXED, RSA_PKCS1_2048_8192_SHA256,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tokio::sync::OnceCell;
+use tower_sessions::cookie::time::OffsetDateTime;
+use url::Url;
+
+use crate::config::{Config, OidcProviderConfig};
+
+/// Tolerance for clock differences with the provider when checking token times.
+const CLOCK_SKEW_SECONDS: i64 = 60;
+
+#[derive(Error, Debug)]
+pub enum OidcError {
+    #[error("Request to the provider failed")]
+    Http(#[from] reqwest::Error),
+    #[error("Invalid provider URL")]
+    Url(#[from] url::ParseError),
+    #[error("The provider returned an error: {0}")]
+    Provider(String),
+    #[error("Invalid ID token: {0}")]
+    InvalidToken(&'static str),
+}
+
+/// The subset of the provider metadata we need, from the discovery document.
+#[derive(Deserialize, Debug)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JwtHeader {
+    alg: String,
+    kid: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Jwk {
+    kty: String,
+    kid: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+    crv: Option<String>,
+    x: Option<String>,
+    y: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Deserialize)]
+struct IdTokenClaims {
+    iss: String,
+    sub: String,
+    aud: Value,
+    exp: i64,
+    nonce: Option<String>,
+    email: Option<String>,
+    email_verified: Option<Value>,
+}
+
+/// The identity asserted by a validated ID token.
+#[derive(Debug, Clone)]
+pub struct VerifiedIdentity {
+    pub subject: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+}
+
+/// Values tied to a single authorization request, which have to be kept by
+/// the caller until the provider redirects back.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuthorizationRequest {
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+}
+
+fn random_string() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+impl AuthorizationRequest {
+    pub fn generate() -> Self {
+        Self {
+            state: random_string(),
+            nonce: random_string(),
+            code_verifier: random_string(),
+        }
+    }
+
+    /// Whether the `state` the provider redirected back with is the one of
+    /// this request, which ties the callback to the browser that started it.
+    pub fn matches_state(&self, state: Option<&str>) -> bool {
+        state == Some(self.state.as_str())
+    }
+
+    fn code_challenge(&self) -> String {
+        URL_SAFE_NO_PAD.encode(Sha256::digest(self.code_verifier.as_bytes()))
+    }
+}
+
+fn decode(value: &str, field: &'static str) -> Result<Vec<u8>, OidcError> {
+    URL_SAFE_NO_PAD
+        .decode(value)
+        .map_err(|_| OidcError::InvalidToken(field))
+}
+
+pub struct OidcProvider {
+    config: OidcProviderConfig,
+    redirect_uri: String,
+    http: reqwest::Client,
+    metadata: OnceCell<ProviderMetadata>,
+}
+
+impl OidcProvider {
+    fn new(config: OidcProviderConfig, redirect_uri: String, http: reqwest::Client) -> Self {
+        Self {
+            config,
+            redirect_uri,
+            http,
+            metadata: OnceCell::new(),
+        }
+    }
+
+    pub fn slug(&self) -> &str {
+        &self.config.slug
+    }
+
+    pub fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    async fn metadata(&self) -> Result<&ProviderMetadata, OidcError> {
+        self.metadata
+            .get_or_try_init(|| async {
+                let url = format!("{}/.well-known/openid-configuration", self.config.issuer);
+                let metadata: ProviderMetadata = self
+                    .http
+                    .get(url)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+
+                if metadata.issuer.trim_end_matches('/') != self.config.issuer {
+                    return Err(OidcError::Provider(
+                        "discovery document is for another issuer".to_string(),
+                    ));
+                }
+
+                Ok(metadata)
+            })
+            .await
+    }
+
+    /// Builds the URL the user is sent to, using the authorization code flow
+    /// with PKCE.
+    pub async fn authorization_url(
+        &self,
+        request: &AuthorizationRequest,
+    ) -> Result<String, OidcError> {
+        let metadata = self.metadata().await?;
+        let mut url = Url::parse(&metadata.authorization_endpoint)?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("redirect_uri", &self.redirect_uri)
+            .append_pair("scope", &self.config.scopes)
+            .append_pair("state", &request.state)
+            .append_pair("nonce", &request.nonce)
+            .append_pair("code_challenge", &request.code_challenge())
+            .append_pair("code_challenge_method", "S256");
+
+        Ok(url.into())
+    }
+
+    /// Exchanges the authorization code for tokens and validates the ID token.
+    pub async fn exchange_code(
+        &self,
+        code: &str,
+        request: &AuthorizationRequest,
+    ) -> Result<VerifiedIdentity, OidcError> {
+        let metadata = self.metadata().await?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.redirect_uri.as_str()),
+            ("client_id", self.config.client_id.as_str()),
+            ("code_verifier", request.code_verifier.as_str()),
+        ];
+        if let Some(client_secret) = &self.config.client_secret {
+            form.push(("client_secret", client_secret));
+        }
+
+        let response: TokenResponse = self
+            .http
+            .post(&metadata.token_endpoint)
+            .form(&form)
+            .send()
+            .await?
+            .json()
+            .await?;
+        if let Some(error) = response.error {
+            return Err(OidcError::Provider(
+                response.error_description.unwrap_or(error),
+            ));
+        }
+        let id_token = response
+            .id_token
+            .ok_or(OidcError::Provider("no ID token in response".to_string()))?;
+
+        self.validate_id_token(&id_token, &request.nonce).await
+    }
+
+    async fn validate_id_token(
+        &self,
+        id_token: &str,
+        nonce: &str,
+    ) -> Result<VerifiedIdentity, OidcError> {
+        let metadata = self.metadata().await?;
+        let Some((signed_data, signature)) = id_token.rsplit_once('.') else {
+            return Err(OidcError::InvalidToken("not a JWT"));
+        };
+        let Some((header, payload)) = signed_data.split_once('.') else {
+            return Err(OidcError::InvalidToken("not a JWT"));
+        };
+
+        let header: JwtHeader = serde_json::from_slice(&decode(header, "header")?)
+            .map_err(|_| OidcError::InvalidToken("header"))?;
+        let signature = decode(signature, "signature")?;
+
+        let jwks: JwkSet = self
+            .http
+            .get(&metadata.jwks_uri)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        verify_signature(&header, &jwks, signed_data.as_bytes(), &signature)?;
+
+        let claims: IdTokenClaims = serde_json::from_slice(&decode(payload, "payload")?)
+            .map_err(|_| OidcError::InvalidToken("payload"))?;
+        if claims.iss.trim_end_matches('/') != self.config.issuer {
+            return Err(OidcError::InvalidToken("issuer mismatch"));
+        }
+        let audience_matches = match &claims.aud {
+            Value::String(audience) => *audience == self.config.client_id,
+            Value::Array(audiences) => audiences
+                .iter()
+                .any(|audience| audience.as_str() == Some(&self.config.client_id)),
+            _ => false,
+        };
+        if !audience_matches {
+            return Err(OidcError::InvalidToken("audience mismatch"));
+        }
+        if claims.exp + CLOCK_SKEW_SECONDS < OffsetDateTime::now_utc().unix_timestamp() {
+            return Err(OidcError::InvalidToken("expired"));
+        }
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(OidcError::InvalidToken("nonce mismatch"));
+        }
+
+        // Some providers send the flag as a string.
+        let email_verified = match claims.email_verified {
+            Some(Value::Bool(verified)) => verified,
+            Some(Value::String(verified)) => verified == "true",
+            _ => false,
+        };
+
+        Ok(VerifiedIdentity {
+            subject: claims.sub,
+            email: claims.email,
+            email_verified,
+        })
+    }
+}
+
+fn verify_signature(
+    header: &JwtHeader,
+    jwks: &JwkSet,
+    signed_data: &[u8],
+    signature: &[u8],
+) -> Result<(), OidcError> {
+    let kty = match header.alg.as_str() {
+        "RS256" => "RSA",
+        "ES256" => "EC",
+        _ => return Err(OidcError::InvalidToken("unsupported algorithm")),
+    };
+    let key = jwks
+        .keys
+        .iter()
+        .filter(|key| key.kty == kty)
+        .find(|key| header.kid.is_none() || key.kid == header.kid)
+        .ok_or(OidcError::InvalidToken("unknown signing key"))?;
+
+    let verified = match kty {
+        "RSA" => {
+            let (Some(n), Some(e)) = (&key.n, &key.e) else {
+                return Err(OidcError::InvalidToken("incomplete RSA key"));
+            };
+            RsaPublicKeyComponents {
+                n: decode(n, "RSA modulus")?,
+                e: decode(e, "RSA exponent")?,
+            }
+            .verify(&RSA_PKCS1_2048_8192_SHA256, signed_data, signature)
+            .is_ok()
+        }
+        _ => {
+            let (Some("P-256"), Some(x), Some(y)) = (key.crv.as_deref(), &key.x, &key.y) else {
+                return Err(OidcError::InvalidToken("unsupported EC key"));
+            };
+            let mut point = vec![0x04];
+            point.extend(decode(x, "EC x coordinate")?);
+            point.extend(decode(y, "EC y coordinate")?);
+            UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, point)
+                .verify(signed_data, signature)
+                .is_ok()
+        }
+    };
+
+    if !verified {
+        return Err(OidcError::InvalidToken("bad signature"));
+    }
+
+    Ok(())
+}
+
+/// The configured providers, in the order they were listed.
+#[derive(Clone, Default)]
+pub struct OidcProviders(Arc<Vec<OidcProvider>>);
+
+impl OidcProviders {
+    pub fn from_config(config: &Config) -> Self {
+        let http = reqwest::Client::new();
+        let providers = config
+            .oidc_providers
+            .iter()
+            .map(|provider| {
+                let redirect_uri = config.url(&format!("/auth/oidc/{}/callback", provider.slug));
+                OidcProvider::new(provider.clone(), redirect_uri, http.clone())
+            })
+            .collect();
+
+        Self(Arc::new(providers))
+    }
+
+    pub fn get(&self, slug: &str) -> Option<&OidcProvider> {
+        self.0.iter().find(|provider| provider.slug() == slug)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &OidcProvider> {
+        self.0.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use axum::{
+        extract::State,
+        routing::{get, post},
+        Form, Json, Router,
+    };
+    use ring::{
+        rand::SystemRandom,
+        signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+    };
+    use serde_json::json;
+
+    use super::*;
+
+    const CLIENT_ID: &str = "rust-web";
+
+    /// A provider's ES256 key, signing ID tokens.
+    struct SigningKey {
+        key_pair: EcdsaKeyPair,
+        rng: SystemRandom,
+    }
+
+    impl SigningKey {
+        fn generate() -> Self {
+            let rng = SystemRandom::new();
+            let pkcs8 =
+                EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+            let key_pair =
+                EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng)
+                    .unwrap();
+
+            Self { key_pair, rng }
+        }
+
+        fn jwk(&self) -> Value {
+            let point = self.key_pair.public_key().as_ref();
+            json!({
+                "kty": "EC",
+                "kid": "test",
+                "crv": "P-256",
+                "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+                "y": URL_SAFE_NO_PAD.encode(&point[33..]),
+            })
+        }
+
+        fn sign(&self, claims: &Value) -> String {
+            let header =
+                URL_SAFE_NO_PAD.encode(json!({ "alg": "ES256", "kid": "test" }).to_string());
+            let payload = URL_SAFE_NO_PAD.encode(claims.to_string());
+            let signed_data = format!("{}.{}", header, payload);
+            let signature = self
+                .key_pair
+                .sign(&self.rng, signed_data.as_bytes())
+                .unwrap();
+
+            format!("{}.{}", signed_data, URL_SAFE_NO_PAD.encode(signature))
+        }
+    }
+
+    #[derive(Clone)]
+    struct MockIdp {
+        issuer: String,
+        jwk: Value,
+        id_token: String,
+    }
+
+    async fn discovery(State(idp): State<MockIdp>) -> Json<Value> {
+        Json(json!({
+            "issuer": idp.issuer,
+            "authorization_endpoint": format!("{}/authorize", idp.issuer),
+            "token_endpoint": format!("{}/token", idp.issuer),
+            "jwks_uri": format!("{}/jwks", idp.issuer),
+        }))
+    }
+
+    async fn token(
+        State(idp): State<MockIdp>,
+        Form(form): Form<HashMap<String, String>>,
+    ) -> Json<Value> {
+        let valid = form.get("grant_type").map(String::as_str) == Some("authorization_code")
+            && form.get("client_id").map(String::as_str) == Some(CLIENT_ID)
+            && form.contains_key("code_verifier");
+        if !valid {
+            return Json(json!({ "error": "invalid_grant" }));
+        }
+
+        Json(json!({ "id_token": idp.id_token, "token_type": "Bearer" }))
+    }
+
+    async fn jwks(State(idp): State<MockIdp>) -> Json<Value> {
+        Json(json!({ "keys": [idp.jwk] }))
+    }
+
+    /// Starts a provider on a local port that publishes `key` and answers
+    /// every code exchange with the ID token built for its issuer.
+    async fn mock_provider(
+        key: &SigningKey,
+        id_token: impl FnOnce(&str) -> String,
+    ) -> OidcProvider {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+        let idp = MockIdp {
+            id_token: id_token(&issuer),
+            issuer: issuer.clone(),
+            jwk: key.jwk(),
+        };
+        let router = Router::new()
+            .route("/.well-known/openid-configuration", get(discovery))
+            .route("/token", post(token))
+            .route("/jwks", get(jwks))
+            .with_state(idp);
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        let config = OidcProviderConfig {
+            slug: "mock".to_string(),
+            name: "Mock".to_string(),
+            issuer,
+            client_id: CLIENT_ID.to_string(),
+            client_secret: None,
+            scopes: "openid email".to_string(),
+        };
+        let http = reqwest::Client::builder().no_proxy().build().unwrap();
+
+        OidcProvider::new(config, "http://localhost:3000/callback".to_string(), http)
+    }
+
+    fn claims(issuer: &str, nonce: &str) -> Value {
+        json!({
+            "iss": issuer,
+            "sub": "subject",
+            "aud": CLIENT_ID,
+            "exp": OffsetDateTime::now_utc().unix_timestamp() + 300,
+            "nonce": nonce,
+            "email": "user@example.com",
+            "email_verified": true,
+        })
+    }
+
+    async fn exchange(
+        key: &SigningKey,
+        claims: impl FnOnce(&str, &str) -> Value,
+    ) -> Result<VerifiedIdentity, OidcError> {
+        let request = AuthorizationRequest::generate();
+        let nonce = request.nonce.clone();
+        let provider = mock_provider(key, |issuer| key.sign(&claims(issuer, &nonce))).await;
+
+        provider.exchange_code("code", &request).await
+    }
+
+    #[tokio::test]
+    async fn exchanges_code_for_identity() {
+        let key = SigningKey::generate();
+
+        let identity = exchange(&key, claims).await.unwrap();
+        assert_eq!(identity.subject, "subject");
+        assert_eq!(identity.email.as_deref(), Some("user@example.com"));
+        assert!(identity.email_verified);
+    }
+
+    #[test]
+    fn rejects_other_state() {
+        let request = AuthorizationRequest::generate();
+
+        assert!(request.matches_state(Some(&request.state)));
+        assert!(!request.matches_state(Some("other")));
+        assert!(!request.matches_state(None));
+    }
+
+    #[tokio::test]
+    async fn rejects_other_nonce() {
+        let key = SigningKey::generate();
+
+        let result = exchange(&key, |issuer, _| claims(issuer, "other")).await;
+        assert!(matches!(
+            result,
+            Err(OidcError::InvalidToken("nonce mismatch"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_token_signed_by_another_key() {
+        let key = SigningKey::generate();
+        let other_key = SigningKey::generate();
+        let request = AuthorizationRequest::generate();
+        let provider = mock_provider(&key, |issuer| {
+            other_key.sign(&claims(issuer, &request.nonce))
+        })
+        .await;
+
+        let result = provider.exchange_code("code", &request).await;
+        assert!(matches!(
+            result,
+            Err(OidcError::InvalidToken("bad signature"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_other_issuer() {
+        let key = SigningKey::generate();
+
+        let result = exchange(&key, |_, nonce| claims("https://evil.example", nonce)).await;
+        assert!(matches!(
+            result,
+            Err(OidcError::InvalidToken("issuer mismatch"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_other_audience() {
+        let key = SigningKey::generate();
+
+        let result = exchange(&key, |issuer, nonce| {
+            let mut claims = claims(issuer, nonce);
+            claims["aud"] = json!(["other-client"]);
+            claims
+        })
+        .await;
+        assert!(matches!(
+            result,
+            Err(OidcError::InvalidToken("audience mismatch"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_expired_token() {
+        let key = SigningKey::generate();
+
+        let result = exchange(&key, |issuer, nonce| {
+            let mut claims = claims(issuer, nonce);
+            claims["exp"] = json!(OffsetDateTime::now_utc().unix_timestamp() - 3600);
+            claims
+        })
+        .await;
+        assert!(matches!(result, Err(OidcError::InvalidToken("expired"))));
+    }
+}
@@ -0,0 +1,376 @@
+use super::{
+    db_user, db_user_identity,
+    email_verification_page::send_verification_email,
+    layer::{AuthSession, BackendError, Credentials},
+    oidc::{AuthorizationRequest, OidcProvider, VerifiedIdentity},
+    redirect::NextUrl,
+    two_factor_page::start_pending_login,
+};
+use crate::{
+    app::AppState,
+    audit::{action::Action, context::AuditContext, event::AuditEvent},
+    config::RegistrationMode,
+    error::AppError,
+    layout::template_response::TemplateResponse,
+};
+use axum::{
+    extract::{Path, Query, State},
+    response::{IntoResponse, Redirect, Response},
+};
+use axum_login::AuthUser;
+use axum_messages::Messages;
+use serde::{Deserialize, Serialize};
+use tower_sessions::{
+    cookie::time::{Duration, OffsetDateTime},
+    Session,
+};
+
+const PENDING_AUTHORIZATION_KEY: &str = "auth.oidc_pending_authorization";
+const PENDING_AUTHORIZATION_LIFETIME: Duration = Duration::minutes(10);
+
+/// What to do with the identity once the provider redirects back.
+#[derive(Serialize, Deserialize)]
+enum Intent {
+    Login { next: Option<String> },
+    Link { user_id: i32 },
+}
+
+#[derive(Serialize, Deserialize)]
+struct PendingAuthorization {
+    provider: String,
+    request: AuthorizationRequest,
+    intent: Intent,
+    expires_at: i64,
+}
+
+#[derive(Serialize)]
+pub struct OidcErrorPage {
+    message: String,
+    email_not_verified: bool,
+}
+
+fn oidc_error(message: impl Into<String>) -> Response {
+    TemplateResponse::new("auth/oidc_error")
+        .content(OidcErrorPage {
+            message: message.into(),
+            email_not_verified: false,
+        })
+        .into_response()
+}
+
+async fn start_authorization(
+    app: &AppState,
+    session: &Session,
+    provider: &str,
+    intent: Intent,
+) -> Response {
+    let Some(provider) = app.oidc_providers.get(provider) else {
+        return AppError::NotFound.into_response();
+    };
+
+    let request = AuthorizationRequest::generate();
+    let url = match provider.authorization_url(&request).await {
+        Ok(url) => url,
+        Err(e) => {
+            tracing::error!("Failed to reach OIDC provider {}: {:?}", provider.slug(), e);
+            return oidc_error(format!(
+                "{} is not available right now, try again later",
+                provider.name()
+            ));
+        }
+    };
+
+    let pending_authorization = PendingAuthorization {
+        provider: provider.slug().to_string(),
+        request,
+        intent,
+        expires_at: (OffsetDateTime::now_utc() + PENDING_AUTHORIZATION_LIFETIME).unix_timestamp(),
+    };
+    if let Err(e) = session
+        .insert(PENDING_AUTHORIZATION_KEY, pending_authorization)
+        .await
+    {
+        tracing::error!("Failed to store OIDC authorization request: {:?}", e);
+        return oidc_error("Internal Error: Failed to start login, try again later");
+    }
+
+    Redirect::to(&url).into_response()
+}
+
+pub async fn get_oidc_login(
+    State(app): State<AppState>,
+    session: Session,
+    Path(provider): Path<String>,
+    NextUrl(next): NextUrl,
+) -> Response {
+    start_authorization(&app, &session, &provider, Intent::Login { next }).await
+}
+
+pub async fn get_oidc_link(
+    State(app): State<AppState>,
+    auth_session: AuthSession,
+    session: Session,
+    Path(provider): Path<String>,
+) -> Response {
+    let Some(user) = auth_session.user else {
+        return Redirect::to("/login").into_response();
+    };
+
+    start_authorization(
+        &app,
+        &session,
+        &provider,
+        Intent::Link { user_id: user.id() },
+    )
+    .await
+}
+
+#[derive(Deserialize)]
+pub struct CallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+pub async fn get_oidc_callback(
+    State(app): State<AppState>,
+    auth_session: AuthSession,
+    session: Session,
+    audit: AuditContext,
+    messages: Messages,
+    Path(provider): Path<String>,
+    Query(query): Query<CallbackQuery>,
+) -> Response {
+    let Some(provider) = app.oidc_providers.get(&provider) else {
+        return AppError::NotFound.into_response();
+    };
+
+    let pending_authorization = match session
+        .remove::<PendingAuthorization>(PENDING_AUTHORIZATION_KEY)
+        .await
+    {
+        Ok(Some(pending)) if pending.provider == provider.slug() => pending,
+        _ => return oidc_error("This login attempt is not valid anymore, try again"),
+    };
+    if pending_authorization.expires_at < OffsetDateTime::now_utc().unix_timestamp() {
+        return oidc_error("This login attempt has expired, try again");
+    }
+
+    if let Some(error) = query.error {
+        return oidc_error(format!(
+            "{} did not complete the login: {}",
+            provider.name(),
+            query.error_description.unwrap_or(error)
+        ));
+    }
+    if !pending_authorization
+        .request
+        .matches_state(query.state.as_deref())
+    {
+        return oidc_error("This login attempt is not valid anymore, try again");
+    }
+    let Some(code) = query.code else {
+        return oidc_error("This login attempt is not valid anymore, try again");
+    };
+
+    let identity = match provider
+        .exchange_code(&code, &pending_authorization.request)
+        .await
+    {
+        Ok(identity) => identity,
+        Err(e) => {
+            tracing::warn!(
+                "Failed to verify identity from {}: {:?}",
+                provider.slug(),
+                e
+            );
+            return oidc_error(format!(
+                "Failed to verify your identity with {}",
+                provider.name()
+            ));
+        }
+    };
+
+    match pending_authorization.intent {
+        Intent::Login { next } => {
+            login_with_identity(
+                &app,
+                auth_session,
+                &session,
+                &audit,
+                provider,
+                identity,
+                next,
+            )
+            .await
+        }
+        Intent::Link { user_id } => {
+            link_identity(&app, auth_session, messages, provider, identity, user_id).await
+        }
+    }
+}
+
+async fn login_with_identity(
+    app: &AppState,
+    mut auth_session: AuthSession,
+    session: &Session,
+    audit: &AuditContext,
+    provider: &OidcProvider,
+    identity: VerifiedIdentity,
+    next: Option<String>,
+) -> Response {
+    let db = &app.database_connection;
+
+    match db_user_identity::get_identity(db, provider.slug(), &identity.subject).await {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            let Some(email) = &identity.email else {
+                return oidc_error(format!(
+                    "{} did not share your email address with us",
+                    provider.name()
+                ));
+            };
+            if app.config.registration_mode != RegistrationMode::Open {
+                return oidc_error(format!(
+                    "There is no account linked to your {} account, and signing up is not open",
+                    provider.name()
+                ));
+            }
+            // Linking to an existing account has to be done by its owner from
+            // the profile page, otherwise anyone controlling an account with
+            // the same email at the provider could take it over.
+            if db_user::user_exists(db, email).await {
+                return oidc_error(format!(
+                    "An account for {} already exists. Log in with your password and link your {} account from your profile.",
+                    email,
+                    provider.name()
+                ));
+            }
+            match db_user_identity::create_user_with_identity(
+                db,
+                email,
+                identity.email_verified,
+                provider.slug(),
+                &identity.subject,
+            )
+            .await
+            {
+                Ok(user) => {
+                    let event = AuditEvent::new(Action::Register)
+                        .by_user(user.id)
+                        .details(provider.slug());
+                    audit.record(db, event).await;
+
+                    if user.email_verified_at.is_none() {
+                        send_verification_email(app, user.id, &user.email).await;
+                    }
+                }
+                Err(e) => {
+                    tracing::error!("Failed to create user from OIDC identity: {:?}", e);
+                    return oidc_error("Failed to create user");
+                }
+            }
+        }
+        Err(e) => {
+            tracing::error!("Failed to find OIDC identity: {:?}", e);
+            return oidc_error("Internal Error: Failed to login user, try again later");
+        }
+    }
+
+    let user = match auth_session
+        .authenticate(Credentials::Oidc {
+            provider: provider.slug().to_string(),
+            subject: identity.subject,
+        })
+        .await
+    {
+        Ok(Some(user)) => user,
+        Ok(None) => return oidc_error("Internal Error: Failed to login user, try again later"),
+        Err(axum_login::Error::Backend(BackendError::EmailNotVerified)) => {
+            return TemplateResponse::new("auth/oidc_error")
+                .content(OidcErrorPage {
+                    message: "Please verify your email address before logging in".to_string(),
+                    email_not_verified: true,
+                })
+                .into_response()
+        }
+        Err(axum_login::Error::Backend(BackendError::AccountDisabled)) => {
+            return oidc_error("This account has been disabled")
+        }
+        Err(axum_login::Error::Backend(BackendError::PasswordResetRequired)) => {
+            return oidc_error("You need to choose a new password, use the reset link we emailed you or request a new one")
+        }
+        Err(e) => {
+            tracing::error!("Failed to authenticate user: {:?}", e);
+            return oidc_error("Internal Error: Failed to authenticate user, try again later");
+        }
+    };
+
+    if user.has_two_factor() {
+        if let Err(e) = start_pending_login(session, user.id(), next).await {
+            tracing::error!("Failed to start two-factor login: {:?}", e);
+            return oidc_error("Internal Error: Failed to login user, try again later");
+        }
+
+        return Redirect::to("/login/2fa").into_response();
+    }
+
+    if auth_session.login(&user).await.is_err() {
+        tracing::error!("Failed to login user: {:?}", user);
+        return oidc_error("Internal Error: Failed to login user, try again later");
+    }
+
+    let event = AuditEvent::new(Action::Login)
+        .by_user(user.id())
+        .details(provider.slug());
+    audit.record(db, event).await;
+
+    Redirect::to(next.as_deref().unwrap_or("/")).into_response()
+}
+
+async fn link_identity(
+    app: &AppState,
+    auth_session: AuthSession,
+    messages: Messages,
+    provider: &OidcProvider,
+    identity: VerifiedIdentity,
+    user_id: i32,
+) -> Response {
+    // The user who started linking must still be the one logged in.
+    if auth_session.user.map(|user| user.id()) != Some(user_id) {
+        return Redirect::to("/login").into_response();
+    }
+    let db = &app.database_connection;
+
+    match db_user_identity::get_identity(db, provider.slug(), &identity.subject).await {
+        Ok(Some(existing)) if existing.user_id == user_id => {}
+        Ok(Some(_)) => {
+            return oidc_error(format!(
+                "This {} account is already linked to another user",
+                provider.name()
+            ))
+        }
+        Ok(None) => {
+            if let Err(e) = db_user_identity::create_identity(
+                db,
+                user_id,
+                provider.slug(),
+                &identity.subject,
+                identity.email,
+            )
+            .await
+            {
+                tracing::error!("Failed to link OIDC identity: {:?}", e);
+                return oidc_error(format!("Failed to link your {} account", provider.name()));
+            }
+        }
+        Err(e) => {
+            tracing::error!("Failed to find OIDC identity: {:?}", e);
+            return oidc_error(format!("Failed to link your {} account", provider.name()));
+        }
+    }
+
+    messages.success(format!("Your {} account is linked", provider.name()));
+    Redirect::to("/user/profile").into_response()
+}
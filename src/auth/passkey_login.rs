@@ -0,0 +1,105 @@
+use super::{
+    layer::{AuthSession, BackendError, Credentials},
+    redirect::NextUrl,
+    webauthn::{self, AssertionResponse},
+};
+use crate::{
+    app::AppState,
+    audit::{action::Action, context::AuditContext, event::AuditEvent},
+};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_login::AuthUser;
+use serde_json::json;
+use tower_sessions::Session;
+
+const LOGIN_CHALLENGE_KEY: &str = "auth.passkey_login_challenge";
+
+fn passkey_error(status: StatusCode, message: &str) -> Response {
+    (status, Json(json!({ "error": message }))).into_response()
+}
+
+pub async fn post_passkey_login_options(State(app): State<AppState>, session: Session) -> Response {
+    match webauthn::start_ceremony(&session, LOGIN_CHALLENGE_KEY).await {
+        Ok(challenge) => Json(json!({
+            "publicKey": app.webauthn.authentication_options(&challenge),
+        }))
+        .into_response(),
+        Err(e) => {
+            tracing::error!("Failed to start passkey login: {:?}", e);
+            passkey_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Internal Error: Failed to start passkey login, try again later",
+            )
+        }
+    }
+}
+
+pub async fn post_passkey_login(
+    State(app): State<AppState>,
+    mut auth_session: AuthSession,
+    session: Session,
+    audit: AuditContext,
+    NextUrl(next): NextUrl,
+    Json(response): Json<AssertionResponse>,
+) -> Response {
+    let Some(challenge) = webauthn::finish_ceremony(&session, LOGIN_CHALLENGE_KEY).await else {
+        return passkey_error(
+            StatusCode::BAD_REQUEST,
+            "The passkey request has expired, try again",
+        );
+    };
+
+    let user = match auth_session
+        .authenticate(Credentials::WebAuthn {
+            challenge,
+            response,
+        })
+        .await
+    {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            return passkey_error(StatusCode::UNAUTHORIZED, "This passkey is not recognized")
+        }
+        Err(axum_login::Error::Backend(BackendError::EmailNotVerified)) => {
+            return passkey_error(
+                StatusCode::FORBIDDEN,
+                "Please verify your email address before logging in",
+            )
+        }
+        Err(axum_login::Error::Backend(BackendError::AccountDisabled)) => {
+            return passkey_error(StatusCode::FORBIDDEN, "This account has been disabled")
+        }
+        Err(axum_login::Error::Backend(BackendError::PasswordResetRequired)) => {
+            return passkey_error(StatusCode::FORBIDDEN, "You need to choose a new password, use the reset link we emailed you or request a new one")
+        }
+        Err(e) => {
+            tracing::error!("Failed to authenticate user: {:?}", e);
+            return passkey_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Internal Error: Failed to authenticate user, try again later",
+            );
+        }
+    };
+
+    // Unlike the other ways of logging in, there is no `/login/2fa` step: the
+    // assertion proves both the key and a PIN or biometrics check.
+    if auth_session.login(&user).await.is_err() {
+        tracing::error!("Failed to login user: {:?}", user);
+        return passkey_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Internal Error: Failed to login user, try again later",
+        );
+    }
+
+    let event = AuditEvent::new(Action::Login)
+        .by_user(user.id())
+        .details("passkey");
+    audit.record(&app.database_connection, event).await;
+
+    Json(json!({ "redirect": next.unwrap_or_else(|| "/".to_string()) })).into_response()
+}
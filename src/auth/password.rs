@@ -16,9 +16,17 @@ pub fn hash(password: &str) -> Result<String, Error> {
     Ok(password_hash)
 }
 
+/// A stored hash that can't be parsed never matches, so a corrupted row
+/// only locks its user out.
 pub fn verify(password: &str, hash: &str) -> bool {
     let argon2 = Argon2::default();
-    let password_hash = PasswordHash::new(hash).unwrap();
+    let password_hash = match PasswordHash::new(hash) {
+        Ok(password_hash) => password_hash,
+        Err(e) => {
+            tracing::error!("Failed to parse password hash: {:?}", e);
+            return false;
+        }
+    };
     argon2
         .verify_password(password.as_bytes(), &password_hash)
         .is_ok()
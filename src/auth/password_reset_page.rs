@@ -0,0 +1,166 @@
+use super::{db_password_reset, db_user};
+use crate::{
+    app::AppState, error::AppError, layout::template_response::TemplateResponse, mailer::Email,
+};
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+    Form,
+};
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationErrors};
+
+#[derive(Debug, Deserialize, Serialize, Default, Validate)]
+pub struct ForgotPasswordForm {
+    #[validate(email(message = "Invalid email address"))]
+    email: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ForgotPasswordPageData {
+    form: ForgotPasswordForm,
+    errors: Option<ValidationErrors>,
+    submitted: bool,
+}
+
+pub async fn get_forgot_password() -> Response {
+    TemplateResponse::new("auth/forgot_password")
+        .content(ForgotPasswordPageData::default())
+        .into_response()
+}
+
+pub async fn post_forgot_password(
+    State(app): State<AppState>,
+    Form(form): Form<ForgotPasswordForm>,
+) -> Response {
+    let response = TemplateResponse::new("auth/forgot_password");
+    if let Err(errors) = form.validate() {
+        return response
+            .content(ForgotPasswordPageData {
+                form,
+                errors: Some(errors),
+                submitted: false,
+            })
+            .into_response();
+    }
+
+    if let Some(user) = db_user::get_user_by_email(&app.database_connection, &form.email).await {
+        match db_password_reset::create_token(&app.database_connection, user.id).await {
+            Ok(token) => {
+                let email = Email {
+                    to: user.email,
+                    subject: "Reset your password".to_string(),
+                    body: format!(
+                        "Someone asked to reset the password of your account.\n\n\
+                        To choose a new password, open the link below within the next hour:\n\n{}\n\n\
+                        If it wasn't you, you can safely ignore this email.",
+                        app.config.url(&format!("/reset-password/{}", token))
+                    ),
+                };
+                if let Err(e) = app.mailer.send(email).await {
+                    tracing::error!("Failed to send password reset email: {:?}", e);
+                }
+            }
+            Err(e) => tracing::error!("Failed to create password reset token: {:?}", e),
+        }
+    }
+
+    // The same answer is given whether or not the account exists, so this
+    // page can't be used to find out which addresses are registered.
+    response
+        .content(ForgotPasswordPageData {
+            form,
+            errors: None,
+            submitted: true,
+        })
+        .add_success_message("If an account exists for this address, a reset link is on its way")
+        .into_response()
+}
+
+#[derive(Debug, Deserialize, Default, Validate)]
+pub struct ResetPasswordForm {
+    #[validate(length(min = 8, message = "Password must be at least 8 characters long"))]
+    password: String,
+    #[validate(must_match(other = "password", message = "Passwords do not match"))]
+    confirm_password: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ResetPasswordPageData {
+    token: String,
+    errors: Option<ValidationErrors>,
+    invalid_token: bool,
+    completed: bool,
+}
+
+pub async fn get_reset_password(
+    State(app): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<Response, AppError> {
+    let response = TemplateResponse::new("auth/reset_password");
+    let reset_token = db_password_reset::find_valid_token(&app.database_connection, &token)
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to find password reset token: {:?}", e)))?;
+    if reset_token.is_none() {
+        return Ok(response
+            .content(ResetPasswordPageData {
+                invalid_token: true,
+                ..Default::default()
+            })
+            .add_error_message("This reset link is invalid or has expired")
+            .into_response());
+    }
+
+    Ok(response
+        .content(ResetPasswordPageData {
+            token,
+            ..Default::default()
+        })
+        .into_response())
+}
+
+pub async fn post_reset_password(
+    State(app): State<AppState>,
+    Path(token): Path<String>,
+    Form(form): Form<ResetPasswordForm>,
+) -> Response {
+    let response = TemplateResponse::new("auth/reset_password");
+    if let Err(errors) = form.validate() {
+        return response
+            .content(ResetPasswordPageData {
+                token,
+                errors: Some(errors),
+                ..Default::default()
+            })
+            .into_response();
+    }
+
+    match db_password_reset::reset_password(&app.database_connection, &token, &form.password).await
+    {
+        Ok(()) => response
+            .content(ResetPasswordPageData {
+                completed: true,
+                ..Default::default()
+            })
+            .add_success_message("Your password has been changed")
+            .into_response(),
+        Err(db_password_reset::ResetPasswordError::InvalidToken) => response
+            .content(ResetPasswordPageData {
+                invalid_token: true,
+                ..Default::default()
+            })
+            .add_error_message("This reset link is invalid or has expired")
+            .into_response(),
+        Err(e) => {
+            tracing::error!("Failed to reset password: {:?}", e);
+
+            response
+                .content(ResetPasswordPageData {
+                    token,
+                    ..Default::default()
+                })
+                .add_error_message("Failed to reset password, try again later")
+                .into_response()
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use std::convert::Infallible;
+
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRequestParts, Query},
+    http::{request::Parts, HeaderMap},
+    response::{IntoResponse, Redirect, Response},
+};
+use serde::Deserialize;
+use serde_json::json;
+use url::Url;
+
+use crate::{app::AppState, config::Config, layout::template_response::TemplateResponse};
+
+/// Whether the user can be sent to `target` after completing a flow. Only
+/// paths on this site are accepted, plus absolute URLs on one of the hosts
+/// listed in `REDIRECT_ALLOWED_HOSTS`.
+pub fn is_safe_redirect(config: &Config, target: &str) -> bool {
+    // Browsers read backslashes as slashes and strip whitespace and some
+    // control characters, which would turn `/\evil.com` into another origin.
+    if target
+        .chars()
+        .any(|c| c.is_control() || c.is_whitespace() || c == '\\')
+    {
+        return false;
+    }
+    if target.starts_with('/') {
+        return !target.starts_with("//");
+    }
+
+    match Url::parse(target) {
+        Ok(url) => {
+            matches!(url.scheme(), "http" | "https")
+                && url.host_str().is_some_and(|host| {
+                    config
+                        .redirect_allowed_hosts
+                        .iter()
+                        .any(|allowed| allowed.eq_ignore_ascii_case(host))
+                })
+        }
+        Err(_) => false,
+    }
+}
+
+/// Sends the user on to `next` after a login form was posted. Boosted forms
+/// follow redirects inside the request, which can't leave this site, so htmx
+/// is told to load the other hosts as a full page instead.
+pub fn redirect_to_next(headers: &HeaderMap, next: &str) -> Response {
+    if headers.contains_key("hx-request") && !next.starts_with('/') {
+        return TemplateResponse::new("auth/redirect")
+            .hx_redirect(next)
+            .content(json!({ "url": next }))
+            .into_response();
+    }
+
+    Redirect::to(next).into_response()
+}
+
+#[derive(Deserialize)]
+struct NextQuery {
+    next: Option<String>,
+}
+
+/// The `next` query parameter of the request, dropped unless it passes
+/// `is_safe_redirect`, so handlers can redirect to it as is.
+#[derive(Clone, Debug, Default)]
+pub struct NextUrl(pub Option<String>);
+
+#[async_trait]
+impl FromRequestParts<AppState> for NextUrl {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        app: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let next = Query::<NextQuery>::try_from_uri(&parts.uri)
+            .ok()
+            .and_then(|Query(query)| query.next)
+            .filter(|next| {
+                let safe = is_safe_redirect(&app.config, next);
+                if !safe {
+                    tracing::warn!("Ignored unsafe redirect target: {:?}", next);
+                }
+                safe
+            });
+
+        Ok(NextUrl(next))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RegistrationMode;
+
+    fn config() -> Config {
+        Config {
+            base_url: "http://localhost:3000".to_string(),
+            require_verified_email: false,
+            registration_mode: RegistrationMode::Open,
+            trust_forwarded_for: false,
+            account_deletion_grace_days: 0,
+            redirect_allowed_hosts: vec!["docs.example.com".to_string()],
+            oidc_providers: vec![],
+        }
+    }
+
+    #[test]
+    fn accepts_paths_on_this_site() {
+        assert!(is_safe_redirect(&config(), "/user/profile?tab=security"));
+        assert!(is_safe_redirect(&config(), "/"));
+    }
+
+    #[test]
+    fn rejects_protocol_relative_urls() {
+        assert!(!is_safe_redirect(&config(), "//evil.com"));
+        assert!(!is_safe_redirect(&config(), "/\\evil.com"));
+        assert!(!is_safe_redirect(&config(), "\\\\evil.com"));
+    }
+
+    #[test]
+    fn rejects_control_characters_and_whitespace() {
+        assert!(!is_safe_redirect(&config(), "/\t/evil.com"));
+        assert!(!is_safe_redirect(&config(), "/\n/evil.com"));
+        assert!(!is_safe_redirect(&config(), "/\u{0}/evil.com"));
+        assert!(!is_safe_redirect(&config(), " //evil.com"));
+        assert!(!is_safe_redirect(&config(), "/ /evil.com"));
+    }
+
+    #[test]
+    fn rejects_other_schemes() {
+        assert!(!is_safe_redirect(&config(), "javascript:alert(1)"));
+        assert!(!is_safe_redirect(
+            &config(),
+            "data:text/html,<script>alert(1)</script>"
+        ));
+        assert!(!is_safe_redirect(&config(), "ftp://docs.example.com/file"));
+    }
+
+    #[test]
+    fn accepts_absolute_urls_only_on_allowed_hosts() {
+        assert!(is_safe_redirect(
+            &config(),
+            "https://docs.example.com/guide"
+        ));
+        assert!(is_safe_redirect(&config(), "http://DOCS.example.com"));
+        assert!(!is_safe_redirect(&config(), "https://evil.com/guide"));
+        assert!(!is_safe_redirect(
+            &config(),
+            "https://docs.example.com.evil.com"
+        ));
+        assert!(!is_safe_redirect(
+            &config(),
+            "https://docs.example.com@evil.com"
+        ));
+    }
+}
@@ -1,21 +1,36 @@
-use super::db_user;
-use crate::{app::AppState, layout::template_response::TemplateResponse};
+use super::{
+    db_invitation::{self, RegisterWithInvitationError},
+    db_user,
+    email_verification_page::send_verification_email,
+};
+use crate::{
+    app::AppState,
+    audit::{action::Action, context::AuditContext, event::AuditEvent},
+    config::RegistrationMode,
+    layout::template_response::TemplateResponse,
+};
 use axum::{
-    extract::State,
-    response::{IntoResponse, Redirect, Response},
+    extract::{Query, State},
+    response::{IntoResponse, Response},
     Form,
 };
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use utoipa::ToSchema;
 use validator::{Validate, ValidationErrors};
 
-#[derive(Debug, Deserialize, Serialize, Default, Validate)]
+#[derive(Debug, Deserialize, Default, Clone, Validate, ToSchema)]
 pub struct RegisterForm {
     #[validate(email(message = "Invalid email address"))]
     email: String,
     #[validate(length(min = 8, message = "Password must be at least 8 characters long"))]
+    #[schema(format = Password, min_length = 8, required = true)]
     password: String,
     #[validate(must_match(other = "password", message = "Passwords do not match"))]
+    #[schema(format = Password, required = true)]
     confirm_password: String,
+    /// Code of the invitation the visitor arrived with.
+    invite: Option<String>,
 }
 
 impl From<RegisterForm> for db_user::CreateUserData {
@@ -23,52 +38,236 @@ impl From<RegisterForm> for db_user::CreateUserData {
         db_user::CreateUserData {
             email: form.email,
             password: form.password,
+            email_verified: false,
+        }
+    }
+}
+
+/// What the page shows back of a submitted registration form, never the
+/// passwords.
+#[derive(Debug, Default, Serialize)]
+pub struct RegisterFormValues {
+    email: String,
+    invite: Option<String>,
+}
+
+impl From<RegisterForm> for RegisterFormValues {
+    fn from(form: RegisterForm) -> Self {
+        RegisterFormValues {
+            email: form.email,
+            invite: form.invite,
         }
     }
 }
 
 #[derive(Debug, Default, Serialize)]
 pub struct RegisterPageData {
-    form: RegisterForm,
+    form: RegisterFormValues,
     errors: Option<ValidationErrors>,
+    registered_email: Option<String>,
+    registration_closed: bool,
+    invitation_required: bool,
 }
 
-pub async fn get_register() -> Response {
+fn registration_closed() -> Response {
     TemplateResponse::new("auth/register")
-        .content(RegisterPageData::default())
+        .content(RegisterPageData {
+            registration_closed: true,
+            ..Default::default()
+        })
         .into_response()
 }
 
-pub async fn post_register(
+#[derive(Deserialize)]
+pub struct InviteQuery {
+    invite: Option<String>,
+}
+
+pub async fn get_register(
     State(app): State<AppState>,
-    Form(form): Form<RegisterForm>,
+    Query(InviteQuery { invite }): Query<InviteQuery>,
 ) -> Response {
+    let mode = app.config.registration_mode;
+    if mode == RegistrationMode::Closed {
+        return registration_closed();
+    }
+
     let response = TemplateResponse::new("auth/register");
-    if let Err(errors) = form.validate() {
+    let Some(code) = invite.filter(|code| !code.is_empty()) else {
         return response
             .content(RegisterPageData {
-                form,
-                errors: Some(errors),
+                invitation_required: mode == RegistrationMode::InviteOnly,
+                ..Default::default()
             })
             .into_response();
+    };
+
+    match db_invitation::find_usable_invitation(&app.database_connection, &code).await {
+        Ok(Some(invitation)) => response
+            .content(RegisterPageData {
+                form: RegisterFormValues {
+                    email: invitation.email.unwrap_or_default(),
+                    invite: Some(code),
+                },
+                ..Default::default()
+            })
+            .into_response(),
+        Ok(None) => response
+            .content(RegisterPageData {
+                invitation_required: mode == RegistrationMode::InviteOnly,
+                ..Default::default()
+            })
+            .add_error_message("This invitation is invalid, has expired or was already used")
+            .into_response(),
+        Err(e) => {
+            tracing::error!("Failed to find invitation: {:?}", e);
+            response
+                .content(RegisterPageData {
+                    invitation_required: mode == RegistrationMode::InviteOnly,
+                    ..Default::default()
+                })
+                .add_error_message("Failed to check the invitation, try again later")
+                .into_response()
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum RegisterError {
+    #[error("Registration is closed")]
+    Closed,
+    #[error("An invitation is required to sign up")]
+    InvitationRequired,
+    #[error("Invalid registration data")]
+    Validation(ValidationErrors),
+    #[error("User already exists")]
+    UserExists,
+    #[error("This invitation is invalid, has expired or was already used")]
+    InvalidInvitation,
+    #[error("This invitation is for another email address")]
+    EmailMismatch,
+    #[error("Failed to create user")]
+    Internal,
+}
+
+/// Signs up a new user according to the registration mode, shared by the
+/// register page and the API.
+pub async fn register(
+    app: &AppState,
+    audit: &AuditContext,
+    form: &RegisterForm,
+) -> Result<db_user::UserModel, RegisterError> {
+    let mode = app.config.registration_mode;
+    if mode == RegistrationMode::Closed {
+        return Err(RegisterError::Closed);
     }
 
+    let invite = form.invite.as_deref().filter(|code| !code.is_empty());
+    if invite.is_none() && mode == RegistrationMode::InviteOnly {
+        return Err(RegisterError::InvitationRequired);
+    }
+
+    form.validate().map_err(RegisterError::Validation)?;
+
     if db_user::user_exists(&app.database_connection, &form.email).await {
-        return response
-            .content(RegisterPageData { form, errors: None })
-            .add_error_message("User already exists")
-            .into_response();
+        return Err(RegisterError::UserExists);
     }
 
-    match db_user::create_user(&app.database_connection, form.into()).await {
-        Ok(_) => Redirect::to("/login?registered=true").into_response(),
+    let result = match invite {
+        Some(code) => {
+            db_invitation::register_with_invitation(
+                &app.database_connection,
+                code,
+                form.clone().into(),
+            )
+            .await
+        }
+        None => db_user::create_user(&app.database_connection, form.clone().into())
+            .await
+            .map_err(RegisterWithInvitationError::from),
+    };
+    let user = match result {
+        Ok(user) => user,
+        Err(RegisterWithInvitationError::InvalidInvitation) => {
+            return Err(RegisterError::InvalidInvitation)
+        }
+        Err(RegisterWithInvitationError::EmailMismatch) => {
+            return Err(RegisterError::EmailMismatch)
+        }
         Err(e) => {
             tracing::error!("Failed to create user: {:?}", e);
-
-            response
-                .content(RegisterPageData::default())
-                .add_error_message("Failed to create user")
-                .into_response()
+            return Err(RegisterError::Internal);
         }
+    };
+
+    audit
+        .record(
+            &app.database_connection,
+            AuditEvent::new(Action::Register).by_user(user.id),
+        )
+        .await;
+    send_verification_email(app, user.id, &user.email).await;
+
+    Ok(user)
+}
+
+pub async fn post_register(
+    State(app): State<AppState>,
+    audit: AuditContext,
+    Form(form): Form<RegisterForm>,
+) -> Response {
+    let mode = app.config.registration_mode;
+    let response = TemplateResponse::new("auth/register");
+
+    match register(&app, &audit, &form).await {
+        Ok(user) => response
+            .content(RegisterPageData {
+                registered_email: Some(user.email),
+                ..Default::default()
+            })
+            .add_success_message("Account created")
+            .into_response(),
+        Err(RegisterError::Closed) => registration_closed(),
+        Err(RegisterError::InvitationRequired) => response
+            .content(RegisterPageData {
+                invitation_required: true,
+                ..Default::default()
+            })
+            .into_response(),
+        Err(RegisterError::Validation(errors)) => response
+            .content(RegisterPageData {
+                form: form.into(),
+                errors: Some(errors),
+                ..Default::default()
+            })
+            .into_response(),
+        Err(e @ RegisterError::UserExists) => response
+            .content(RegisterPageData {
+                form: form.into(),
+                ..Default::default()
+            })
+            .add_error_message(e.to_string())
+            .into_response(),
+        Err(e @ RegisterError::InvalidInvitation) => response
+            .content(RegisterPageData {
+                invitation_required: mode == RegistrationMode::InviteOnly,
+                ..Default::default()
+            })
+            .add_error_message(e.to_string())
+            .into_response(),
+        Err(e @ RegisterError::EmailMismatch) => response
+            .content(RegisterPageData {
+                form: RegisterFormValues {
+                    invite: form.invite,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .add_error_message(e.to_string())
+            .into_response(),
+        Err(e @ RegisterError::Internal) => response
+            .content(RegisterPageData::default())
+            .add_error_message(e.to_string())
+            .into_response(),
     }
 }
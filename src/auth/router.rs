@@ -1,9 +1,19 @@
 use crate::app::AppState;
 
 use super::{
+    email_verification_page::{
+        get_resend_verification, get_verify_email, post_resend_verification,
+    },
     layer::AuthSession,
     login_page::{get_login, get_logout, post_login},
+    magic_link_page::{get_magic_link, get_magic_link_login, post_magic_link},
+    oidc_page::{get_oidc_callback, get_oidc_link, get_oidc_login},
+    passkey_login::{post_passkey_login, post_passkey_login_options},
+    password_reset_page::{
+        get_forgot_password, get_reset_password, post_forgot_password, post_reset_password,
+    },
     register_page::{get_register, post_register},
+    two_factor_page::{get_two_factor, post_two_factor},
 };
 use axum::{
     extract::Request,
@@ -17,10 +27,27 @@ pub fn router() -> Router<AppState> {
     Router::new()
         .route("/login", get(get_login))
         .route("/login", post(post_login))
+        .route("/login/2fa", get(get_two_factor))
+        .route("/login/2fa", post(post_two_factor))
+        .route("/login/passkey/options", post(post_passkey_login_options))
+        .route("/login/passkey", post(post_passkey_login))
+        .route("/login/magic", get(get_magic_link))
+        .route("/login/magic", post(post_magic_link))
+        .route("/login/magic/:token", get(get_magic_link_login))
+        .route("/auth/oidc/:provider/login", get(get_oidc_login))
         .route("/register", get(get_register))
         .route("/register", post(post_register))
+        .route("/forgot-password", get(get_forgot_password))
+        .route("/forgot-password", post(post_forgot_password))
+        .route("/reset-password/:token", get(get_reset_password))
+        .route("/reset-password/:token", post(post_reset_password))
         .layer(middleware::from_fn(invalid_when_signed_in))
         .route("/logout", get(get_logout))
+        .route("/verify-email", get(get_resend_verification))
+        .route("/verify-email", post(post_resend_verification))
+        .route("/verify-email/:token", get(get_verify_email))
+        .route("/auth/oidc/:provider/link", get(get_oidc_link))
+        .route("/auth/oidc/:provider/callback", get(get_oidc_callback))
 }
 
 async fn invalid_when_signed_in(
@@ -0,0 +1,46 @@
+/// What a personal API token may be used for. Stored by name, so variants
+/// can be added but existing names must not change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    ProfileRead,
+    ProfileWrite,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 2] = [Scope::ProfileRead, Scope::ProfileWrite];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::ProfileRead => "profile:read",
+            Scope::ProfileWrite => "profile:write",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Scope::ProfileRead => "Read your account and profile",
+            Scope::ProfileWrite => "Update your profile",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Scope> {
+        Scope::ALL.into_iter().find(|scope| scope.as_str() == name)
+    }
+}
+
+/// Scopes are stored space separated, like OAuth scopes.
+pub fn join(scopes: &[Scope]) -> String {
+    scopes
+        .iter()
+        .map(|scope| scope.as_str())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Reads stored scopes back, skipping names that are no longer known.
+pub fn split(scopes: &str) -> Vec<Scope> {
+    scopes
+        .split_whitespace()
+        .filter_map(Scope::from_name)
+        .collect()
+}
@@ -0,0 +1,78 @@
+use axum::{extract::Request, http::header::USER_AGENT, middleware::Next, response::Response};
+use serde::{Deserialize, Serialize};
+use tower_sessions::{cookie::time::OffsetDateTime, Session};
+
+use super::{client_ip::ClientIp, layer::AuthSession};
+
+pub const SESSION_ACTIVITY_KEY: &str = "auth.session_activity";
+/// How often the last activity is written back, so that browsing doesn't
+/// save the session on every request.
+const LAST_SEEN_RESOLUTION_SECONDS: i64 = 60;
+
+/// Where and when an authenticated session is being used. It is kept in the
+/// session data and copied to the session table by the store, so the user
+/// can review their sessions.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SessionActivity {
+    pub created_at: i64,
+    pub last_seen_at: i64,
+    pub ip_address: String,
+    pub user_agent: Option<String>,
+}
+
+pub async fn track_session_activity(
+    auth_session: AuthSession,
+    session: Session,
+    ClientIp(ip): ClientIp,
+    request: Request,
+    next: Next,
+) -> Response {
+    if auth_session.user.is_none() {
+        return next.run(request).await;
+    }
+
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let ip_address = ip.to_string();
+    let user_agent = request
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    let previous = match session.get::<SessionActivity>(SESSION_ACTIVITY_KEY).await {
+        Ok(previous) => previous,
+        Err(e) => {
+            tracing::error!("Failed to read session activity: {:?}", e);
+            return next.run(request).await;
+        }
+    };
+    let activity = match previous {
+        Some(previous)
+            if previous.ip_address == ip_address
+                && previous.user_agent == user_agent
+                && previous.last_seen_at + LAST_SEEN_RESOLUTION_SECONDS > now =>
+        {
+            None
+        }
+        Some(previous) => Some(SessionActivity {
+            last_seen_at: now,
+            ip_address,
+            user_agent,
+            ..previous
+        }),
+        None => Some(SessionActivity {
+            created_at: now,
+            last_seen_at: now,
+            ip_address,
+            user_agent,
+        }),
+    };
+
+    if let Some(activity) = activity {
+        if let Err(e) = session.insert(SESSION_ACTIVITY_KEY, activity).await {
+            tracing::error!("Failed to save session activity: {:?}", e);
+        }
+    }
+
+    next.run(request).await
+}
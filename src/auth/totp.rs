@@ -0,0 +1,142 @@
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use qrcode::{render::svg, QrCode};
+use rand::{rngs::OsRng, RngCore};
+use sha1::Sha1;
+use tower_sessions::cookie::time::OffsetDateTime;
+
+const ISSUER: &str = "Rust Web App";
+const DIGITS: u32 = 6;
+const PERIOD: i64 = 30;
+/// Number of periods before and after the current one that are still accepted,
+/// to make up for clock drift and slow typing.
+const ALLOWED_DRIFT: i64 = 1;
+
+/// Generates a new base32 encoded secret, as expected by authenticator apps.
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 20];
+    OsRng.fill_bytes(&mut bytes);
+    BASE32_NOPAD.encode(&bytes)
+}
+
+fn code_at(secret: &[u8], counter: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset],
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]) & 0x7fff_ffff;
+
+    binary % 10u32.pow(DIGITS)
+}
+
+/// Checks a code typed by the user against the secret, per RFC 6238, and
+/// returns the time step it belongs to. Codes of `last_step` or earlier are
+/// rejected so that each code only works once.
+pub fn verify(secret: &str, code: &str, last_step: Option<i64>) -> Option<i64> {
+    verify_at(secret, code, last_step, OffsetDateTime::now_utc())
+}
+
+fn verify_at(secret: &str, code: &str, last_step: Option<i64>, now: OffsetDateTime) -> Option<i64> {
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    if code.len() != DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let code = code.parse::<u32>().ok()?;
+    let secret = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+
+    let counter = now.unix_timestamp() / PERIOD;
+    (counter - ALLOWED_DRIFT..=counter + ALLOWED_DRIFT)
+        .filter(|step| last_step.is_none_or(|last_step| *step > last_step))
+        .find(|step| code_at(&secret, *step as u64) == code)
+}
+
+/// Builds the `otpauth://` URI that authenticator apps use to enroll the secret.
+pub fn otpauth_uri(secret: &str, account: &str) -> String {
+    let issuer = utf8_percent_encode(ISSUER, NON_ALPHANUMERIC);
+    let account = utf8_percent_encode(account, NON_ALPHANUMERIC);
+
+    format!(
+        "otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={DIGITS}&period={PERIOD}"
+    )
+}
+
+/// Renders the URI as an SVG QR code to be scanned by authenticator apps.
+pub fn qr_code_svg(uri: &str) -> Option<String> {
+    let code = QrCode::new(uri.as_bytes()).ok()?;
+    let svg = code
+        .render::<svg::Color>()
+        .min_dimensions(200, 200)
+        .quiet_zone(true)
+        .build();
+
+    Some(svg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The secret of the RFC 6238 test vectors, "12345678901234567890".
+    const SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+    const NOW: i64 = 1_111_111_109;
+
+    fn now() -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp(NOW).unwrap()
+    }
+
+    fn code(step: i64) -> String {
+        let secret = BASE32_NOPAD.decode(SECRET.as_bytes()).unwrap();
+        format!("{:06}", code_at(&secret, step as u64))
+    }
+
+    #[test]
+    fn matches_rfc_6238_test_vector() {
+        assert_eq!(code(NOW / PERIOD), "081804");
+        assert_eq!(
+            verify_at(SECRET, "081 804", None, now()),
+            Some(NOW / PERIOD)
+        );
+    }
+
+    #[test]
+    fn accepts_codes_at_the_edges_of_the_window() {
+        let step = NOW / PERIOD;
+
+        assert_eq!(
+            verify_at(SECRET, &code(step - 1), None, now()),
+            Some(step - 1)
+        );
+        assert_eq!(
+            verify_at(SECRET, &code(step + 1), None, now()),
+            Some(step + 1)
+        );
+        assert_eq!(verify_at(SECRET, &code(step - 2), None, now()), None);
+        assert_eq!(verify_at(SECRET, &code(step + 2), None, now()), None);
+    }
+
+    #[test]
+    fn refuses_a_step_already_used() {
+        let step = NOW / PERIOD;
+
+        assert_eq!(verify_at(SECRET, &code(step), Some(step), now()), None);
+        assert_eq!(verify_at(SECRET, &code(step - 1), Some(step), now()), None);
+        assert_eq!(
+            verify_at(SECRET, &code(step + 1), Some(step), now()),
+            Some(step + 1)
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_codes() {
+        assert_eq!(verify_at(SECRET, "08180", None, now()), None);
+        assert_eq!(verify_at(SECRET, "08180a", None, now()), None);
+        assert_eq!(verify_at("not base32!", "081804", None, now()), None);
+    }
+}
@@ -0,0 +1,176 @@
+use super::{
+    client_ip::ClientIp, db_login_throttle, db_two_factor, layer::AuthSession,
+    redirect::redirect_to_next,
+};
+use crate::{
+    app::AppState,
+    audit::{action::Action, context::AuditContext, event::AuditEvent},
+    layout::template_response::TemplateResponse,
+};
+use axum::{
+    extract::State,
+    http::HeaderMap,
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_login::{AuthUser, AuthnBackend};
+use axum_messages::Messages;
+use serde::{Deserialize, Serialize};
+use tower_sessions::{
+    cookie::time::{Duration, OffsetDateTime},
+    Session,
+};
+
+const PENDING_LOGIN_KEY: &str = "auth.pending_login";
+const PENDING_LOGIN_LIFETIME: Duration = Duration::minutes(5);
+const MAX_ATTEMPTS: u8 = 5;
+
+/// A login whose password was verified but which still has to pass the
+/// second factor. Until then the session is not authenticated.
+#[derive(Serialize, Deserialize)]
+struct PendingLogin {
+    user_id: i32,
+    next: Option<String>,
+    expires_at: i64,
+    attempts: u8,
+}
+
+impl PendingLogin {
+    fn is_expired(&self) -> bool {
+        self.expires_at < OffsetDateTime::now_utc().unix_timestamp()
+    }
+}
+
+pub async fn start_pending_login(
+    session: &Session,
+    user_id: i32,
+    next: Option<String>,
+) -> Result<(), tower_sessions::session::Error> {
+    let pending_login = PendingLogin {
+        user_id,
+        next,
+        expires_at: (OffsetDateTime::now_utc() + PENDING_LOGIN_LIFETIME).unix_timestamp(),
+        attempts: 0,
+    };
+
+    session.insert(PENDING_LOGIN_KEY, pending_login).await
+}
+
+async fn get_pending_login(session: &Session) -> Option<PendingLogin> {
+    match session.get::<PendingLogin>(PENDING_LOGIN_KEY).await {
+        Ok(Some(pending_login)) if !pending_login.is_expired() => Some(pending_login),
+        Ok(_) => None,
+        Err(e) => {
+            tracing::error!("Failed to read pending login: {:?}", e);
+            None
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Default)]
+pub struct TwoFactorForm {
+    code: String,
+}
+
+pub async fn get_two_factor(session: Session) -> Response {
+    if get_pending_login(&session).await.is_none() {
+        return Redirect::to("/login").into_response();
+    }
+
+    TemplateResponse::new("auth/two_factor").into_response()
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn post_two_factor(
+    State(app): State<AppState>,
+    mut auth_session: AuthSession,
+    session: Session,
+    ClientIp(ip): ClientIp,
+    audit: AuditContext,
+    messages: Messages,
+    headers: HeaderMap,
+    Form(form): Form<TwoFactorForm>,
+) -> Response {
+    let Some(mut pending_login) = get_pending_login(&session).await else {
+        return Redirect::to("/login").into_response();
+    };
+    let response = TemplateResponse::new("auth/two_factor");
+    let db = &app.database_connection;
+    let ip = ip.to_string();
+
+    let user = match auth_session.backend.get_user(&pending_login.user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return Redirect::to("/login").into_response(),
+        Err(e) => {
+            tracing::error!("Failed to load user: {:?}", e);
+            return response
+                .add_error_message("Internal Error: Failed to login user, try again later")
+                .into_response();
+        }
+    };
+
+    // Codes are throttled like passwords, so starting the login over doesn't
+    // give more guesses.
+    match db_login_throttle::locked_until(db, user.email(), &ip).await {
+        Ok(Some(_)) => return too_many_attempts(&session, messages).await,
+        Ok(None) => {}
+        Err(e) => tracing::error!("Failed to check login throttling: {:?}", e),
+    }
+
+    if !db_two_factor::verify_code(db, user.id(), &form.code).await {
+        let event = AuditEvent::new(Action::LoginFailed)
+            .target(user.id())
+            .details("two-factor code");
+        audit.record(db, event).await;
+
+        let locked_out = match db_login_throttle::record_failure(db, user.email(), &ip).await {
+            Ok(locked_until) => locked_until.is_some(),
+            Err(e) => {
+                tracing::error!("Failed to record failed login: {:?}", e);
+                false
+            }
+        };
+
+        pending_login.attempts += 1;
+        if locked_out || pending_login.attempts >= MAX_ATTEMPTS {
+            return too_many_attempts(&session, messages).await;
+        }
+        if let Err(e) = session.insert(PENDING_LOGIN_KEY, &pending_login).await {
+            tracing::error!("Failed to update pending login: {:?}", e);
+        }
+
+        return response
+            .add_error_message("Invalid authentication code")
+            .into_response();
+    }
+
+    if let Err(e) = session.remove_value(PENDING_LOGIN_KEY).await {
+        tracing::error!("Failed to clear pending login: {:?}", e);
+    }
+    if let Err(e) = db_login_throttle::record_success(db, user.email()).await {
+        tracing::error!("Failed to reset login throttling: {:?}", e);
+    }
+
+    if auth_session.login(&user).await.is_err() {
+        tracing::error!("Failed to login user: {:?}", user);
+        return response
+            .add_error_message("Internal Error: Failed to login user, try again later")
+            .into_response();
+    }
+
+    let event = AuditEvent::new(Action::Login)
+        .by_user(user.id())
+        .details("two-factor code");
+    audit.record(db, event).await;
+
+    redirect_to_next(&headers, pending_login.next.as_deref().unwrap_or("/"))
+}
+
+async fn too_many_attempts(session: &Session, messages: Messages) -> Response {
+    if let Err(e) = session.remove_value(PENDING_LOGIN_KEY).await {
+        tracing::error!("Failed to clear pending login: {:?}", e);
+    }
+    messages.error("Too many invalid codes, log in again");
+
+    Redirect::to("/login").into_response()
+}
@@ -0,0 +1,739 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::{rngs::OsRng, RngCore};
+use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_ASN1};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tower_sessions::{
+    cookie::time::{Duration, OffsetDateTime},
+    Session,
+};
+use url::Url;
+
+use crate::config::Config;
+
+/// COSE identifier of ES256, the only algorithm we accept.
+const ES256: i64 = -7;
+/// DER prefix of a SubjectPublicKeyInfo holding an uncompressed P-256 point.
+const P256_SPKI_PREFIX: [u8; 26] = [
+    0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x08, 0x2a,
+    0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00,
+];
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL_DATA: u8 = 0x40;
+const TIMEOUT_MS: u32 = 60_000;
+const CHALLENGE_LIFETIME: Duration = Duration::minutes(5);
+const MALFORMED_COSE_KEY: WebauthnError = WebauthnError::Malformed("invalid credential public key");
+/// CBOR major types and COSE_Key labels and values of an ES256 key.
+const CBOR_UNSIGNED: u8 = 0;
+const CBOR_NEGATIVE: u8 = 1;
+const CBOR_BYTES: u8 = 2;
+const CBOR_TEXT: u8 = 3;
+const CBOR_MAP: u8 = 5;
+const COSE_KTY: i64 = 1;
+const COSE_ALG: i64 = 3;
+const COSE_EC2_CRV: i64 = -1;
+const COSE_EC2_X: i64 = -2;
+const COSE_EC2_Y: i64 = -3;
+const COSE_KTY_EC2: i64 = 2;
+const COSE_CRV_P256: i64 = 1;
+
+#[derive(Error, Debug)]
+pub enum WebauthnError {
+    #[error("Malformed response: {0}")]
+    Malformed(&'static str),
+    #[error("Client data does not match the ceremony: {0}")]
+    ClientData(&'static str),
+    #[error("Authenticator data does not match the relying party: {0}")]
+    AuthenticatorData(&'static str),
+    #[error("Unsupported public key algorithm")]
+    UnsupportedAlgorithm,
+    #[error("Invalid signature")]
+    InvalidSignature,
+    #[error("Signature counter went backwards, the authenticator may be cloned")]
+    CounterRegression,
+}
+
+/// Response of `navigator.credentials.create()`, with every binary field
+/// base64url encoded by the browser script.
+#[derive(Deserialize, Debug)]
+pub struct RegistrationResponse {
+    pub id: String,
+    pub client_data_json: String,
+    pub authenticator_data: String,
+    pub public_key: String,
+    pub public_key_algorithm: i64,
+}
+
+/// Response of `navigator.credentials.get()`, with every binary field
+/// base64url encoded by the browser script.
+#[derive(Deserialize, Debug, Clone)]
+pub struct AssertionResponse {
+    pub id: String,
+    pub client_data_json: String,
+    pub authenticator_data: String,
+    pub signature: String,
+}
+
+/// A credential that passed the registration ceremony and can be stored.
+pub struct VerifiedCredential {
+    pub credential_id: String,
+    pub public_key: String,
+    pub sign_count: u32,
+}
+
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    ceremony: String,
+    challenge: String,
+    origin: String,
+}
+
+struct AuthenticatorData<'a> {
+    rp_id_hash: &'a [u8],
+    flags: u8,
+    sign_count: u32,
+    attested_credential_data: &'a [u8],
+}
+
+impl<'a> AuthenticatorData<'a> {
+    fn parse(bytes: &'a [u8]) -> Result<Self, WebauthnError> {
+        if bytes.len() < 37 {
+            return Err(WebauthnError::Malformed("authenticator data is too short"));
+        }
+
+        Ok(Self {
+            rp_id_hash: &bytes[..32],
+            flags: bytes[32],
+            sign_count: u32::from_be_bytes([bytes[33], bytes[34], bytes[35], bytes[36]]),
+            attested_credential_data: &bytes[37..],
+        })
+    }
+
+    /// Splits the attested credential data, a 16 bytes AAGUID followed by the
+    /// id length and the id, into the credential id and the COSE key after it.
+    fn attested_credential(&self) -> Result<(&'a [u8], &'a [u8]), WebauthnError> {
+        let data = self.attested_credential_data;
+        if data.len() < 18 {
+            return Err(WebauthnError::Malformed("missing attested credential data"));
+        }
+        let length = u16::from_be_bytes([data[16], data[17]]) as usize;
+        let credential_id = data
+            .get(18..18 + length)
+            .ok_or(WebauthnError::Malformed("truncated credential id"))?;
+
+        Ok((credential_id, &data[18 + length..]))
+    }
+
+    fn credential_id(&self) -> Result<&'a [u8], WebauthnError> {
+        Ok(self.attested_credential()?.0)
+    }
+
+    /// The uncompressed P-256 point of the key the authenticator created,
+    /// read from its COSE_Key in the signed authenticator data.
+    fn credential_public_key(&self) -> Result<Vec<u8>, WebauthnError> {
+        let (_, cose_key) = self.attested_credential()?;
+        let mut reader = CborReader {
+            data: cose_key,
+            position: 0,
+        };
+
+        let (mut kty, mut alg, mut crv, mut x, mut y) = (None, None, None, None, None);
+        let (major, entries) = reader.head()?;
+        if major != CBOR_MAP {
+            return Err(MALFORMED_COSE_KEY);
+        }
+        for _ in 0..entries {
+            let label = reader.integer()?;
+            match label {
+                COSE_KTY => kty = Some(reader.integer()?),
+                COSE_ALG => alg = Some(reader.integer()?),
+                COSE_EC2_CRV => crv = Some(reader.integer()?),
+                COSE_EC2_X => x = Some(reader.bytes()?),
+                COSE_EC2_Y => y = Some(reader.bytes()?),
+                _ => reader.skip()?,
+            }
+        }
+
+        if kty != Some(COSE_KTY_EC2) || alg != Some(ES256) || crv != Some(COSE_CRV_P256) {
+            return Err(WebauthnError::UnsupportedAlgorithm);
+        }
+        match (x, y) {
+            (Some(x), Some(y)) if x.len() == 32 && y.len() == 32 => Ok([&[0x04], x, y].concat()),
+            _ => Err(MALFORMED_COSE_KEY),
+        }
+    }
+}
+
+/// Reads the few CBOR items a COSE_Key is made of: integers, byte and text
+/// strings inside a single map.
+struct CborReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> CborReader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], WebauthnError> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + length)
+            .ok_or(MALFORMED_COSE_KEY)?;
+        self.position += length;
+
+        Ok(bytes)
+    }
+
+    /// The major type and argument of the next item.
+    fn head(&mut self) -> Result<(u8, u64), WebauthnError> {
+        let initial = self.take(1)?[0];
+        let length = match initial & 0x1f {
+            argument @ 0..=23 => return Ok((initial >> 5, argument.into())),
+            24 => 1,
+            25 => 2,
+            26 => 4,
+            27 => 8,
+            _ => return Err(MALFORMED_COSE_KEY),
+        };
+        let argument = self
+            .take(length)?
+            .iter()
+            .fold(0, |argument, byte| argument << 8 | u64::from(*byte));
+
+        Ok((initial >> 5, argument))
+    }
+
+    fn integer(&mut self) -> Result<i64, WebauthnError> {
+        let (major, argument) = self.head()?;
+        let argument = i64::try_from(argument).map_err(|_| MALFORMED_COSE_KEY)?;
+        match major {
+            CBOR_UNSIGNED => Ok(argument),
+            CBOR_NEGATIVE => Ok(-1 - argument),
+            _ => Err(MALFORMED_COSE_KEY),
+        }
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], WebauthnError> {
+        match self.head()? {
+            (CBOR_BYTES, length) => self.take(length as usize),
+            _ => Err(MALFORMED_COSE_KEY),
+        }
+    }
+
+    fn skip(&mut self) -> Result<(), WebauthnError> {
+        match self.head()? {
+            (CBOR_UNSIGNED | CBOR_NEGATIVE, _) => Ok(()),
+            (CBOR_BYTES | CBOR_TEXT, length) => self.take(length as usize).map(|_| ()),
+            _ => Err(MALFORMED_COSE_KEY),
+        }
+    }
+}
+
+fn decode(value: &str, field: &'static str) -> Result<Vec<u8>, WebauthnError> {
+    URL_SAFE_NO_PAD
+        .decode(value)
+        .map_err(|_| WebauthnError::Malformed(field))
+}
+
+#[derive(Serialize, Deserialize)]
+struct PendingChallenge {
+    challenge: String,
+    expires_at: i64,
+}
+
+/// Generates a new challenge and keeps it in the session under `key` until
+/// the browser answers the ceremony.
+pub async fn start_ceremony(
+    session: &Session,
+    key: &str,
+) -> Result<String, tower_sessions::session::Error> {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    let challenge = URL_SAFE_NO_PAD.encode(bytes);
+
+    let pending = PendingChallenge {
+        challenge: challenge.clone(),
+        expires_at: (OffsetDateTime::now_utc() + CHALLENGE_LIFETIME).unix_timestamp(),
+    };
+    session.insert(key, pending).await?;
+
+    Ok(challenge)
+}
+
+/// Removes the challenge from the session, so each one is answered only once.
+pub async fn finish_ceremony(session: &Session, key: &str) -> Option<String> {
+    match session.remove::<PendingChallenge>(key).await {
+        Ok(Some(pending)) if pending.expires_at >= OffsetDateTime::now_utc().unix_timestamp() => {
+            Some(pending.challenge)
+        }
+        Ok(_) => None,
+        Err(e) => {
+            tracing::error!("Failed to read WebAuthn challenge: {:?}", e);
+            None
+        }
+    }
+}
+
+/// Relying party settings, derived from the public URL of the application.
+/// Built once at startup and shared through `AppState`.
+#[derive(Clone, Debug)]
+pub struct Webauthn {
+    rp_id: String,
+    rp_name: String,
+    origin: String,
+}
+
+impl Webauthn {
+    pub fn new(config: &Config) -> Self {
+        let url = Url::parse(&config.base_url).expect("BASE_URL is checked by Config::from_env");
+
+        Self {
+            rp_id: url.host_str().unwrap_or("localhost").to_string(),
+            rp_name: "Rust Web App".to_string(),
+            origin: url.origin().ascii_serialization(),
+        }
+    }
+
+    /// Options for `navigator.credentials.create()`, requesting a discoverable
+    /// credential so the user can later sign in without typing an email.
+    pub fn registration_options(
+        &self,
+        challenge: &str,
+        user_id: i32,
+        email: &str,
+        exclude_credential_ids: &[String],
+    ) -> Value {
+        let exclude_credentials: Vec<Value> = exclude_credential_ids
+            .iter()
+            .map(|id| json!({ "type": "public-key", "id": id }))
+            .collect();
+
+        json!({
+            "challenge": challenge,
+            "rp": { "id": self.rp_id, "name": self.rp_name },
+            "user": {
+                "id": URL_SAFE_NO_PAD.encode(user_id.to_string()),
+                "name": email,
+                "displayName": email,
+            },
+            "pubKeyCredParams": [{ "type": "public-key", "alg": ES256 }],
+            "timeout": TIMEOUT_MS,
+            "attestation": "none",
+            "authenticatorSelection": {
+                "residentKey": "required",
+                "requireResidentKey": true,
+                "userVerification": "required",
+            },
+            "excludeCredentials": exclude_credentials,
+        })
+    }
+
+    /// Options for `navigator.credentials.get()`, leaving the choice of the
+    /// credential to the authenticator. Passkeys skip the second factor, so
+    /// the authenticator has to verify the user with a PIN or biometrics.
+    pub fn authentication_options(&self, challenge: &str) -> Value {
+        json!({
+            "challenge": challenge,
+            "rpId": self.rp_id,
+            "timeout": TIMEOUT_MS,
+            "userVerification": "required",
+            "allowCredentials": [],
+        })
+    }
+
+    fn verify_client_data(
+        &self,
+        client_data_json: &[u8],
+        ceremony: &str,
+        challenge: &str,
+    ) -> Result<(), WebauthnError> {
+        let client_data: ClientData = serde_json::from_slice(client_data_json)
+            .map_err(|_| WebauthnError::Malformed("client data is not valid JSON"))?;
+
+        if client_data.ceremony != ceremony {
+            return Err(WebauthnError::ClientData("unexpected ceremony type"));
+        }
+        if client_data.challenge != challenge {
+            return Err(WebauthnError::ClientData("challenge mismatch"));
+        }
+        if client_data.origin != self.origin {
+            return Err(WebauthnError::ClientData("origin mismatch"));
+        }
+
+        Ok(())
+    }
+
+    fn verify_rp(&self, authenticator_data: &AuthenticatorData) -> Result<(), WebauthnError> {
+        if authenticator_data.rp_id_hash != Sha256::digest(self.rp_id.as_bytes()).as_slice() {
+            return Err(WebauthnError::AuthenticatorData(
+                "relying party id mismatch",
+            ));
+        }
+        if authenticator_data.flags & FLAG_USER_PRESENT == 0 {
+            return Err(WebauthnError::AuthenticatorData("user was not present"));
+        }
+        if authenticator_data.flags & FLAG_USER_VERIFIED == 0 {
+            return Err(WebauthnError::AuthenticatorData("user was not verified"));
+        }
+
+        Ok(())
+    }
+
+    pub fn verify_registration(
+        &self,
+        challenge: &str,
+        response: &RegistrationResponse,
+    ) -> Result<VerifiedCredential, WebauthnError> {
+        let client_data_json = decode(&response.client_data_json, "client data")?;
+        self.verify_client_data(&client_data_json, "webauthn.create", challenge)?;
+
+        let authenticator_data_bytes = decode(&response.authenticator_data, "authenticator data")?;
+        let authenticator_data = AuthenticatorData::parse(&authenticator_data_bytes)?;
+        self.verify_rp(&authenticator_data)?;
+        if authenticator_data.flags & FLAG_ATTESTED_CREDENTIAL_DATA == 0 {
+            return Err(WebauthnError::AuthenticatorData(
+                "missing attested credential data",
+            ));
+        }
+        if authenticator_data.credential_id()? != decode(&response.id, "credential id")? {
+            return Err(WebauthnError::AuthenticatorData("credential id mismatch"));
+        }
+
+        if response.public_key_algorithm != ES256 {
+            return Err(WebauthnError::UnsupportedAlgorithm);
+        }
+        // The key from `getPublicKey()` isn't covered by any signature, only
+        // the one in the authenticator data is.
+        let point = authenticator_data.credential_public_key()?;
+        let public_key = decode(&response.public_key, "public key")?;
+        if public_key.strip_prefix(P256_SPKI_PREFIX.as_slice()) != Some(point.as_slice()) {
+            return Err(WebauthnError::AuthenticatorData("public key mismatch"));
+        }
+
+        Ok(VerifiedCredential {
+            credential_id: response.id.clone(),
+            public_key: URL_SAFE_NO_PAD.encode(point),
+            sign_count: authenticator_data.sign_count,
+        })
+    }
+
+    /// Verifies an assertion made with a stored credential and returns the
+    /// new signature counter to be saved.
+    pub fn verify_assertion(
+        &self,
+        challenge: &str,
+        response: &AssertionResponse,
+        public_key: &str,
+        stored_sign_count: u32,
+    ) -> Result<u32, WebauthnError> {
+        let client_data_json = decode(&response.client_data_json, "client data")?;
+        self.verify_client_data(&client_data_json, "webauthn.get", challenge)?;
+
+        let authenticator_data_bytes = decode(&response.authenticator_data, "authenticator data")?;
+        let authenticator_data = AuthenticatorData::parse(&authenticator_data_bytes)?;
+        self.verify_rp(&authenticator_data)?;
+
+        let mut signed_data = authenticator_data_bytes.clone();
+        signed_data.extend_from_slice(&Sha256::digest(&client_data_json));
+        let signature = decode(&response.signature, "signature")?;
+        let public_key = decode(public_key, "stored public key")?;
+        UnparsedPublicKey::new(&ECDSA_P256_SHA256_ASN1, public_key)
+            .verify(&signed_data, &signature)
+            .map_err(|_| WebauthnError::InvalidSignature)?;
+
+        // Authenticators that don't implement a counter always report zero.
+        let sign_count = authenticator_data.sign_count;
+        if (sign_count != 0 || stored_sign_count != 0) && sign_count <= stored_sign_count {
+            return Err(WebauthnError::CounterRegression);
+        }
+
+        Ok(sign_count)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use ring::{
+        rand::SystemRandom,
+        signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING},
+    };
+
+    use super::*;
+
+    const RP_ID: &str = "localhost";
+    const ORIGIN: &str = "http://localhost:3000";
+
+    pub fn relying_party() -> Webauthn {
+        Webauthn {
+            rp_id: RP_ID.to_string(),
+            rp_name: "Rust Web App".to_string(),
+            origin: ORIGIN.to_string(),
+        }
+    }
+
+    /// Answers ceremonies the way a browser and a platform authenticator
+    /// would, with a P-256 key held in memory.
+    pub struct SoftwareAuthenticator {
+        key_pair: EcdsaKeyPair,
+        rng: SystemRandom,
+        credential_id: Vec<u8>,
+        pub rp_id: String,
+        pub origin: String,
+        pub sign_count: u32,
+        /// Whether the authenticator claims to have checked a PIN or
+        /// biometrics, rather than only a touch.
+        pub user_verified: bool,
+    }
+
+    impl SoftwareAuthenticator {
+        pub fn new() -> Self {
+            let rng = SystemRandom::new();
+            let pkcs8 =
+                EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).unwrap();
+            let key_pair =
+                EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng)
+                    .unwrap();
+            let mut credential_id = vec![0u8; 16];
+            OsRng.fill_bytes(&mut credential_id);
+
+            Self {
+                key_pair,
+                rng,
+                credential_id,
+                rp_id: RP_ID.to_string(),
+                origin: ORIGIN.to_string(),
+                sign_count: 0,
+                user_verified: true,
+            }
+        }
+
+        fn client_data(&self, ceremony: &str, challenge: &str) -> Vec<u8> {
+            json!({ "type": ceremony, "challenge": challenge, "origin": self.origin })
+                .to_string()
+                .into_bytes()
+        }
+
+        fn authenticator_data(&self, flags: u8) -> Vec<u8> {
+            let mut data = Sha256::digest(self.rp_id.as_bytes()).to_vec();
+            let user_verified = if self.user_verified {
+                FLAG_USER_VERIFIED
+            } else {
+                0
+            };
+            data.push(flags | FLAG_USER_PRESENT | user_verified);
+            data.extend_from_slice(&self.sign_count.to_be_bytes());
+            data
+        }
+
+        /// The public key as an ES256 COSE_Key: kty EC2, alg ES256, crv P-256
+        /// and the coordinates of the point.
+        fn cose_key(&self) -> Vec<u8> {
+            let point = self.key_pair.public_key().as_ref();
+            let mut key = vec![0xa5, 0x01, 0x02, 0x03, 0x26, 0x20, 0x01, 0x21, 0x58, 0x20];
+            key.extend_from_slice(&point[1..33]);
+            key.extend_from_slice(&[0x22, 0x58, 0x20]);
+            key.extend_from_slice(&point[33..]);
+            key
+        }
+
+        pub fn register(&mut self, challenge: &str) -> RegistrationResponse {
+            self.sign_count += 1;
+            let mut authenticator_data = self.authenticator_data(FLAG_ATTESTED_CREDENTIAL_DATA);
+            // A zero AAGUID, as sent with `none` attestation.
+            authenticator_data.extend_from_slice(&[0u8; 16]);
+            authenticator_data.extend_from_slice(&(self.credential_id.len() as u16).to_be_bytes());
+            authenticator_data.extend_from_slice(&self.credential_id);
+            authenticator_data.extend_from_slice(&self.cose_key());
+            let mut public_key = P256_SPKI_PREFIX.to_vec();
+            public_key.extend_from_slice(self.key_pair.public_key().as_ref());
+
+            RegistrationResponse {
+                id: URL_SAFE_NO_PAD.encode(&self.credential_id),
+                client_data_json: URL_SAFE_NO_PAD
+                    .encode(self.client_data("webauthn.create", challenge)),
+                authenticator_data: URL_SAFE_NO_PAD.encode(authenticator_data),
+                public_key: URL_SAFE_NO_PAD.encode(public_key),
+                public_key_algorithm: ES256,
+            }
+        }
+
+        pub fn assert(&mut self, challenge: &str) -> AssertionResponse {
+            self.sign_count += 1;
+            let authenticator_data = self.authenticator_data(0);
+            let client_data_json = self.client_data("webauthn.get", challenge);
+            let mut signed_data = authenticator_data.clone();
+            signed_data.extend_from_slice(&Sha256::digest(&client_data_json));
+            let signature = self.key_pair.sign(&self.rng, &signed_data).unwrap();
+
+            AssertionResponse {
+                id: URL_SAFE_NO_PAD.encode(&self.credential_id),
+                client_data_json: URL_SAFE_NO_PAD.encode(client_data_json),
+                authenticator_data: URL_SAFE_NO_PAD.encode(authenticator_data),
+                signature: URL_SAFE_NO_PAD.encode(signature.as_ref()),
+            }
+        }
+    }
+
+    fn registered(authenticator: &mut SoftwareAuthenticator) -> VerifiedCredential {
+        relying_party()
+            .verify_registration("registration", &authenticator.register("registration"))
+            .unwrap()
+    }
+
+    #[test]
+    fn registers_and_asserts_with_software_authenticator() {
+        let mut authenticator = SoftwareAuthenticator::new();
+        let credential = registered(&mut authenticator);
+        assert_eq!(credential.sign_count, 1);
+
+        let sign_count = relying_party()
+            .verify_assertion(
+                "login",
+                &authenticator.assert("login"),
+                &credential.public_key,
+                credential.sign_count,
+            )
+            .unwrap();
+        assert_eq!(sign_count, 2);
+    }
+
+    #[test]
+    fn registration_rejects_wrong_origin() {
+        let mut authenticator = SoftwareAuthenticator::new();
+        authenticator.origin = "https://evil.example".to_string();
+
+        let result =
+            relying_party().verify_registration("challenge", &authenticator.register("challenge"));
+        assert!(matches!(
+            result,
+            Err(WebauthnError::ClientData("origin mismatch"))
+        ));
+    }
+
+    #[test]
+    fn registration_rejects_wrong_challenge() {
+        let mut authenticator = SoftwareAuthenticator::new();
+
+        let result =
+            relying_party().verify_registration("challenge", &authenticator.register("other"));
+        assert!(matches!(
+            result,
+            Err(WebauthnError::ClientData("challenge mismatch"))
+        ));
+    }
+
+    #[test]
+    fn registration_rejects_other_relying_party() {
+        let mut authenticator = SoftwareAuthenticator::new();
+        authenticator.rp_id = "evil.example".to_string();
+
+        let result =
+            relying_party().verify_registration("challenge", &authenticator.register("challenge"));
+        assert!(matches!(
+            result,
+            Err(WebauthnError::AuthenticatorData(
+                "relying party id mismatch"
+            ))
+        ));
+    }
+
+    #[test]
+    fn assertion_rejects_wrong_origin() {
+        let mut authenticator = SoftwareAuthenticator::new();
+        let credential = registered(&mut authenticator);
+        authenticator.origin = "https://evil.example".to_string();
+
+        let result = relying_party().verify_assertion(
+            "login",
+            &authenticator.assert("login"),
+            &credential.public_key,
+            credential.sign_count,
+        );
+        assert!(matches!(
+            result,
+            Err(WebauthnError::ClientData("origin mismatch"))
+        ));
+    }
+
+    #[test]
+    fn assertion_rejects_wrong_challenge() {
+        let mut authenticator = SoftwareAuthenticator::new();
+        let credential = registered(&mut authenticator);
+
+        let result = relying_party().verify_assertion(
+            "login",
+            &authenticator.assert("replayed"),
+            &credential.public_key,
+            credential.sign_count,
+        );
+        assert!(matches!(
+            result,
+            Err(WebauthnError::ClientData("challenge mismatch"))
+        ));
+    }
+
+    #[test]
+    fn assertion_rejects_sign_count_regression() {
+        let mut authenticator = SoftwareAuthenticator::new();
+        let credential = registered(&mut authenticator);
+
+        let result = relying_party().verify_assertion(
+            "login",
+            &authenticator.assert("login"),
+            &credential.public_key,
+            5,
+        );
+        assert!(matches!(result, Err(WebauthnError::CounterRegression)));
+    }
+
+    #[test]
+    fn assertion_rejects_signature_of_another_key() {
+        let mut authenticator = SoftwareAuthenticator::new();
+        let credential = registered(&mut authenticator);
+        let mut other = SoftwareAuthenticator::new();
+        other.sign_count = authenticator.sign_count;
+
+        let result = relying_party().verify_assertion(
+            "login",
+            &other.assert("login"),
+            &credential.public_key,
+            credential.sign_count,
+        );
+        assert!(matches!(result, Err(WebauthnError::InvalidSignature)));
+    }
+
+    #[test]
+    fn assertion_rejects_presence_without_verification() {
+        let mut authenticator = SoftwareAuthenticator::new();
+        let credential = registered(&mut authenticator);
+        authenticator.user_verified = false;
+
+        let result = relying_party().verify_assertion(
+            "login",
+            &authenticator.assert("login"),
+            &credential.public_key,
+            credential.sign_count,
+        );
+        assert!(matches!(
+            result,
+            Err(WebauthnError::AuthenticatorData("user was not verified"))
+        ));
+    }
+
+    #[test]
+    fn registration_rejects_key_not_in_authenticator_data() {
+        let mut authenticator = SoftwareAuthenticator::new();
+        let mut response = authenticator.register("registration");
+        response.public_key = SoftwareAuthenticator::new()
+            .register("registration")
+            .public_key;
+
+        let result = relying_party().verify_registration("registration", &response);
+        assert!(matches!(
+            result,
+            Err(WebauthnError::AuthenticatorData("public key mismatch"))
+        ));
+    }
+}
@@ -0,0 +1,81 @@
+use sea_orm::DatabaseConnection;
+
+use crate::{
+    audit::db_audit_event,
+    auth::{db_authz, db_login_throttle, db_user},
+};
+
+const USAGE: &str = "Usage: rust-web [unlock-account <email> | grant-role <email> <role> | revoke-role <email> <role> | verify-audit-log]";
+
+/// Runs an administrative command given on the command line instead of
+/// starting the server, returning the process exit code.
+pub async fn run(db: &DatabaseConnection, args: &[String]) -> i32 {
+    match args {
+        [command, email] if command == "unlock-account" => {
+            match db_login_throttle::unlock_account(db, email).await {
+                Ok(true) => {
+                    println!("Unlocked {}", email);
+                    0
+                }
+                Ok(false) => {
+                    println!("{} has no failed login attempts", email);
+                    0
+                }
+                Err(e) => {
+                    eprintln!("Failed to unlock {}: {:?}", email, e);
+                    1
+                }
+            }
+        }
+        [command, email, role] if command == "grant-role" || command == "revoke-role" => {
+            let Some(user) = db_user::get_user_by_email(db, email).await else {
+                eprintln!("There is no user with the email {}", email);
+                return 1;
+            };
+
+            let result = if command == "grant-role" {
+                db_authz::assign_role(db, user.id, role).await
+            } else {
+                db_authz::remove_role(db, user.id, role).await
+            };
+            match result {
+                Ok(true) => {
+                    println!("Updated the roles of {}", email);
+                    0
+                }
+                Ok(false) if command == "grant-role" => {
+                    eprintln!("There is no role named {}", role);
+                    1
+                }
+                Ok(false) => {
+                    println!("{} doesn't have the role {}", email, role);
+                    0
+                }
+                Err(e) => {
+                    eprintln!("Failed to update the roles of {}: {:?}", email, e);
+                    1
+                }
+            }
+        }
+        [command] if command == "verify-audit-log" => {
+            match db_audit_event::verify_chain(db).await {
+                Ok(None) => {
+                    println!("The audit log is intact");
+                    0
+                }
+                Ok(Some(id)) => {
+                    eprintln!("The audit log was altered at event {}", id);
+                    1
+                }
+                Err(e) => {
+                    eprintln!("Failed to verify the audit log: {:?}", e);
+                    1
+                }
+            }
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            2
+        }
+    }
+}
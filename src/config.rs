@@ -0,0 +1,134 @@
+use std::env;
+
+use url::Url;
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub base_url: String,
+    pub require_verified_email: bool,
+    pub registration_mode: RegistrationMode,
+    pub trust_forwarded_for: bool,
+    /// Days a deleted account is kept, and can be restored, before it is
+    /// removed for good. Zero deletes accounts right away.
+    pub account_deletion_grace_days: i64,
+    /// Hosts, besides this site, that users can be sent back to after
+    /// logging in.
+    pub redirect_allowed_hosts: Vec<String>,
+    pub oidc_providers: Vec<OidcProviderConfig>,
+}
+
+/// Who can create an account, set through `REGISTRATION_MODE`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegistrationMode {
+    /// Anyone can sign up.
+    Open,
+    /// Only people holding a valid invitation can sign up.
+    InviteOnly,
+    /// Nobody can sign up, accounts already created can still log in.
+    Closed,
+}
+
+impl RegistrationMode {
+    fn from_env() -> Self {
+        match env::var("REGISTRATION_MODE").as_deref() {
+            Err(_) | Ok("open") => RegistrationMode::Open,
+            Ok("invite-only") => RegistrationMode::InviteOnly,
+            Ok("closed") => RegistrationMode::Closed,
+            Ok(mode) => panic!(
+                "REGISTRATION_MODE must be open, invite-only or closed, not {}",
+                mode
+            ),
+        }
+    }
+}
+
+/// An OpenID Connect provider users can log in with, configured through
+/// `OIDC_<SLUG>_*` environment variables for every slug in `OIDC_PROVIDERS`.
+#[derive(Clone, Debug)]
+pub struct OidcProviderConfig {
+    pub slug: String,
+    pub name: String,
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub scopes: String,
+}
+
+impl OidcProviderConfig {
+    fn from_env(slug: &str) -> Self {
+        let var = |name: &str| env::var(format!("OIDC_{}_{}", slug.to_uppercase(), name)).ok();
+        let required = |name: &str| {
+            var(name).unwrap_or_else(|| {
+                panic!(
+                    "OIDC_{}_{} must be set for OIDC provider {}",
+                    slug.to_uppercase(),
+                    name,
+                    slug
+                )
+            })
+        };
+
+        Self {
+            slug: slug.to_string(),
+            name: var("NAME").unwrap_or_else(|| slug.to_string()),
+            issuer: required("ISSUER").trim_end_matches('/').to_string(),
+            client_id: required("CLIENT_ID"),
+            client_secret: var("CLIENT_SECRET"),
+            scopes: var("SCOPES").unwrap_or_else(|| "openid email profile".to_string()),
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        let base_url = env::var("BASE_URL").unwrap_or_else(|_| "http://localhost:3000".to_string());
+        if !Url::parse(&base_url).is_ok_and(|url| url.has_host()) {
+            panic!("BASE_URL must be an absolute URL, not {}", base_url);
+        }
+
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            require_verified_email: env_flag("REQUIRE_VERIFIED_EMAIL"),
+            registration_mode: RegistrationMode::from_env(),
+            trust_forwarded_for: env_flag("TRUST_FORWARDED_FOR"),
+            account_deletion_grace_days: env::var("ACCOUNT_DELETION_GRACE_DAYS")
+                .ok()
+                .and_then(|days| days.parse().ok())
+                .unwrap_or(0),
+            redirect_allowed_hosts: env_list("REDIRECT_ALLOWED_HOSTS"),
+            oidc_providers: env_list("OIDC_PROVIDERS")
+                .iter()
+                .map(|slug| OidcProviderConfig::from_env(slug))
+                .collect(),
+        }
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    /// Cookies are only sent over HTTPS when the application is served over
+    /// it, so plain HTTP keeps working in development.
+    pub fn secure_cookies(&self) -> bool {
+        self.base_url.starts_with("https://")
+    }
+}
+
+fn env_flag(name: &str) -> bool {
+    env::var(name)
+        .map(|value| matches!(value.as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false)
+}
+
+fn env_list(name: &str) -> Vec<String> {
+    env::var(name)
+        .map(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
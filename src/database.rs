@@ -14,3 +14,16 @@ pub async fn connect() -> DatabaseConnection {
 
     conn
 }
+
+/// A fresh, migrated database that only lives as long as the connection.
+#[cfg(test)]
+pub async fn connect_in_memory() -> DatabaseConnection {
+    let conn = Database::connect("sqlite::memory:")
+        .await
+        .expect("Failed to connect to database");
+    Migrator::up(&conn, None)
+        .await
+        .expect("Failed to run migrations");
+
+    conn
+}
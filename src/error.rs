@@ -0,0 +1,148 @@
+use axum::{
+    http::{
+        header::{CONTENT_LENGTH, CONTENT_TYPE},
+        StatusCode,
+    },
+    response::{IntoResponse, Redirect, Response},
+};
+use sea_orm::DbErr;
+use serde::Serialize;
+use thiserror::Error;
+use tower_sessions::session;
+
+use crate::layout::template_response::TemplateResponse;
+
+/// Errors page handlers return with `?`, rendered as error pages through the
+/// layout. Internal errors are logged and never shown.
+#[derive(Error, Debug)]
+pub enum AppError {
+    #[error("Login required")]
+    Unauthenticated,
+    #[error("Page not found")]
+    NotFound,
+    #[error("{0}")]
+    Forbidden(&'static str),
+    #[error("Failed to query the database")]
+    Database(#[from] DbErr),
+    #[error("Failed to access the session")]
+    Session(#[from] session::Error),
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl AppError {
+    fn status(&self) -> StatusCode {
+        match self {
+            AppError::NotFound => StatusCode::NOT_FOUND,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            // Sent as a redirect to the login page rather than an error page.
+            AppError::Unauthenticated => StatusCode::SEE_OTHER,
+            AppError::Database(_) | AppError::Session(_) | AppError::Internal(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let reason = match &self {
+            // Handlers behind `login_required!` only see this when the user
+            // was logged out during the request.
+            AppError::Unauthenticated => return Redirect::to("/login").into_response(),
+            AppError::Forbidden(reason) => Some(*reason),
+            AppError::Database(e) => {
+                tracing::error!("Failed to query the database: {:?}", e);
+                None
+            }
+            AppError::Session(e) => {
+                tracing::error!("Failed to access the session: {:?}", e);
+                None
+            }
+            AppError::Internal(message) => {
+                tracing::error!("{}", message);
+                None
+            }
+            AppError::NotFound => None,
+        };
+
+        error_page(self.status(), reason)
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorPage {
+    title: &'static str,
+    reason: Option<&'static str>,
+}
+
+fn error_template(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::FORBIDDEN => "errors/403",
+        StatusCode::NOT_FOUND => "errors/404",
+        StatusCode::METHOD_NOT_ALLOWED => "errors/405",
+        status if status.is_server_error() => "errors/500",
+        _ => "errors/400",
+    }
+}
+
+/// The error page for the status, with the reason in place of the generic
+/// explanation when given.
+pub fn error_page(status: StatusCode, reason: Option<&'static str>) -> Response {
+    TemplateResponse::new(error_template(status))
+        .status(status)
+        // Replaces the page rather than the element htmx was updating.
+        .hx_retarget("body")
+        .content(ErrorPage {
+            title: status.canonical_reason().unwrap_or("Error"),
+            reason,
+        })
+        .into_response()
+}
+
+pub async fn fallback() -> AppError {
+    AppError::NotFound
+}
+
+/// Turns the bare error responses of the router, extractors and layers, such
+/// as a 405 for a known path or the 403 of `permission_required!`, into error
+/// pages. Responses with a body meant for machines, like JSON, are kept.
+pub async fn with_error_pages(response: Response) -> Response {
+    let status = response.status();
+    if !status.is_client_error() && !status.is_server_error() {
+        return response;
+    }
+
+    let is_bare = response.extensions().get::<TemplateResponse>().is_none()
+        && response
+            .headers()
+            .get(CONTENT_TYPE)
+            .is_none_or(|value| value.as_bytes().starts_with(b"text/plain"));
+    if !is_bare {
+        return response;
+    }
+
+    // Keeps headers such as `Allow` and `Retry-After`.
+    let (mut parts, _) = response.into_parts();
+    parts.headers.remove(CONTENT_TYPE);
+    parts.headers.remove(CONTENT_LENGTH);
+    let mut page = error_page(status, None);
+    page.headers_mut().extend(parts.headers);
+
+    page
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::header::LOCATION;
+
+    use super::*;
+
+    #[test]
+    fn unauthenticated_redirects_to_login() {
+        let response = AppError::Unauthenticated.into_response();
+
+        assert_eq!(response.status(), AppError::Unauthenticated.status());
+        assert_eq!(response.headers()[LOCATION], "/login");
+    }
+}
@@ -1,4 +1,4 @@
 pub mod messages;
 pub mod navbar;
 pub mod page_template;
-pub mod template_response;
\ No newline at end of file
+pub mod template_response;
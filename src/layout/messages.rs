@@ -1,3 +1,4 @@
+use axum_messages::{Level, Message, Messages};
 use serde::Serialize;
 
 #[derive(Serialize, Clone)]
@@ -7,15 +8,19 @@ pub struct PageMessage {
 }
 
 pub enum MessageLevel {
-    Error,
+    Info,
     Success,
+    Warning,
+    Error,
 }
 
 impl PageMessage {
     pub fn new(level: MessageLevel, text: impl Into<String>) -> PageMessage {
         let level = match level {
-            MessageLevel::Error => "error",
+            MessageLevel::Info => "info",
             MessageLevel::Success => "success",
+            MessageLevel::Warning => "warning",
+            MessageLevel::Error => "error",
         };
         PageMessage {
             level,
@@ -24,6 +29,18 @@ impl PageMessage {
     }
 }
 
+impl From<Message> for PageMessage {
+    fn from(message: Message) -> Self {
+        let level = match message.level {
+            Level::Debug | Level::Info => MessageLevel::Info,
+            Level::Success => MessageLevel::Success,
+            Level::Warning => MessageLevel::Warning,
+            Level::Error => MessageLevel::Error,
+        };
+        PageMessage::new(level, message.message)
+    }
+}
+
 #[derive(Serialize, Clone)]
 pub struct PageMessages(Vec<PageMessage>);
 
@@ -35,4 +52,14 @@ impl PageMessages {
     pub fn add(&mut self, message: PageMessage) {
         self.0.push(message);
     }
+
+    /// Messages flashed before a redirect, through the `Messages` extractor,
+    /// followed by the ones added to the page being rendered. Taking the
+    /// flashed messages removes them from the session.
+    pub fn with_flashed(flashed: Messages, messages: Option<PageMessages>) -> Option<PageMessages> {
+        let mut all: Vec<PageMessage> = flashed.map(PageMessage::from).collect();
+        all.extend(messages.map(|messages| messages.0).unwrap_or_default());
+
+        Some(PageMessages(all)).filter(|messages| !messages.0.is_empty())
+    }
 }
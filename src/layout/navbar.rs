@@ -8,10 +8,10 @@ pub struct NavbarTemplateData {
 }
 
 impl NavbarTemplateData {
-    pub fn new(is_signed_in: bool) -> Self {
+    pub fn new(is_signed_in: bool, signup_open: bool) -> Self {
         Self {
             login_visible: !is_signed_in,
-            signup_visible: !is_signed_in,
+            signup_visible: !is_signed_in && signup_open,
             logout_visible: is_signed_in,
         }
     }
@@ -19,6 +19,6 @@ impl NavbarTemplateData {
 
 impl Default for NavbarTemplateData {
     fn default() -> Self {
-        Self::new(false)
+        Self::new(false, true)
     }
 }
@@ -8,12 +8,16 @@ use axum::{
 use serde::Serialize;
 use serde_json::Value;
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 pub struct PageTemplate {
     navbar: Option<NavbarTemplateData>,
     content: Option<Value>,
     messages: Option<PageMessages>,
+    permissions: Vec<String>,
+    csrf_token: Option<String>,
     template_name: String,
+    #[serde(skip)]
+    layout: &'static str,
 }
 
 impl PageTemplate {
@@ -22,10 +26,27 @@ impl PageTemplate {
     }
 
     pub fn render(&self, template_engine: &TemplateEngine) -> Response {
-        match template_engine.render("layout/page", self) {
+        match template_engine.render(self.layout, self) {
             Ok(contents) => Html(contents).into_response(),
             Err(e) => {
                 tracing::error!("Failed to render template: {}", e);
+                self.render_error_page(template_engine)
+            }
+        }
+    }
+
+    /// The `errors/500` page in place of a partial that failed to render,
+    /// or plain text when the layout itself is broken.
+    fn render_error_page(&self, template_engine: &TemplateEngine) -> Response {
+        let error_page = PageTemplate {
+            template_name: "errors/500".to_string(),
+            content: None,
+            ..self.clone()
+        };
+        match template_engine.render(error_page.layout, &error_page) {
+            Ok(contents) => (StatusCode::INTERNAL_SERVER_ERROR, Html(contents)).into_response(),
+            Err(e) => {
+                tracing::error!("Failed to render error page: {}", e);
                 (
                     StatusCode::INTERNAL_SERVER_ERROR,
                     "Failed to render page template",
@@ -41,6 +62,9 @@ pub struct PageTemplateBuilder {
     content: Option<Value>,
     navbar: Option<NavbarTemplateData>,
     messages: Option<PageMessages>,
+    permissions: Vec<String>,
+    csrf_token: Option<String>,
+    layout: &'static str,
 }
 
 impl PageTemplateBuilder {
@@ -50,16 +74,26 @@ impl PageTemplateBuilder {
             content: None,
             navbar: None,
             messages: None,
+            permissions: Vec::new(),
+            csrf_token: None,
+            layout: "layout/page",
         }
     }
 
+    /// The template wrapping the partial, the whole `layout/page` unless
+    /// htmx only needs part of it.
+    pub fn layout(mut self, layout: &'static str) -> Self {
+        self.layout = layout;
+        self
+    }
+
     pub fn maybe_content(mut self, content: Option<Value>) -> Self {
         self.content = content;
         self
     }
 
-    pub fn navbar(mut self, is_signed_in: bool) -> Self {
-        self.navbar = Some(NavbarTemplateData::new(is_signed_in));
+    pub fn navbar(mut self, is_signed_in: bool, signup_open: bool) -> Self {
+        self.navbar = Some(NavbarTemplateData::new(is_signed_in, signup_open));
         self
     }
 
@@ -68,12 +102,28 @@ impl PageTemplateBuilder {
         self
     }
 
+    /// Permissions of the current user, checked with the `has_permission`
+    /// helper.
+    pub fn permissions(mut self, permissions: Vec<String>) -> Self {
+        self.permissions = permissions;
+        self
+    }
+
+    /// Token embedded in forms by the `form/csrf` partial.
+    pub fn maybe_csrf_token(mut self, csrf_token: Option<String>) -> Self {
+        self.csrf_token = csrf_token;
+        self
+    }
+
     pub fn build(self) -> PageTemplate {
         PageTemplate {
             navbar: self.navbar,
             content: self.content,
             messages: self.messages,
+            permissions: self.permissions,
+            csrf_token: self.csrf_token,
             template_name: self.template_name,
+            layout: self.layout,
         }
     }
 }
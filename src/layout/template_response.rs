@@ -1,12 +1,23 @@
 use axum::{
     extract::State,
+    http::{
+        header::{ACCEPT, VARY},
+        HeaderMap, HeaderName, HeaderValue, StatusCode,
+    },
     response::{IntoResponse, Response},
-    Extension,
+    Extension, Json,
 };
+use axum_messages::Messages;
 use serde::Serialize;
 use serde_json::Value;
+use tower_sessions::Session;
 
-use crate::{app::AppState, auth::layer::AuthSession};
+use crate::{
+    app::AppState,
+    auth::{authz::Permissions, csrf, layer::AuthSession},
+    config::RegistrationMode,
+    templates::uses_csrf_token,
+};
 
 use super::{
     messages::{MessageLevel, PageMessage, PageMessages},
@@ -16,19 +27,28 @@ use super::{
 #[derive(Clone)]
 pub struct TemplateResponse {
     partial_name: String,
+    status: StatusCode,
     content: Option<Value>,
     messages: Option<PageMessages>,
+    htmx_headers: HeaderMap,
 }
 
 impl TemplateResponse {
     pub fn new(partial_name: impl Into<String>) -> Self {
         Self {
             partial_name: partial_name.into(),
+            status: StatusCode::OK,
             content: None,
             messages: None,
+            htmx_headers: HeaderMap::new(),
         }
     }
 
+    pub fn status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+
     pub fn content(mut self, content: impl Serialize) -> Self {
         self.content = serde_json::to_value(content).ok();
         self
@@ -48,6 +68,59 @@ impl TemplateResponse {
         self.push_message(MessageLevel::Error, message);
         self
     }
+
+    fn set_htmx_header(&mut self, name: &'static str, value: &str) {
+        let name = HeaderName::from_static(name);
+        match HeaderValue::from_str(value) {
+            Ok(value) => {
+                self.htmx_headers.insert(name, value);
+            }
+            Err(_) => tracing::warn!("Ignored invalid {} header: {:?}", name, value),
+        }
+    }
+
+    /// Triggers a client-side event once htmx receives the response. Each
+    /// call adds the event to the ones already triggered.
+    pub fn hx_trigger(mut self, event: &str) -> Self {
+        let events = match self
+            .htmx_headers
+            .get("hx-trigger")
+            .and_then(|value| value.to_str().ok())
+        {
+            Some(events) => format!("{}, {}", events, event),
+            None => event.to_string(),
+        };
+        self.set_htmx_header("hx-trigger", &events);
+        self
+    }
+
+    /// Makes htmx load the URL as a full page instead of swapping content.
+    pub fn hx_redirect(mut self, url: &str) -> Self {
+        self.set_htmx_header("hx-redirect", url);
+        self
+    }
+
+    /// The URL to show in the address bar, for forms posted to another URL
+    /// than the page they render.
+    pub fn hx_push_url(mut self, url: &str) -> Self {
+        self.set_htmx_header("hx-push-url", url);
+        self
+    }
+
+    /// Swaps the response into the element matching the CSS selector instead
+    /// of the request's target.
+    pub fn hx_retarget(mut self, selector: &str) -> Self {
+        self.set_htmx_header("hx-retarget", selector);
+        self
+    }
+}
+
+#[cfg(test)]
+impl TemplateResponse {
+    /// The status the template layer will send the page with.
+    pub fn status_code(&self) -> StatusCode {
+        self.status
+    }
 }
 
 impl IntoResponse for TemplateResponse {
@@ -56,9 +129,67 @@ impl IntoResponse for TemplateResponse {
     }
 }
 
+/// The JSON counterpart of a page, for clients that prefer it to HTML.
+#[derive(Serialize)]
+struct JsonPage {
+    content: Option<Value>,
+    messages: PageMessages,
+}
+
+/// The layout for an htmx request: the contents of the body when the whole
+/// body is swapped, as for boosted links and forms, and only the partial
+/// when swapping into another element, with out-of-band swaps keeping the
+/// navbar, messages and CSRF token current. Restoring history needs the
+/// whole page.
+fn htmx_layout(headers: &HeaderMap, template_response: &TemplateResponse) -> &'static str {
+    if !headers.contains_key("hx-request") || headers.contains_key("hx-history-restore-request") {
+        return "layout/page";
+    }
+
+    let target = template_response
+        .htmx_headers
+        .get("hx-retarget")
+        .or_else(|| headers.get("hx-target"))
+        .and_then(|value| value.to_str().ok());
+    match target {
+        None | Some("body") => "layout/htmx_body",
+        Some(_) => "layout/htmx_partial",
+    }
+}
+
+/// Whether the `Accept` header ranks `application/json` above `text/html`.
+/// Browsers list HTML first, so they keep getting pages.
+fn prefers_json(headers: &HeaderMap) -> bool {
+    let Some(accept) = headers.get(ACCEPT).and_then(|value| value.to_str().ok()) else {
+        return false;
+    };
+
+    let mut json_quality = 0.0;
+    let mut html_quality = 0.0;
+    for media_range in accept.split(',') {
+        let mut params = media_range.split(';').map(str::trim);
+        let media_type = params.next().unwrap_or_default().to_ascii_lowercase();
+        let quality = params
+            .find_map(|param| param.strip_prefix("q="))
+            .and_then(|quality| quality.parse::<f32>().ok())
+            .unwrap_or(1.0);
+
+        match media_type.as_str() {
+            "application/json" => json_quality = f32::max(json_quality, quality),
+            "text/html" => html_quality = f32::max(html_quality, quality),
+            _ => {}
+        }
+    }
+
+    json_quality > 0.0 && json_quality > html_quality
+}
+
 struct TemplateStateWrapper {
     app_state: AppState,
+    layout: &'static str,
     auth_session: AuthSession,
+    permissions: Permissions,
+    csrf_token: Option<String>,
     template_response: TemplateResponse,
 }
 
@@ -66,31 +197,108 @@ impl IntoResponse for TemplateStateWrapper {
     fn into_response(self) -> Response {
         let template_engine = self.app_state.template_engine;
         let is_signed_in = self.auth_session.user.is_some();
-        PageTemplate::builder(self.template_response.partial_name)
+        let signup_open = self.app_state.config.registration_mode == RegistrationMode::Open;
+        let mut response = PageTemplate::builder(self.template_response.partial_name)
+            .layout(self.layout)
             .maybe_content(self.template_response.content)
-            .navbar(is_signed_in)
+            .navbar(is_signed_in, signup_open)
             .maybe_messages(self.template_response.messages)
+            .permissions(self.permissions.names())
+            .maybe_csrf_token(self.csrf_token)
             .build()
-            .render(&template_engine)
+            .render(&template_engine);
+        if response.status().is_success() {
+            *response.status_mut() = self.template_response.status;
+            response
+                .headers_mut()
+                .extend(self.template_response.htmx_headers);
+        }
+
+        response
     }
 }
 
 pub async fn with_template_response(
     State(app_state): State<AppState>,
     auth_session: AuthSession,
+    session: Session,
+    flashed: Messages,
+    headers: HeaderMap,
     response: Response,
 ) -> Response {
-    let response = match response.extensions().get::<TemplateResponse>() {
-        Some(template_response) => {
+    let mut response = match response.extensions().get::<TemplateResponse>() {
+        Some(template_response) if prefers_json(&headers) => {
             let template_response = template_response.to_owned();
+            let status = template_response.status;
+            let page = JsonPage {
+                content: template_response.content,
+                messages: PageMessages::with_flashed(flashed, template_response.messages)
+                    .unwrap_or_else(PageMessages::new),
+            };
+            (status, Json(page)).into_response()
+        }
+        Some(template_response) => {
+            let mut template_response = template_response.to_owned();
+            template_response.messages =
+                PageMessages::with_flashed(flashed, template_response.messages);
+            let layout = htmx_layout(&headers, &template_response);
+            let permissions = Permissions::load(&auth_session).await;
+            // Keeps anonymous visitors of pages without forms from getting a
+            // session.
+            let has_forms =
+                uses_csrf_token(&app_state.template_engine, &template_response.partial_name);
+            let csrf_token = if has_forms {
+                csrf::session_token(&session).await.map(Some)
+            } else {
+                csrf::existing_token(&session).await
+            };
+            let csrf_token = csrf_token.unwrap_or_else(|e| {
+                tracing::error!("Failed to load CSRF token: {:?}", e);
+                None
+            });
             TemplateStateWrapper {
                 app_state,
+                layout,
                 auth_session,
+                permissions,
+                csrf_token,
                 template_response,
-            }.into_response()
+            }
+            .into_response()
         }
-        None => response,
+        None => return response,
     };
+    // Every representation comes from the same URL.
+    response.headers_mut().append(
+        VARY,
+        HeaderValue::from_static("accept, hx-request, hx-target"),
+    );
 
     response
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hx_trigger_adds_to_the_triggered_events() {
+        let response = TemplateResponse::new("page")
+            .hx_trigger("saved")
+            .hx_trigger("closeModal");
+
+        assert_eq!(response.htmx_headers["hx-trigger"], "saved, closeModal");
+    }
+
+    #[test]
+    fn htmx_headers_are_set() {
+        let response = TemplateResponse::new("page")
+            .hx_redirect("/login")
+            .hx_push_url("/user/sessions")
+            .hx_retarget("body");
+
+        assert_eq!(response.htmx_headers["hx-redirect"], "/login");
+        assert_eq!(response.htmx_headers["hx-push-url"], "/user/sessions");
+        assert_eq!(response.htmx_headers["hx-retarget"], "body");
+    }
+}
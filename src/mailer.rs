@@ -0,0 +1,91 @@
+use std::{env, path::PathBuf, sync::Arc};
+
+use async_trait::async_trait;
+use thiserror::Error;
+use tower_sessions::cookie::time::OffsetDateTime;
+
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Error, Debug)]
+pub enum MailerError {
+    #[error("Failed to write email")]
+    Io(#[from] std::io::Error),
+}
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: Email) -> Result<(), MailerError>;
+}
+
+pub type SharedMailer = Arc<dyn Mailer>;
+
+/// Writes outgoing emails to the application log, for local development.
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, email: Email) -> Result<(), MailerError> {
+        tracing::info!(
+            "Email to {} with subject {:?}:\n{}",
+            email.to,
+            email.subject,
+            email.body
+        );
+
+        Ok(())
+    }
+}
+
+/// Writes every outgoing email as a file in a directory, so the delivered
+/// messages can be inspected offline.
+pub struct FileMailer {
+    directory: PathBuf,
+}
+
+impl FileMailer {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: Email) -> Result<(), MailerError> {
+        tokio::fs::create_dir_all(&self.directory).await?;
+
+        let recipient: String = email
+            .to
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let file_name = format!(
+            "{}-{}.eml",
+            OffsetDateTime::now_utc().unix_timestamp_nanos(),
+            recipient
+        );
+        let contents = format!(
+            "To: {}\r\nSubject: {}\r\n\r\n{}",
+            email.to, email.subject, email.body
+        );
+        tokio::fs::write(self.directory.join(file_name), contents).await?;
+
+        Ok(())
+    }
+}
+
+pub fn build_mailer() -> SharedMailer {
+    match env::var("MAILER_TRANSPORT").as_deref() {
+        Ok("file") => {
+            let directory = env::var("MAILER_DIRECTORY").unwrap_or_else(|_| "mail".to_string());
+            Arc::new(FileMailer::new(directory))
+        }
+        _ => Arc::new(LogMailer),
+    }
+}
@@ -1,7 +1,16 @@
+use std::net::SocketAddr;
+
+mod admin;
+mod api;
 mod app;
+mod audit;
 mod auth;
+mod cli;
+mod config;
 mod database;
+mod error;
 mod layout;
+mod mailer;
 mod templates;
 mod user;
 
@@ -11,13 +20,31 @@ async fn main() {
         .with_max_level(tracing::Level::DEBUG)
         .init();
 
-    dotenvy::dotenv().unwrap();
+    // Deployments set the environment directly, .env is for local development.
+    dotenvy::dotenv().ok();
 
     let database_connection = database::connect().await;
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(cli::run(&database_connection, &args).await);
+    }
+
+    tokio::spawn(user::db_user_account::run_purge_task(
+        database_connection.clone(),
+    ));
+
     let template_engine = templates::build_template_engine().unwrap();
-    let app = app::create_app(template_engine, database_connection);
+    let config = config::Config::from_env();
+    let mailer = mailer::build_mailer();
+    let app = app::create_app(template_engine, database_connection, config, mailer);
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
     tracing::debug!("Listening on {}", listener.local_addr().unwrap());
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
 }
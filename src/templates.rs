@@ -1,15 +1,176 @@
-use handlebars::{DirectorySourceOptions, Handlebars, TemplateError};
+use handlebars::{
+    handlebars_helper,
+    template::{Template, TemplateElement},
+    Context, Handlebars, Helper, HelperDef, RenderContext, RenderErrorReason, ScopedJson,
+    TemplateError,
+};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use rust_embed::RustEmbed;
+use serde_json::json;
+use thiserror::Error;
 
 pub type TemplateEngine = Handlebars<'static>;
 
-pub fn build_template_engine() -> Result<TemplateEngine, TemplateError> {
+/// Layouts the pages are rendered with, see `PageTemplate`.
+const LAYOUTS: [&str; 3] = ["layout/page", "layout/htmx_body", "layout/htmx_partial"];
+
+/// The `templates/` directory. Release builds, and debug builds with the
+/// `embed-templates` feature, carry it in the binary so they run from any
+/// directory. Other debug builds read it from disk on every render.
+#[derive(RustEmbed)]
+#[folder = "templates/"]
+#[include = "*.hbs"]
+struct Templates;
+
+/// Partials that submit the CSRF token, as a form field or from the
+/// `csrf-token` meta tag.
+const CSRF_PARTIALS: [&str; 2] = ["form/csrf", "auth/passkey_script"];
+
+#[derive(Error, Debug)]
+pub enum TemplateEngineError {
+    #[error(transparent)]
+    Template(#[from] TemplateError),
+    #[error("Template {template} uses the missing partial {partial}")]
+    MissingPartial { template: String, partial: String },
+}
+
+/// `{{#if (has_permission "users.manage")}}` is true when the current user
+/// has the permission. It reads the `permissions` of the page being rendered,
+/// so it works the same inside partials.
+struct HasPermissionHelper;
+
+impl HelperDef for HasPermissionHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        ctx: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, handlebars::RenderError> {
+        let permission = h.param(0).and_then(|param| param.value().as_str()).ok_or(
+            RenderErrorReason::ParamNotFoundForIndex("has_permission", 0),
+        )?;
+        let has_permission = ctx.data()["permissions"]
+            .as_array()
+            .is_some_and(|permissions| permissions.iter().any(|name| name == permission));
+
+        Ok(ScopedJson::Derived(json!(has_permission)))
+    }
+}
+
+// `{{ url_encode next_url }}` escapes a value for use in a query string.
+handlebars_helper!(url_encode: |value: str| utf8_percent_encode(value, NON_ALPHANUMERIC).to_string());
+
+pub fn build_template_engine() -> Result<TemplateEngine, TemplateEngineError> {
     let mut handlebars = Handlebars::new();
     if cfg!(debug_assertions) {
         handlebars.set_dev_mode(true);
     }
-    let options = DirectorySourceOptions::default();
 
-    handlebars.register_templates_directory("templates/", options)?;
+    handlebars.register_embed_templates_with_extension::<Templates>(".hbs")?;
+    handlebars.register_helper("has_permission", Box::new(HasPermissionHelper));
+    handlebars.register_helper("url_encode", Box::new(url_encode));
+
+    for layout in LAYOUTS {
+        check_partials(&handlebars, layout, layout)?;
+    }
 
     Ok(handlebars)
 }
+
+/// Fails when `template`, or a partial it includes, includes a partial that
+/// isn't registered, instead of when a page is first rendered. Partials
+/// picked at render time, like the page's own, can't be checked.
+fn check_partials(
+    handlebars: &TemplateEngine,
+    parent: &str,
+    template: &str,
+) -> Result<(), TemplateEngineError> {
+    let Some(compiled) = handlebars.get_template(template) else {
+        return Err(TemplateEngineError::MissingPartial {
+            template: parent.to_string(),
+            partial: template.to_string(),
+        });
+    };
+
+    let mut partials = Vec::new();
+    collect_partials(compiled, &mut partials);
+    for partial in partials {
+        check_partials(handlebars, template, partial)?;
+    }
+
+    Ok(())
+}
+
+/// Whether `template`, or a partial it includes, submits the CSRF token, so
+/// pages without forms don't need one issued.
+pub fn uses_csrf_token(handlebars: &TemplateEngine, template: &str) -> bool {
+    if CSRF_PARTIALS.contains(&template) {
+        return true;
+    }
+    let Some(compiled) = handlebars.get_template(template) else {
+        return false;
+    };
+
+    let mut partials = Vec::new();
+    collect_partials(compiled, &mut partials);
+    partials
+        .into_iter()
+        .any(|partial| uses_csrf_token(handlebars, partial))
+}
+
+fn collect_partials<'a>(template: &'a Template, partials: &mut Vec<&'a str>) {
+    for element in &template.elements {
+        match element {
+            TemplateElement::PartialExpression(partial)
+            | TemplateElement::PartialBlock(partial) => {
+                if let Some(name) = partial.name.as_name() {
+                    partials.push(name);
+                }
+                if let Some(block) = &partial.template {
+                    collect_partials(block, partials);
+                }
+            }
+            TemplateElement::HelperBlock(helper) => {
+                for block in [&helper.template, &helper.inverse].into_iter().flatten() {
+                    collect_partials(block, partials);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layouts_only_use_existing_partials() {
+        build_template_engine().unwrap();
+    }
+
+    #[test]
+    fn pages_with_forms_use_csrf_token() {
+        let handlebars = build_template_engine().unwrap();
+
+        assert!(uses_csrf_token(&handlebars, "auth/login"));
+        assert!(uses_csrf_token(&handlebars, "user/security"));
+        assert!(!uses_csrf_token(&handlebars, "errors/404"));
+    }
+
+    #[test]
+    fn missing_partial_is_reported() {
+        let mut handlebars = Handlebars::new();
+        handlebars
+            .register_template_string("page", "{{#if navbar}}{{> navbar }}{{/if}}")
+            .unwrap();
+
+        let error = check_partials(&handlebars, "page", "page").unwrap_err();
+        assert!(matches!(
+            error,
+            TemplateEngineError::MissingPartial { template, partial }
+                if template == "page" && partial == "navbar"
+        ));
+    }
+}
@@ -1,3 +1,11 @@
-mod db_user_profile;
-mod profile_page;
+mod account_page;
+mod activity_page;
+pub mod db_user_account;
+pub mod db_user_profile;
+pub mod db_user_session;
+mod passkeys;
+pub mod profile_page;
 pub mod router;
+mod security_page;
+pub mod sessions_page;
+mod tokens_page;
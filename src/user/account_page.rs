@@ -0,0 +1,220 @@
+use std::io::Write;
+
+use axum::{
+    extract::{Query, State},
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_login::AuthUser;
+use serde::{Deserialize, Serialize};
+use tower_sessions::cookie::time::{Duration, OffsetDateTime};
+use validator::ValidationErrors;
+use zip::{write::FileOptions, ZipWriter};
+
+use super::{db_user_account, security_page::check_current_password};
+use crate::{
+    app::AppState,
+    auth::{client_ip::ClientIp, db_user, layer::AuthSession},
+    error::AppError,
+    layout::template_response::TemplateResponse,
+    mailer::Email,
+};
+
+fn format_date(timestamp: i64) -> String {
+    OffsetDateTime::from_unix_timestamp(timestamp)
+        .map(|date_time| date_time.date().to_string())
+        .unwrap_or_default()
+}
+
+#[derive(Serialize, Default)]
+pub struct AccountPage {
+    deletion_scheduled_on: Option<String>,
+    grace_days: i64,
+    errors: Option<ValidationErrors>,
+}
+
+impl AccountPage {
+    async fn load(app: &AppState, user_id: i32) -> Self {
+        let user = db_user::get_user_by_id(&app.database_connection, user_id).await;
+
+        Self {
+            deletion_scheduled_on: user
+                .and_then(|user| user.deletion_scheduled_at)
+                .map(format_date),
+            grace_days: app.config.account_deletion_grace_days,
+            errors: None,
+        }
+    }
+}
+
+pub async fn get_account_page(
+    State(app): State<AppState>,
+    auth_session: AuthSession,
+) -> Result<Response, AppError> {
+    let user = auth_session.user.ok_or(AppError::Unauthenticated)?;
+
+    Ok(TemplateResponse::new("user/account")
+        .content(AccountPage::load(&app, user.id()).await)
+        .into_response())
+}
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    format: Option<String>,
+}
+
+fn zip_archive(file_name: &str, contents: &[u8]) -> zip::result::ZipResult<Vec<u8>> {
+    let mut archive = ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    archive.start_file(file_name, FileOptions::default())?;
+    archive.write_all(contents)?;
+
+    Ok(archive.finish()?.into_inner())
+}
+
+pub async fn get_account_export(
+    State(app): State<AppState>,
+    auth_session: AuthSession,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, AppError> {
+    let user = auth_session.user.ok_or(AppError::Unauthenticated)?;
+    let response = TemplateResponse::new("user/account");
+
+    let export = match db_user_account::export_account(&app.database_connection, user.id()).await {
+        Ok(Some(export)) => export,
+        Ok(None) => return Ok(Redirect::to("/login").into_response()),
+        Err(e) => {
+            tracing::error!("Failed to export account: {:?}", e);
+            return Ok(response
+                .content(AccountPage::load(&app, user.id()).await)
+                .add_error_message("Failed to export your data, try again later")
+                .into_response());
+        }
+    };
+    let json = serde_json::to_vec_pretty(&export).unwrap_or_default();
+
+    if query.format.as_deref() == Some("zip") {
+        return Ok(match zip_archive("account.json", &json) {
+            Ok(archive) => (
+                [
+                    (CONTENT_TYPE, "application/zip"),
+                    (CONTENT_DISPOSITION, "attachment; filename=\"account.zip\""),
+                ],
+                archive,
+            )
+                .into_response(),
+            Err(e) => {
+                tracing::error!("Failed to build export archive: {:?}", e);
+                response
+                    .content(AccountPage::load(&app, user.id()).await)
+                    .add_error_message("Failed to export your data, try again later")
+                    .into_response()
+            }
+        });
+    }
+
+    Ok((
+        [
+            (CONTENT_TYPE, "application/json"),
+            (CONTENT_DISPOSITION, "attachment; filename=\"account.json\""),
+        ],
+        json,
+    )
+        .into_response())
+}
+
+#[derive(Deserialize)]
+pub struct DeleteAccountForm {
+    password: String,
+}
+
+#[derive(Serialize)]
+pub struct AccountDeletedPage {
+    deletion_scheduled_on: Option<String>,
+}
+
+pub async fn post_delete_account(
+    State(app): State<AppState>,
+    mut auth_session: AuthSession,
+    ClientIp(ip): ClientIp,
+    Form(form): Form<DeleteAccountForm>,
+) -> Result<Response, AppError> {
+    let user = auth_session.user.clone().ok_or(AppError::Unauthenticated)?;
+    let response = TemplateResponse::new("user/account");
+
+    if let Err(errors) = check_current_password(&app, &user, &ip.to_string(), &form.password).await
+    {
+        let mut page = AccountPage::load(&app, user.id()).await;
+        page.errors = Some(errors);
+        return Ok(response.content(page).into_response());
+    }
+
+    let grace_days = app.config.account_deletion_grace_days;
+    let deletion_scheduled_at = if grace_days > 0 {
+        let delete_at = (OffsetDateTime::now_utc() + Duration::days(grace_days)).unix_timestamp();
+        db_user_account::schedule_deletion(&app.database_connection, user.id(), delete_at)
+            .await
+            .map(|()| Some(delete_at))
+    } else {
+        db_user_account::delete_account(&app.database_connection, user.id())
+            .await
+            .map(|()| None)
+    };
+    let deletion_scheduled_at = match deletion_scheduled_at {
+        Ok(deletion_scheduled_at) => deletion_scheduled_at,
+        Err(e) => {
+            tracing::error!("Failed to delete account: {:?}", e);
+            return Ok(response
+                .content(AccountPage::load(&app, user.id()).await)
+                .add_error_message("Failed to delete your account, try again later")
+                .into_response());
+        }
+    };
+
+    if let Some(delete_at) = deletion_scheduled_at {
+        let notice = Email {
+            to: user.email().to_string(),
+            subject: "Your account will be deleted".to_string(),
+            body: format!(
+                "Your account and all its data will be deleted on {}.\n\n\
+                Changed your mind? Log in and cancel the deletion from your account page: {}",
+                format_date(delete_at),
+                app.config.url("/user/account")
+            ),
+        };
+        if let Err(e) = app.mailer.send(notice).await {
+            tracing::error!("Failed to send account deletion notice: {:?}", e);
+        }
+    }
+
+    if let Err(e) = auth_session.logout().await {
+        tracing::error!("Failed to logout after account deletion: {:?}", e);
+    }
+
+    Ok(TemplateResponse::new("user/account_deleted")
+        .content(AccountDeletedPage {
+            deletion_scheduled_on: deletion_scheduled_at.map(format_date),
+        })
+        .into_response())
+}
+
+pub async fn post_cancel_deletion(
+    State(app): State<AppState>,
+    auth_session: AuthSession,
+) -> Result<Response, AppError> {
+    let user = auth_session.user.ok_or(AppError::Unauthenticated)?;
+    let response = TemplateResponse::new("user/account");
+
+    let response = match db_user_account::cancel_deletion(&app.database_connection, user.id()).await
+    {
+        Ok(()) => response.add_success_message("Your account will not be deleted"),
+        Err(e) => {
+            tracing::error!("Failed to cancel account deletion: {:?}", e);
+            response.add_error_message("Failed to cancel the deletion, try again later")
+        }
+    };
+
+    Ok(response
+        .content(AccountPage::load(&app, user.id()).await)
+        .into_response())
+}
@@ -0,0 +1,74 @@
+use axum::{
+    extract::State,
+    response::{IntoResponse, Response},
+};
+use axum_login::AuthUser;
+use serde::Serialize;
+
+use super::sessions_page::format_date_time;
+use crate::{
+    app::AppState,
+    audit::{action::Action, db_audit_event},
+    auth::layer::AuthSession,
+    error::AppError,
+    layout::template_response::TemplateResponse,
+};
+
+/// How many of the latest events are shown.
+const RECENT_EVENTS: u64 = 50;
+
+#[derive(Serialize)]
+pub struct ActivityEntry {
+    occurred_at: String,
+    description: String,
+    by_administrator: bool,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+}
+
+#[derive(Serialize, Default)]
+pub struct ActivityPage {
+    events: Vec<ActivityEntry>,
+}
+
+pub async fn get_activity_page(
+    State(app): State<AppState>,
+    auth_session: AuthSession,
+) -> Result<Response, AppError> {
+    let user = auth_session.user.ok_or(AppError::Unauthenticated)?;
+    let response = TemplateResponse::new("user/activity");
+
+    let events = match db_audit_event::recent_user_events(
+        &app.database_connection,
+        user.id(),
+        RECENT_EVENTS,
+    )
+    .await
+    {
+        Ok(events) => events,
+        Err(e) => {
+            tracing::error!("Failed to list account activity: {:?}", e);
+            return Ok(response
+                .content(ActivityPage::default())
+                .add_error_message("Failed to load your account activity, try again later")
+                .into_response());
+        }
+    };
+
+    Ok(response
+        .content(ActivityPage {
+            events: events
+                .into_iter()
+                .map(|event| ActivityEntry {
+                    occurred_at: format_date_time(event.created_at),
+                    description: Action::from_name(&event.action)
+                        .map(|action| action.label().to_string())
+                        .unwrap_or(event.action),
+                    by_administrator: event.actor_id.is_some_and(|actor_id| actor_id != user.id()),
+                    ip_address: event.ip_address,
+                    user_agent: event.user_agent,
+                })
+                .collect(),
+        })
+        .into_response())
+}
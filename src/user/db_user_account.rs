@@ -0,0 +1,472 @@
+use std::time::Duration;
+
+use entity::{
+    api_token, email_verification_token, invitation, magic_link_token, password_reset_token,
+    recovery_code, session, user, user_identity, user_profile, user_role, webauthn_credential,
+};
+use sea_orm::{
+    sea_query::{Expr, Func},
+    ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter,
+    QueryOrder, TransactionTrait,
+};
+use serde::Serialize;
+use tower_sessions::cookie::time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use super::db_user_session;
+use crate::{
+    audit::db_audit_event::{self, AuditFilter},
+    auth::db_login_throttle,
+};
+
+/// How often accounts whose grace period is over are looked for.
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+fn format_timestamp(timestamp: i64) -> String {
+    OffsetDateTime::from_unix_timestamp(timestamp)
+        .ok()
+        .and_then(|date_time| date_time.format(&Rfc3339).ok())
+        .unwrap_or_default()
+}
+
+#[derive(Serialize)]
+pub struct UserExport {
+    id: i32,
+    email: String,
+    email_verified_at: Option<String>,
+    two_factor_enabled: bool,
+    deletion_scheduled_at: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ProfileExport {
+    display_name: String,
+}
+
+#[derive(Serialize)]
+pub struct SessionExport {
+    created_at: String,
+    last_seen_at: String,
+    expires_at: String,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct LinkedAccountExport {
+    provider: String,
+    subject: String,
+    email: Option<String>,
+    created_at: String,
+}
+
+#[derive(Serialize)]
+pub struct PasskeyExport {
+    name: String,
+    created_at: String,
+    last_used_at: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ApiTokenExport {
+    name: String,
+    scopes: String,
+    created_at: String,
+    expires_at: Option<String>,
+    last_used_at: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct AuditEventExport {
+    action: String,
+    created_at: String,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+}
+
+/// Everything stored about a user. Password hashes, secrets and session ids
+/// are credentials rather than personal data, so only their presence shows.
+#[derive(Serialize)]
+pub struct AccountExport {
+    exported_at: String,
+    user: UserExport,
+    profile: Option<ProfileExport>,
+    sessions: Vec<SessionExport>,
+    linked_accounts: Vec<LinkedAccountExport>,
+    passkeys: Vec<PasskeyExport>,
+    api_tokens: Vec<ApiTokenExport>,
+    audit_events: Vec<AuditEventExport>,
+}
+
+pub async fn export_account(
+    db: &DatabaseConnection,
+    user_id: i32,
+) -> Result<Option<AccountExport>, DbErr> {
+    let Some(user) = user::Entity::find_by_id(user_id).one(db).await? else {
+        return Ok(None);
+    };
+    let profile = user_profile::Entity::find_by_id(user_id).one(db).await?;
+    let sessions = session::Entity::find()
+        .filter(session::Column::UserId.eq(user_id))
+        .order_by_asc(session::Column::CreatedAt)
+        .all(db)
+        .await?;
+    let identities = user_identity::Entity::find()
+        .filter(user_identity::Column::UserId.eq(user_id))
+        .order_by_asc(user_identity::Column::CreatedAt)
+        .all(db)
+        .await?;
+    let credentials = webauthn_credential::Entity::find()
+        .filter(webauthn_credential::Column::UserId.eq(user_id))
+        .order_by_asc(webauthn_credential::Column::CreatedAt)
+        .all(db)
+        .await?;
+    let api_tokens = api_token::Entity::find()
+        .filter(api_token::Column::UserId.eq(user_id))
+        .order_by_asc(api_token::Column::CreatedAt)
+        .all(db)
+        .await?;
+    // The same events as the account activity page, done by or to the user.
+    let audit_events = db_audit_event::all_events(
+        db,
+        &AuditFilter {
+            user_id: Some(user_id),
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    Ok(Some(AccountExport {
+        exported_at: format_timestamp(OffsetDateTime::now_utc().unix_timestamp()),
+        user: UserExport {
+            id: user.id,
+            email: user.email,
+            email_verified_at: user.email_verified_at.map(format_timestamp),
+            two_factor_enabled: user.totp_secret.is_some(),
+            deletion_scheduled_at: user.deletion_scheduled_at.map(format_timestamp),
+        },
+        profile: profile.map(|profile| ProfileExport {
+            display_name: profile.display_name,
+        }),
+        sessions: sessions
+            .into_iter()
+            .map(|session| SessionExport {
+                created_at: format_timestamp(session.created_at),
+                last_seen_at: format_timestamp(session.last_seen_at),
+                expires_at: format_timestamp(session.expiry.into()),
+                ip_address: session.ip_address,
+                user_agent: session.user_agent,
+            })
+            .collect(),
+        linked_accounts: identities
+            .into_iter()
+            .map(|identity| LinkedAccountExport {
+                provider: identity.provider,
+                subject: identity.subject,
+                email: identity.email,
+                created_at: format_timestamp(identity.created_at),
+            })
+            .collect(),
+        passkeys: credentials
+            .into_iter()
+            .map(|credential| PasskeyExport {
+                name: credential.name,
+                created_at: format_timestamp(credential.created_at),
+                last_used_at: credential.last_used_at.map(format_timestamp),
+            })
+            .collect(),
+        api_tokens: api_tokens
+            .into_iter()
+            .map(|api_token| ApiTokenExport {
+                name: api_token.name,
+                scopes: api_token.scopes,
+                created_at: format_timestamp(api_token.created_at),
+                expires_at: api_token.expires_at.map(format_timestamp),
+                last_used_at: api_token.last_used_at.map(format_timestamp),
+            })
+            .collect(),
+        audit_events: audit_events
+            .into_iter()
+            .map(|event| AuditEventExport {
+                action: event.action,
+                created_at: format_timestamp(event.created_at),
+                ip_address: event.ip_address,
+                user_agent: event.user_agent,
+            })
+            .collect(),
+    }))
+}
+
+async fn delete_user_data<C: ConnectionTrait>(db: &C, user: user::Model) -> Result<(), DbErr> {
+    user_profile::Entity::delete_by_id(user.id).exec(db).await?;
+    session::Entity::delete_many()
+        .filter(session::Column::UserId.eq(user.id))
+        .exec(db)
+        .await?;
+    password_reset_token::Entity::delete_many()
+        .filter(password_reset_token::Column::UserId.eq(user.id))
+        .exec(db)
+        .await?;
+    magic_link_token::Entity::delete_many()
+        .filter(magic_link_token::Column::UserId.eq(user.id))
+        .exec(db)
+        .await?;
+    email_verification_token::Entity::delete_many()
+        .filter(email_verification_token::Column::UserId.eq(user.id))
+        .exec(db)
+        .await?;
+    recovery_code::Entity::delete_many()
+        .filter(recovery_code::Column::UserId.eq(user.id))
+        .exec(db)
+        .await?;
+    webauthn_credential::Entity::delete_many()
+        .filter(webauthn_credential::Column::UserId.eq(user.id))
+        .exec(db)
+        .await?;
+    user_identity::Entity::delete_many()
+        .filter(user_identity::Column::UserId.eq(user.id))
+        .exec(db)
+        .await?;
+    user_role::Entity::delete_many()
+        .filter(user_role::Column::UserId.eq(user.id))
+        .exec(db)
+        .await?;
+    api_token::Entity::delete_many()
+        .filter(api_token::Column::UserId.eq(user.id))
+        .exec(db)
+        .await?;
+    // Addresses on invitations are compared ignoring case when they are used.
+    invitation::Entity::delete_many()
+        .filter(
+            Condition::any()
+                .add(invitation::Column::InviterId.eq(user.id))
+                .add(
+                    Expr::expr(Func::lower(Expr::col(invitation::Column::Email)))
+                        .eq(user.email.to_lowercase()),
+                ),
+        )
+        .exec(db)
+        .await?;
+    db_login_throttle::unlock_account(db, &user.email).await?;
+    user::Entity::delete_by_id(user.id).exec(db).await?;
+
+    Ok(())
+}
+
+/// Removes the user and everything stored about them in a single
+/// transaction.
+pub async fn delete_account(db: &DatabaseConnection, user_id: i32) -> Result<(), DbErr> {
+    let txn = db.begin().await?;
+
+    if let Some(user) = user::Entity::find_by_id(user_id).one(&txn).await? {
+        delete_user_data(&txn, user).await?;
+    }
+
+    txn.commit().await
+}
+
+/// Marks the account for deletion at the given time and signs it out
+/// everywhere. Until then the user can log in and cancel.
+pub async fn schedule_deletion(
+    db: &DatabaseConnection,
+    user_id: i32,
+    delete_at: i64,
+) -> Result<(), DbErr> {
+    let txn = db.begin().await?;
+
+    user::Entity::update_many()
+        .col_expr(user::Column::DeletionScheduledAt, Expr::value(delete_at))
+        .filter(user::Column::Id.eq(user_id))
+        .exec(&txn)
+        .await?;
+    db_user_session::delete_all_sessions(&txn, user_id).await?;
+
+    txn.commit().await
+}
+
+pub async fn cancel_deletion(db: &DatabaseConnection, user_id: i32) -> Result<(), DbErr> {
+    user::Entity::update_many()
+        .col_expr(
+            user::Column::DeletionScheduledAt,
+            Expr::value(Option::<i64>::None),
+        )
+        .filter(user::Column::Id.eq(user_id))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+/// Deletes the accounts whose grace period is over, returning how many were
+/// deleted.
+pub async fn purge_scheduled_deletions(db: &DatabaseConnection) -> Result<u64, DbErr> {
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let users = user::Entity::find()
+        .filter(user::Column::DeletionScheduledAt.lte(now))
+        .all(db)
+        .await?;
+
+    let mut deleted = 0;
+    for user in users {
+        let txn = db.begin().await?;
+        // The user may have cancelled since the accounts were listed.
+        let user = user::Entity::find_by_id(user.id)
+            .filter(user::Column::DeletionScheduledAt.lte(now))
+            .one(&txn)
+            .await?;
+        if let Some(user) = user {
+            delete_user_data(&txn, user).await?;
+            deleted += 1;
+        }
+        txn.commit().await?;
+    }
+
+    Ok(deleted)
+}
+
+/// Runs `purge_scheduled_deletions` periodically for as long as the server
+/// is up.
+pub async fn run_purge_task(db: DatabaseConnection) {
+    let mut interval = tokio::time::interval(PURGE_INTERVAL);
+    loop {
+        interval.tick().await;
+        match purge_scheduled_deletions(&db).await {
+            Ok(0) => {}
+            Ok(deleted) => tracing::info!("Deleted {} accounts after their grace period", deleted),
+            Err(e) => tracing::error!("Failed to delete scheduled accounts: {:?}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        audit::db_audit_event::NewAuditEvent,
+        auth::{
+            db_api_token, db_invitation, db_user, db_user_identity, db_webauthn,
+            webauthn::VerifiedCredential,
+        },
+        database::connect_in_memory,
+        user::db_user_profile,
+    };
+    use sea_orm::{ActiveModelTrait, Set};
+
+    async fn create_user(db: &DatabaseConnection, email: &str) -> i32 {
+        db_user::create_user(
+            db,
+            db_user::CreateUserData {
+                email: email.to_string(),
+                password: "correct horse battery staple".to_string(),
+                email_verified: true,
+            },
+        )
+        .await
+        .unwrap()
+        .id
+    }
+
+    async fn invite(db: &DatabaseConnection, inviter_id: i32, email: Option<&str>) {
+        db_invitation::create_invitation(
+            db,
+            db_invitation::NewInvitation {
+                inviter_id,
+                email: email.map(str::to_string),
+                expires_at: None,
+                max_uses: None,
+            },
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn deleting_account_removes_its_invitations() {
+        let db = connect_in_memory().await;
+        let user_id = create_user(&db, "user@example.com").await;
+        let other_id = create_user(&db, "other@example.com").await;
+        invite(&db, user_id, None).await;
+        invite(&db, other_id, Some("User@Example.com")).await;
+        invite(&db, other_id, Some("friend@example.com")).await;
+
+        delete_account(&db, user_id).await.unwrap();
+
+        let invitations = db_invitation::list_invitations(&db).await.unwrap();
+        assert_eq!(invitations.len(), 1);
+        assert_eq!(invitations[0].email.as_deref(), Some("friend@example.com"));
+    }
+
+    #[tokio::test]
+    async fn export_includes_every_table() {
+        let db = connect_in_memory().await;
+        let user_id = create_user(&db, "user@example.com").await;
+        db_user_profile::save_user_profile(
+            &db,
+            user_id,
+            db_user_profile::SaveUserProfileData {
+                display_name: "User".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        session::ActiveModel {
+            id: Set("session-id".to_string()),
+            data: Set("{}".to_string()),
+            expiry: Set(2_000_000_000),
+            user_id: Set(Some(user_id)),
+            created_at: Set(1_700_000_000),
+            last_seen_at: Set(1_700_000_000),
+            ip_address: Set(Some("203.0.113.7".to_string())),
+            user_agent: Set(Some("Firefox".to_string())),
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+        db_user_identity::create_identity(&db, user_id, "github", "42", None)
+            .await
+            .unwrap();
+        let credential = VerifiedCredential {
+            credential_id: "credential-id".to_string(),
+            public_key: "public-key".to_string(),
+            sign_count: 0,
+        };
+        db_webauthn::create_credential(&db, user_id, "Laptop", credential)
+            .await
+            .unwrap();
+        db_api_token::create_token(
+            &db,
+            db_api_token::NewApiToken {
+                user_id,
+                name: "ci".to_string(),
+                scopes: vec![],
+                expires_at: None,
+            },
+        )
+        .await
+        .unwrap();
+        let event = NewAuditEvent {
+            action: "login".to_string(),
+            actor_id: Some(user_id),
+            target_id: None,
+            ip_address: Some("203.0.113.7".to_string()),
+            user_agent: Some("Firefox".to_string()),
+            details: None,
+        };
+        db_audit_event::insert_event(&db, event).await.unwrap();
+
+        let export = export_account(&db, user_id).await.unwrap().unwrap();
+        let export = serde_json::to_value(export).unwrap();
+
+        assert_eq!(export["user"]["email"], "user@example.com");
+        assert_eq!(export["profile"]["display_name"], "User");
+        assert_eq!(export["sessions"][0]["ip_address"], "203.0.113.7");
+        assert_eq!(export["linked_accounts"][0]["provider"], "github");
+        assert_eq!(export["passkeys"][0]["name"], "Laptop");
+        assert_eq!(export["api_tokens"][0]["name"], "ci");
+        assert_eq!(export["audit_events"][0]["action"], "login");
+        assert_eq!(export["audit_events"][0]["ip_address"], "203.0.113.7");
+        assert_eq!(export["audit_events"][0]["user_agent"], "Firefox");
+        assert!(export["audit_events"][0]["created_at"]
+            .as_str()
+            .is_some_and(|created_at| !created_at.is_empty()));
+    }
+}
@@ -0,0 +1,64 @@
+use entity::session;
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder,
+};
+use tower_sessions::cookie::time::OffsetDateTime;
+
+pub type SessionModel = session::Model;
+
+/// Lists the sessions the user is logged in with, most recently used first.
+pub async fn list_sessions(
+    db: &DatabaseConnection,
+    user_id: i32,
+) -> Result<Vec<SessionModel>, DbErr> {
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+
+    session::Entity::find()
+        .filter(session::Column::UserId.eq(user_id))
+        .filter(session::Column::Expiry.gte(now))
+        .order_by_desc(session::Column::LastSeenAt)
+        .all(db)
+        .await
+}
+
+/// Deletes one of the user's sessions, returning false if it doesn't exist or
+/// belongs to someone else.
+pub async fn delete_session(
+    db: &DatabaseConnection,
+    user_id: i32,
+    session_id: &str,
+) -> Result<bool, DbErr> {
+    let result = session::Entity::delete_many()
+        .filter(session::Column::Id.eq(session_id))
+        .filter(session::Column::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+
+    Ok(result.rows_affected == 1)
+}
+
+/// Deletes all the user's sessions except the current one, returning how many
+/// were deleted.
+pub async fn delete_other_sessions(
+    db: &DatabaseConnection,
+    user_id: i32,
+    current_session_id: &str,
+) -> Result<u64, DbErr> {
+    let result = session::Entity::delete_many()
+        .filter(session::Column::UserId.eq(user_id))
+        .filter(session::Column::Id.ne(current_session_id))
+        .exec(db)
+        .await?;
+
+    Ok(result.rows_affected)
+}
+
+/// Deletes every session of the user, returning how many were deleted.
+pub async fn delete_all_sessions<C: ConnectionTrait>(db: &C, user_id: i32) -> Result<u64, DbErr> {
+    let result = session::Entity::delete_many()
+        .filter(session::Column::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+
+    Ok(result.rows_affected)
+}
@@ -0,0 +1,153 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+    Json,
+};
+use axum_login::AuthUser;
+use axum_messages::Messages;
+use serde::Deserialize;
+use serde_json::json;
+use tower_sessions::Session;
+use validator::Validate;
+
+use crate::{
+    app::AppState,
+    auth::{
+        db_webauthn,
+        layer::AuthSession,
+        webauthn::{self, RegistrationResponse},
+    },
+    error::AppError,
+};
+
+const REGISTRATION_CHALLENGE_KEY: &str = "user.passkey_registration_challenge";
+
+fn passkey_error(status: StatusCode, message: &str) -> Response {
+    (status, Json(json!({ "error": message }))).into_response()
+}
+
+pub async fn post_registration_options(
+    State(app): State<AppState>,
+    auth_session: AuthSession,
+    session: Session,
+) -> Result<Response, AppError> {
+    let user = auth_session.user.ok_or(AppError::Unauthenticated)?;
+
+    let existing = match db_webauthn::list_credentials(&app.database_connection, user.id()).await {
+        Ok(credentials) => credentials
+            .into_iter()
+            .map(|credential| credential.credential_id)
+            .collect::<Vec<_>>(),
+        Err(e) => {
+            tracing::error!("Failed to list passkeys: {:?}", e);
+            return Ok(passkey_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to start passkey registration, try again later",
+            ));
+        }
+    };
+
+    match webauthn::start_ceremony(&session, REGISTRATION_CHALLENGE_KEY).await {
+        Ok(challenge) => Ok(Json(json!({
+            "publicKey": app.webauthn.registration_options(
+                &challenge,
+                user.id(),
+                user.email(),
+                &existing,
+            ),
+        }))
+        .into_response()),
+        Err(e) => {
+            tracing::error!("Failed to start passkey registration: {:?}", e);
+            Ok(passkey_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to start passkey registration, try again later",
+            ))
+        }
+    }
+}
+
+#[derive(Deserialize, Validate)]
+pub struct RegisterPasskeyRequest {
+    #[validate(length(min = 1, max = 64, message = "Name must have 1 to 64 characters"))]
+    name: String,
+    credential: RegistrationResponse,
+}
+
+pub async fn post_register_passkey(
+    State(app): State<AppState>,
+    auth_session: AuthSession,
+    session: Session,
+    Json(request): Json<RegisterPasskeyRequest>,
+) -> Result<Response, AppError> {
+    let user = auth_session.user.ok_or(AppError::Unauthenticated)?;
+
+    if request.validate().is_err() {
+        return Ok(passkey_error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Name must have 1 to 64 characters",
+        ));
+    }
+
+    let Some(challenge) = webauthn::finish_ceremony(&session, REGISTRATION_CHALLENGE_KEY).await
+    else {
+        return Ok(passkey_error(
+            StatusCode::BAD_REQUEST,
+            "The passkey request has expired, try again",
+        ));
+    };
+
+    let credential = match app
+        .webauthn
+        .verify_registration(&challenge, &request.credential)
+    {
+        Ok(credential) => credential,
+        Err(e) => {
+            tracing::warn!("Rejected passkey registration: {}", e);
+            return Ok(passkey_error(
+                StatusCode::BAD_REQUEST,
+                "This passkey can't be used",
+            ));
+        }
+    };
+
+    match db_webauthn::create_credential(
+        &app.database_connection,
+        user.id(),
+        &request.name,
+        credential,
+    )
+    .await
+    {
+        Ok(_) => Ok(Json(json!({ "ok": true })).into_response()),
+        Err(e) => {
+            tracing::error!("Failed to save passkey: {:?}", e);
+            Ok(passkey_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to save passkey, try again later",
+            ))
+        }
+    }
+}
+
+pub async fn post_delete_passkey(
+    State(app): State<AppState>,
+    auth_session: AuthSession,
+    messages: Messages,
+    Path(id): Path<i32>,
+) -> Result<Response, AppError> {
+    let user = auth_session.user.ok_or(AppError::Unauthenticated)?;
+
+    match db_webauthn::delete_credential(&app.database_connection, user.id(), id).await {
+        Ok(_) => {
+            messages.success("Passkey removed");
+        }
+        Err(e) => {
+            tracing::error!("Failed to delete passkey: {:?}", e);
+            messages.error("Failed to remove the passkey, try again later");
+        }
+    }
+
+    Ok(Redirect::to("/user/security").into_response())
+}
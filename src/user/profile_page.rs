@@ -1,26 +1,72 @@
 use axum::{
-    extract::State,
-    response::{IntoResponse, Response},
+    extract::{Path, State},
+    response::{IntoResponse, Redirect, Response},
     Form,
 };
 use axum_login::AuthUser;
+use axum_messages::Messages;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use validator::{Validate, ValidationErrors};
 
-use crate::{app::AppState, auth::layer::AuthSession, layout::template_response::TemplateResponse};
+use crate::{
+    app::AppState,
+    audit::{action::Action, context::AuditContext, event::AuditEvent},
+    auth::{db_user_identity, layer::AuthSession},
+    error::AppError,
+    layout::template_response::TemplateResponse,
+};
 
 use super::db_user_profile::{self, get_user_profile, GetUserProfileResult};
 
-#[derive(Serialize, Deserialize, Default, Validate, Clone)]
+#[derive(Serialize, Deserialize, Default, Validate, Clone, ToSchema)]
 pub struct ProfileForm {
     #[validate(length(min = 1, message = "Display name is required"))]
+    #[schema(min_length = 1)]
     display_name: String,
 }
 
+/// An OpenID Connect provider and, when linked, the user's identity there.
+#[derive(Serialize)]
+pub struct LinkedAccount {
+    provider: String,
+    provider_name: String,
+    identity_id: Option<i32>,
+    email: Option<String>,
+}
+
+async fn linked_accounts(app: &AppState, user_id: i32) -> Vec<LinkedAccount> {
+    let identities =
+        match db_user_identity::list_identities(&app.database_connection, user_id).await {
+            Ok(identities) => identities,
+            Err(e) => {
+                tracing::error!("Failed to list linked accounts: {:?}", e);
+                Vec::new()
+            }
+        };
+
+    app.oidc_providers
+        .iter()
+        .map(|provider| {
+            let identity = identities
+                .iter()
+                .find(|identity| identity.provider == provider.slug());
+
+            LinkedAccount {
+                provider: provider.slug().to_string(),
+                provider_name: provider.name().to_string(),
+                identity_id: identity.map(|identity| identity.id),
+                email: identity.and_then(|identity| identity.email.clone()),
+            }
+        })
+        .collect()
+}
+
 #[derive(Serialize, Default)]
 pub struct ProfilePage {
     form: ProfileForm,
     errors: ValidationErrors,
+    linked_accounts: Vec<LinkedAccount>,
 }
 
 impl From<GetUserProfileResult> for ProfileForm {
@@ -31,19 +77,23 @@ impl From<GetUserProfileResult> for ProfileForm {
     }
 }
 
-pub async fn get_profile_page(State(app): State<AppState>, auth_session: AuthSession) -> Response {
-    let user = auth_session.user.unwrap();
+pub async fn get_profile_page(
+    State(app): State<AppState>,
+    auth_session: AuthSession,
+) -> Result<Response, AppError> {
+    let user = auth_session.user.ok_or(AppError::Unauthenticated)?;
     let form = match get_user_profile(&app.database_connection, user.id()).await {
         Some(profile) => profile.into(),
         None => ProfileForm::default(),
     };
 
-    TemplateResponse::new("user/profile")
+    Ok(TemplateResponse::new("user/profile")
         .content(ProfilePage {
             form,
             errors: ValidationErrors::default(),
+            linked_accounts: linked_accounts(&app, user.id()).await,
         })
-        .into_response()
+        .into_response())
 }
 
 impl From<ProfileForm> for db_user_profile::SaveUserProfileData {
@@ -57,13 +107,14 @@ impl From<ProfileForm> for db_user_profile::SaveUserProfileData {
 pub async fn post_profile_page(
     State(app): State<AppState>,
     auth_session: AuthSession,
+    audit: AuditContext,
     Form(form): Form<ProfileForm>,
-) -> Response {
-    let user = auth_session.user.unwrap();
+) -> Result<Response, AppError> {
+    let user = auth_session.user.ok_or(AppError::Unauthenticated)?;
     let user_id = user.id();
     let response = TemplateResponse::new("user/profile");
 
-    match form.validate() {
+    let response = match form.validate() {
         Ok(()) => {
             match db_user_profile::save_user_profile(
                 &app.database_connection,
@@ -72,13 +123,23 @@ pub async fn post_profile_page(
             )
             .await
             {
-                Ok(()) => response
-                    .content(ProfilePage {
-                        form,
-                        errors: ValidationErrors::default(),
-                    })
-                    .add_success_message("Profile updated")
-                    .into_response(),
+                Ok(()) => {
+                    audit
+                        .record(
+                            &app.database_connection,
+                            AuditEvent::new(Action::ProfileUpdated).by_user(user_id),
+                        )
+                        .await;
+
+                    response
+                        .content(ProfilePage {
+                            form,
+                            errors: ValidationErrors::default(),
+                            linked_accounts: linked_accounts(&app, user_id).await,
+                        })
+                        .add_success_message("Profile updated")
+                        .into_response()
+                }
                 Err(e) => {
                     tracing::error!("Failed to save user profile: {:?}", e);
 
@@ -86,6 +147,7 @@ pub async fn post_profile_page(
                         .content(ProfilePage {
                             form,
                             errors: ValidationErrors::default(),
+                            linked_accounts: linked_accounts(&app, user_id).await,
                         })
                         .add_error_message("Failed to save user profile")
                         .into_response()
@@ -93,7 +155,35 @@ pub async fn post_profile_page(
             }
         }
         Err(errors) => response
-            .content(ProfilePage { form, errors })
+            .content(ProfilePage {
+                form,
+                errors,
+                linked_accounts: linked_accounts(&app, user_id).await,
+            })
             .into_response(),
+    };
+
+    Ok(response)
+}
+
+pub async fn post_unlink_account(
+    State(app): State<AppState>,
+    auth_session: AuthSession,
+    messages: Messages,
+    Path(identity_id): Path<i32>,
+) -> Result<Redirect, AppError> {
+    let user = auth_session.user.ok_or(AppError::Unauthenticated)?;
+
+    match db_user_identity::delete_identity(&app.database_connection, user.id(), identity_id).await
+    {
+        Ok(_) => {
+            messages.success("Account unlinked");
+        }
+        Err(e) => {
+            tracing::error!("Failed to unlink account: {:?}", e);
+            messages.error("Failed to unlink the account, try again later");
+        }
     }
+
+    Ok(Redirect::to("/user/profile"))
 }
@@ -1,4 +1,6 @@
-use super::profile_page;
+use super::{
+    account_page, activity_page, passkeys, profile_page, security_page, sessions_page, tokens_page,
+};
 use crate::app::AppState;
 use axum::{
     routing::{get, post},
@@ -9,4 +11,74 @@ pub fn router() -> Router<AppState> {
     Router::new()
         .route("/user/profile", get(profile_page::get_profile_page))
         .route("/user/profile", post(profile_page::post_profile_page))
+        .route(
+            "/user/linked-accounts/:id/delete",
+            post(profile_page::post_unlink_account),
+        )
+        .route("/user/account", get(account_page::get_account_page))
+        .route(
+            "/user/account/export",
+            get(account_page::get_account_export),
+        )
+        .route(
+            "/user/account/delete",
+            post(account_page::post_delete_account),
+        )
+        .route(
+            "/user/account/cancel-deletion",
+            post(account_page::post_cancel_deletion),
+        )
+        .route("/user/security", get(security_page::get_security_page))
+        .route(
+            "/user/security/password",
+            post(security_page::post_change_password),
+        )
+        .route(
+            "/user/security/email",
+            post(security_page::post_change_email),
+        )
+        .route(
+            "/user/security/totp/setup",
+            post(security_page::post_totp_setup),
+        )
+        .route(
+            "/user/security/totp/confirm",
+            post(security_page::post_totp_confirm),
+        )
+        .route(
+            "/user/security/totp/disable",
+            post(security_page::post_totp_disable),
+        )
+        .route(
+            "/user/security/recovery-codes",
+            post(security_page::post_recovery_codes),
+        )
+        .route(
+            "/user/security/passkeys/options",
+            post(passkeys::post_registration_options),
+        )
+        .route(
+            "/user/security/passkeys",
+            post(passkeys::post_register_passkey),
+        )
+        .route(
+            "/user/security/passkeys/:id/delete",
+            post(passkeys::post_delete_passkey),
+        )
+        .route("/user/activity", get(activity_page::get_activity_page))
+        .route("/user/sessions", get(sessions_page::get_sessions_page))
+        .route(
+            "/user/sessions/others/revoke",
+            post(sessions_page::post_revoke_other_sessions),
+        )
+        .route(
+            "/user/sessions/:key/revoke",
+            post(sessions_page::post_revoke_session),
+        )
+        .route("/user/tokens", get(tokens_page::get_tokens_page))
+        .route("/user/tokens", post(tokens_page::post_create_token))
+        .route(
+            "/user/tokens/:id/delete",
+            post(tokens_page::post_delete_token),
+        )
 }
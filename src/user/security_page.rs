@@ -0,0 +1,469 @@
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_login::AuthUser;
+use axum_messages::Messages;
+use serde::{Deserialize, Serialize};
+use tower_sessions::{cookie::time::OffsetDateTime, Session};
+use validator::{Validate, ValidationError, ValidationErrors};
+
+use super::db_user_session;
+use crate::{
+    app::AppState,
+    auth::{
+        client_ip::ClientIp,
+        db_email_verification, db_login_throttle, db_two_factor, db_user, db_webauthn,
+        email_verification_page::send_email_change_verification,
+        layer::{AuthSession, User},
+        totp,
+    },
+    error::AppError,
+    layout::template_response::TemplateResponse,
+    mailer::Email,
+};
+
+const PENDING_TOTP_SECRET_KEY: &str = "user.pending_totp_secret";
+
+#[derive(Serialize, Default)]
+pub struct TotpSetup {
+    secret: String,
+    otpauth_uri: String,
+    qr_code_svg: Option<String>,
+}
+
+impl TotpSetup {
+    fn new(secret: String, account: &str) -> Self {
+        let otpauth_uri = totp::otpauth_uri(&secret, account);
+        let qr_code_svg = totp::qr_code_svg(&otpauth_uri);
+
+        Self {
+            secret,
+            otpauth_uri,
+            qr_code_svg,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Passkey {
+    id: i32,
+    name: String,
+    created_on: String,
+    last_used_on: Option<String>,
+}
+
+fn format_date(timestamp: i64) -> String {
+    OffsetDateTime::from_unix_timestamp(timestamp)
+        .map(|date_time| date_time.date().to_string())
+        .unwrap_or_default()
+}
+
+impl From<db_webauthn::WebauthnCredentialModel> for Passkey {
+    fn from(credential: db_webauthn::WebauthnCredentialModel) -> Self {
+        Passkey {
+            id: credential.id,
+            name: credential.name,
+            created_on: format_date(credential.created_at),
+            last_used_on: credential.last_used_at.map(format_date),
+        }
+    }
+}
+
+#[derive(Deserialize, Default, Validate)]
+pub struct ChangePasswordForm {
+    current_password: String,
+    #[validate(length(min = 8, message = "Password must be at least 8 characters long"))]
+    new_password: String,
+    #[validate(must_match(other = "new_password", message = "Passwords do not match"))]
+    confirm_password: String,
+}
+
+#[derive(Deserialize, Default, Validate)]
+pub struct ChangeEmailForm {
+    current_password: String,
+    #[validate(email(message = "Invalid email address"))]
+    email: String,
+}
+
+/// What the page shows back of a submitted email change, never the password.
+#[derive(Serialize, Default)]
+pub struct ChangeEmailFormValues {
+    email: String,
+}
+
+impl From<ChangeEmailForm> for ChangeEmailFormValues {
+    fn from(form: ChangeEmailForm) -> Self {
+        ChangeEmailFormValues { email: form.email }
+    }
+}
+
+#[derive(Serialize, Default)]
+pub struct SecurityPage {
+    email: String,
+    change_password_errors: Option<ValidationErrors>,
+    change_email_form: ChangeEmailFormValues,
+    change_email_errors: Option<ValidationErrors>,
+    pending_email: Option<String>,
+    two_factor_enabled: bool,
+    recovery_codes_remaining: u64,
+    totp_setup: Option<TotpSetup>,
+    recovery_codes: Option<Vec<String>>,
+    passkeys: Vec<Passkey>,
+}
+
+impl SecurityPage {
+    async fn load(app: &AppState, user: &User) -> Self {
+        let user_id = user.id();
+        let two_factor_enabled = db_two_factor::get_totp_secret(&app.database_connection, user_id)
+            .await
+            .is_some();
+        let recovery_codes_remaining =
+            db_two_factor::count_unused_recovery_codes(&app.database_connection, user_id).await;
+        let passkeys = match db_webauthn::list_credentials(&app.database_connection, user_id).await
+        {
+            Ok(credentials) => credentials.into_iter().map(Passkey::from).collect(),
+            Err(e) => {
+                tracing::error!("Failed to list passkeys: {:?}", e);
+                Vec::new()
+            }
+        };
+
+        let pending_email = match db_email_verification::pending_email_change(
+            &app.database_connection,
+            user_id,
+            user.email(),
+        )
+        .await
+        {
+            Ok(pending_email) => pending_email,
+            Err(e) => {
+                tracing::error!("Failed to load pending email change: {:?}", e);
+                None
+            }
+        };
+
+        Self {
+            email: user.email().to_string(),
+            pending_email,
+            two_factor_enabled,
+            recovery_codes_remaining,
+            passkeys,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CodeForm {
+    code: String,
+}
+
+pub async fn get_security_page(
+    State(app): State<AppState>,
+    auth_session: AuthSession,
+) -> Result<Response, AppError> {
+    let user = auth_session.user.ok_or(AppError::Unauthenticated)?;
+
+    Ok(TemplateResponse::new("user/security")
+        .content(SecurityPage::load(&app, &user).await)
+        .into_response())
+}
+
+pub async fn post_totp_setup(
+    State(app): State<AppState>,
+    auth_session: AuthSession,
+    session: Session,
+    messages: Messages,
+) -> Result<Response, AppError> {
+    let user = auth_session.user.ok_or(AppError::Unauthenticated)?;
+    let response = TemplateResponse::new("user/security");
+    let mut page = SecurityPage::load(&app, &user).await;
+    if page.two_factor_enabled {
+        messages.warning("Two-factor authentication is already enabled");
+        return Ok(Redirect::to("/user/security").into_response());
+    }
+
+    let secret = totp::generate_secret();
+    if let Err(e) = session.insert(PENDING_TOTP_SECRET_KEY, &secret).await {
+        tracing::error!("Failed to store pending TOTP secret: {:?}", e);
+        return Ok(response
+            .content(page)
+            .add_error_message("Failed to start two-factor setup, try again later")
+            .into_response());
+    }
+
+    page.totp_setup = Some(TotpSetup::new(secret, user.email()));
+    Ok(response.content(page).into_response())
+}
+
+pub async fn post_totp_confirm(
+    State(app): State<AppState>,
+    auth_session: AuthSession,
+    session: Session,
+    messages: Messages,
+    Form(form): Form<CodeForm>,
+) -> Result<Response, AppError> {
+    let user = auth_session.user.ok_or(AppError::Unauthenticated)?;
+    let response = TemplateResponse::new("user/security");
+    let mut page = SecurityPage::load(&app, &user).await;
+
+    let secret = match session.get::<String>(PENDING_TOTP_SECRET_KEY).await {
+        Ok(Some(secret)) => secret,
+        _ => {
+            messages.warning("Two-factor setup expired, start it again");
+            return Ok(Redirect::to("/user/security").into_response());
+        }
+    };
+
+    let Some(step) = totp::verify(&secret, &form.code, None) else {
+        page.totp_setup = Some(TotpSetup::new(secret, user.email()));
+        return Ok(response
+            .content(page)
+            .add_error_message("Invalid authentication code, try again")
+            .into_response());
+    };
+
+    match db_two_factor::enable_totp(&app.database_connection, user.id(), &secret, step).await {
+        Ok(recovery_codes) => {
+            if let Err(e) = session.remove_value(PENDING_TOTP_SECRET_KEY).await {
+                tracing::error!("Failed to clear pending TOTP secret: {:?}", e);
+            }
+
+            let mut page = SecurityPage::load(&app, &user).await;
+            page.recovery_codes = Some(recovery_codes);
+            Ok(response
+                .content(page)
+                .add_success_message("Two-factor authentication enabled")
+                .into_response())
+        }
+        Err(e) => {
+            tracing::error!("Failed to enable two-factor authentication: {:?}", e);
+
+            Ok(response
+                .content(page)
+                .add_error_message("Failed to enable two-factor authentication")
+                .into_response())
+        }
+    }
+}
+
+pub async fn post_totp_disable(
+    State(app): State<AppState>,
+    auth_session: AuthSession,
+    Form(form): Form<CodeForm>,
+) -> Result<Response, AppError> {
+    let user = auth_session.user.ok_or(AppError::Unauthenticated)?;
+    let response = TemplateResponse::new("user/security");
+
+    if !db_two_factor::verify_code(&app.database_connection, user.id(), &form.code).await {
+        return Ok(response
+            .content(SecurityPage::load(&app, &user).await)
+            .add_error_message("Invalid authentication code")
+            .into_response());
+    }
+
+    match db_two_factor::disable_totp(&app.database_connection, user.id()).await {
+        Ok(()) => Ok(response
+            .content(SecurityPage::load(&app, &user).await)
+            .add_success_message("Two-factor authentication disabled")
+            .into_response()),
+        Err(e) => {
+            tracing::error!("Failed to disable two-factor authentication: {:?}", e);
+
+            Ok(response
+                .content(SecurityPage::load(&app, &user).await)
+                .add_error_message("Failed to disable two-factor authentication")
+                .into_response())
+        }
+    }
+}
+
+pub async fn post_recovery_codes(
+    State(app): State<AppState>,
+    auth_session: AuthSession,
+    Form(form): Form<CodeForm>,
+) -> Result<Response, AppError> {
+    let user = auth_session.user.ok_or(AppError::Unauthenticated)?;
+    let response = TemplateResponse::new("user/security");
+
+    if !db_two_factor::verify_code(&app.database_connection, user.id(), &form.code).await {
+        return Ok(response
+            .content(SecurityPage::load(&app, &user).await)
+            .add_error_message("Invalid authentication code")
+            .into_response());
+    }
+
+    match db_two_factor::regenerate_recovery_codes(&app.database_connection, user.id()).await {
+        Ok(recovery_codes) => {
+            let mut page = SecurityPage::load(&app, &user).await;
+            page.recovery_codes = Some(recovery_codes);
+            Ok(response
+                .content(page)
+                .add_success_message("New recovery codes generated")
+                .into_response())
+        }
+        Err(e) => {
+            tracing::error!("Failed to regenerate recovery codes: {:?}", e);
+
+            Ok(response
+                .content(SecurityPage::load(&app, &user).await)
+                .add_error_message("Failed to generate new recovery codes")
+                .into_response())
+        }
+    }
+}
+
+fn incorrect_password() -> ValidationErrors {
+    let mut errors = ValidationErrors::new();
+    let mut error = ValidationError::new("incorrect_password");
+    error.message = Some("Current password is incorrect".into());
+    errors.add("current_password", error);
+    errors
+}
+
+/// Checks the current password before a sensitive change. Wrong guesses count
+/// towards the login lockout, so a stolen session can't be used to find out
+/// the password.
+pub async fn check_current_password(
+    app: &AppState,
+    user: &User,
+    ip: &str,
+    password: &str,
+) -> Result<(), ValidationErrors> {
+    let db = &app.database_connection;
+    match db_login_throttle::locked_until(db, user.email(), ip).await {
+        Ok(Some(_)) => return Err(incorrect_password()),
+        Ok(None) => {}
+        Err(e) => tracing::error!("Failed to check login throttling: {:?}", e),
+    }
+
+    if user.verify_password(password) {
+        return Ok(());
+    }
+
+    if let Err(e) = db_login_throttle::record_failure(db, user.email(), ip).await {
+        tracing::error!("Failed to record failed password check: {:?}", e);
+    }
+
+    Err(incorrect_password())
+}
+
+pub async fn post_change_password(
+    State(app): State<AppState>,
+    mut auth_session: AuthSession,
+    session: Session,
+    ClientIp(ip): ClientIp,
+    Form(form): Form<ChangePasswordForm>,
+) -> Result<Response, AppError> {
+    let user = auth_session.user.clone().ok_or(AppError::Unauthenticated)?;
+    let response = TemplateResponse::new("user/security");
+    let mut page = SecurityPage::load(&app, &user).await;
+
+    let checked = match form.validate() {
+        Ok(()) => {
+            check_current_password(&app, &user, &ip.to_string(), &form.current_password).await
+        }
+        Err(errors) => Err(errors),
+    };
+    if let Err(errors) = checked {
+        page.change_password_errors = Some(errors);
+        return Ok(response.content(page).into_response());
+    }
+
+    let updated_user =
+        match db_user::update_password(&app.database_connection, user.id(), &form.new_password)
+            .await
+        {
+            Ok(updated_user) => User::from(updated_user),
+            Err(e) => {
+                tracing::error!("Failed to change password: {:?}", e);
+                return Ok(response
+                    .content(page)
+                    .add_error_message("Failed to change password, try again later")
+                    .into_response());
+            }
+        };
+
+    // The session hash comes from the password hash, logging in again keeps
+    // this session valid while every other one is rejected.
+    if let Err(e) = auth_session.login(&updated_user).await {
+        tracing::error!("Failed to refresh session after password change: {:?}", e);
+    }
+    let current_session_id = session.id().map(|id| id.to_string()).unwrap_or_default();
+    if let Err(e) = db_user_session::delete_other_sessions(
+        &app.database_connection,
+        user.id(),
+        &current_session_id,
+    )
+    .await
+    {
+        tracing::error!("Failed to revoke sessions after password change: {:?}", e);
+    }
+
+    Ok(response
+        .content(SecurityPage::load(&app, &updated_user).await)
+        .add_success_message("Password changed, all other sessions were signed out")
+        .into_response())
+}
+
+pub async fn post_change_email(
+    State(app): State<AppState>,
+    auth_session: AuthSession,
+    ClientIp(ip): ClientIp,
+    Form(form): Form<ChangeEmailForm>,
+) -> Result<Response, AppError> {
+    let user = auth_session.user.ok_or(AppError::Unauthenticated)?;
+    let response = TemplateResponse::new("user/security");
+    let mut page = SecurityPage::load(&app, &user).await;
+
+    let checked = match form.validate() {
+        Ok(()) => {
+            check_current_password(&app, &user, &ip.to_string(), &form.current_password).await
+        }
+        Err(errors) => Err(errors),
+    };
+    if let Err(errors) = checked {
+        page.change_email_form = form.into();
+        page.change_email_errors = Some(errors);
+        return Ok(response.content(page).into_response());
+    }
+
+    if form.email == user.email() {
+        page.change_email_form = form.into();
+        return Ok(response
+            .content(page)
+            .add_error_message("This is already your email address")
+            .into_response());
+    }
+    if db_user::user_exists(&app.database_connection, &form.email).await {
+        page.change_email_form = form.into();
+        return Ok(response
+            .content(page)
+            .add_error_message("This email address is already used by another account")
+            .into_response());
+    }
+
+    send_email_change_verification(&app, user.id(), &form.email).await;
+
+    let notice = Email {
+        to: user.email().to_string(),
+        subject: "Your email address is being changed".to_string(),
+        body: format!(
+            "Someone asked to change the email address of your account to {}. The change \
+            happens once the new address is verified.\n\n\
+            If this wasn't you, change your password right away: {}",
+            form.email,
+            app.config.url("/forgot-password")
+        ),
+    };
+    if let Err(e) = app.mailer.send(notice).await {
+        tracing::error!("Failed to send email change notice: {:?}", e);
+    }
+
+    Ok(response
+        .content(SecurityPage::load(&app, &user).await)
+        .add_success_message("Check your inbox to confirm the new address")
+        .into_response())
+}
@@ -0,0 +1,167 @@
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+};
+use axum_login::AuthUser;
+use serde::Serialize;
+use tower_sessions::{cookie::time::OffsetDateTime, Session};
+
+use crate::{
+    app::AppState,
+    auth::{layer::AuthSession, token},
+    error::AppError,
+    layout::template_response::TemplateResponse,
+};
+
+use super::db_user_session::{self, SessionModel};
+
+/// A session as shown to its user. Session ids work as credentials, so the
+/// page refers to them by their hash.
+#[derive(Serialize)]
+pub struct ActiveSession {
+    key: String,
+    current: bool,
+    signed_in_at: String,
+    last_seen_at: String,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+}
+
+pub fn format_date_time(timestamp: i64) -> String {
+    OffsetDateTime::from_unix_timestamp(timestamp)
+        .map(|date_time| {
+            format!(
+                "{} {:02}:{:02} UTC",
+                date_time.date(),
+                date_time.hour(),
+                date_time.minute()
+            )
+        })
+        .unwrap_or_default()
+}
+
+impl ActiveSession {
+    fn new(session: SessionModel, current_session_id: Option<&str>) -> Self {
+        ActiveSession {
+            key: token::hash(&session.id),
+            current: Some(session.id.as_str()) == current_session_id,
+            signed_in_at: format_date_time(session.created_at),
+            last_seen_at: format_date_time(session.last_seen_at),
+            ip_address: session.ip_address,
+            user_agent: session.user_agent,
+        }
+    }
+}
+
+#[derive(Serialize, Default)]
+pub struct SessionsPage {
+    sessions: Vec<ActiveSession>,
+    has_other_sessions: bool,
+}
+
+impl SessionsPage {
+    async fn load(app: &AppState, user_id: i32, session: &Session) -> Self {
+        let current_session_id = session.id().map(|id| id.to_string());
+        let sessions = match db_user_session::list_sessions(&app.database_connection, user_id).await
+        {
+            Ok(sessions) => sessions
+                .into_iter()
+                .map(|session| ActiveSession::new(session, current_session_id.as_deref()))
+                .collect::<Vec<_>>(),
+            Err(e) => {
+                tracing::error!("Failed to list sessions: {:?}", e);
+                Vec::new()
+            }
+        };
+        let has_other_sessions = sessions.iter().any(|session| !session.current);
+
+        Self {
+            sessions,
+            has_other_sessions,
+        }
+    }
+}
+
+pub async fn get_sessions_page(
+    State(app): State<AppState>,
+    auth_session: AuthSession,
+    session: Session,
+) -> Result<Response, AppError> {
+    let user = auth_session.user.ok_or(AppError::Unauthenticated)?;
+
+    Ok(TemplateResponse::new("user/sessions")
+        .content(SessionsPage::load(&app, user.id(), &session).await)
+        .into_response())
+}
+
+pub async fn post_revoke_session(
+    State(app): State<AppState>,
+    auth_session: AuthSession,
+    session: Session,
+    Path(key): Path<String>,
+) -> Result<Response, AppError> {
+    let user = auth_session.user.ok_or(AppError::Unauthenticated)?;
+    let response = TemplateResponse::new("user/sessions").hx_push_url("/user/sessions");
+    let current_session_id = session.id().map(|id| id.to_string());
+
+    let revoked = match db_user_session::list_sessions(&app.database_connection, user.id()).await {
+        Ok(sessions) => match sessions
+            .into_iter()
+            .find(|other| token::hash(&other.id) == key)
+        {
+            Some(other) if Some(&other.id) == current_session_id.as_ref() => {
+                return Ok(response
+                    .content(SessionsPage::load(&app, user.id(), &session).await)
+                    .add_error_message("Use Logout to end the current session")
+                    .into_response());
+            }
+            Some(other) => {
+                db_user_session::delete_session(&app.database_connection, user.id(), &other.id)
+                    .await
+            }
+            None => Ok(false),
+        },
+        Err(e) => Err(e),
+    };
+
+    let response = match revoked {
+        Ok(true) => response.add_success_message("Session signed out"),
+        Ok(false) => response.add_error_message("Session not found"),
+        Err(e) => {
+            tracing::error!("Failed to revoke session: {:?}", e);
+            response.add_error_message("Failed to sign out the session, try again later")
+        }
+    };
+
+    Ok(response
+        .content(SessionsPage::load(&app, user.id(), &session).await)
+        .into_response())
+}
+
+pub async fn post_revoke_other_sessions(
+    State(app): State<AppState>,
+    auth_session: AuthSession,
+    session: Session,
+) -> Result<Response, AppError> {
+    let user = auth_session.user.ok_or(AppError::Unauthenticated)?;
+    let response = TemplateResponse::new("user/sessions").hx_push_url("/user/sessions");
+    let current_session_id = session.id().map(|id| id.to_string()).unwrap_or_default();
+
+    let response = match db_user_session::delete_other_sessions(
+        &app.database_connection,
+        user.id(),
+        &current_session_id,
+    )
+    .await
+    {
+        Ok(_) => response.add_success_message("Signed out of all other sessions"),
+        Err(e) => {
+            tracing::error!("Failed to revoke other sessions: {:?}", e);
+            response.add_error_message("Failed to sign out other sessions, try again later")
+        }
+    };
+
+    Ok(response
+        .content(SessionsPage::load(&app, user.id(), &session).await)
+        .into_response())
+}
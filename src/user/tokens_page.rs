@@ -0,0 +1,262 @@
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+    Form,
+};
+use axum_login::AuthUser;
+use serde::Serialize;
+use tower_sessions::cookie::time::{Duration, OffsetDateTime};
+use validator::{ValidationError, ValidationErrors};
+
+use super::sessions_page::format_date_time;
+use crate::{
+    app::AppState,
+    audit::{action::Action, context::AuditContext, event::AuditEvent},
+    auth::{
+        db_api_token::{self, NewApiToken},
+        layer::AuthSession,
+        scope::{self, Scope},
+    },
+    error::AppError,
+    layout::template_response::TemplateResponse,
+};
+
+/// Lifetimes offered when creating a token, in days. An empty choice means
+/// the token never expires.
+const EXPIRY_CHOICES: [i64; 3] = [30, 90, 365];
+
+#[derive(Serialize, Default)]
+pub struct TokenForm {
+    name: String,
+    expires_in_days: String,
+    scopes: Vec<String>,
+}
+
+fn field_error(errors: &mut ValidationErrors, field: &'static str, message: &'static str) {
+    let mut error = ValidationError::new("invalid");
+    error.message = Some(message.into());
+    errors.add(field, error);
+}
+
+impl TokenForm {
+    /// Scopes are checkboxes sharing a name, which only a list of pairs can
+    /// hold.
+    fn from_pairs(pairs: Vec<(String, String)>) -> Self {
+        let mut form = TokenForm::default();
+        for (name, value) in pairs {
+            match name.as_str() {
+                "name" => form.name = value,
+                "expires_in_days" => form.expires_in_days = value,
+                "scopes" => form.scopes.push(value),
+                _ => {}
+            }
+        }
+        form
+    }
+
+    fn parse(&self, user_id: i32) -> Result<NewApiToken, ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+
+        let name = self.name.trim();
+        if name.is_empty() || name.len() > 100 {
+            field_error(
+                &mut errors,
+                "name",
+                "Name must be between 1 and 100 characters long",
+            );
+        }
+
+        let expires_at = match self.expires_in_days.parse::<i64>() {
+            _ if self.expires_in_days.is_empty() => None,
+            Ok(days) if EXPIRY_CHOICES.contains(&days) => {
+                Some((OffsetDateTime::now_utc() + Duration::days(days)).unix_timestamp())
+            }
+            _ => {
+                field_error(
+                    &mut errors,
+                    "expires_in_days",
+                    "Choose one of the expiration options",
+                );
+                None
+            }
+        };
+
+        let scopes: Vec<Scope> = Scope::ALL
+            .into_iter()
+            .filter(|scope| self.scopes.iter().any(|name| name == scope.as_str()))
+            .collect();
+        if scopes.is_empty() {
+            field_error(&mut errors, "scopes", "Choose at least one scope");
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        Ok(NewApiToken {
+            user_id,
+            name: name.to_string(),
+            scopes,
+            expires_at,
+        })
+    }
+}
+
+#[derive(Serialize)]
+pub struct ScopeOption {
+    name: &'static str,
+    label: &'static str,
+    checked: bool,
+}
+
+#[derive(Serialize)]
+pub struct TokenRow {
+    id: i32,
+    name: String,
+    scopes: Vec<&'static str>,
+    created_at: String,
+    expires_at: Option<String>,
+    last_used_at: Option<String>,
+    expired: bool,
+}
+
+#[derive(Serialize, Default)]
+pub struct TokensPage {
+    tokens: Vec<TokenRow>,
+    scope_options: Vec<ScopeOption>,
+    form: TokenForm,
+    errors: Option<ValidationErrors>,
+    /// Secret of the token just created, only shown once.
+    new_token: Option<String>,
+}
+
+impl TokensPage {
+    async fn load(app: &AppState, user_id: i32, form: TokenForm) -> Self {
+        let tokens = match db_api_token::list_tokens(&app.database_connection, user_id).await {
+            Ok(tokens) => tokens,
+            Err(e) => {
+                tracing::error!("Failed to list API tokens: {:?}", e);
+                Vec::new()
+            }
+        };
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+
+        Self {
+            tokens: tokens
+                .into_iter()
+                .map(|token| TokenRow {
+                    id: token.id,
+                    name: token.name,
+                    scopes: scope::split(&token.scopes)
+                        .into_iter()
+                        .map(Scope::as_str)
+                        .collect(),
+                    created_at: format_date_time(token.created_at),
+                    expires_at: token.expires_at.map(format_date_time),
+                    last_used_at: token.last_used_at.map(format_date_time),
+                    expired: token.expires_at.is_some_and(|expires_at| expires_at <= now),
+                })
+                .collect(),
+            scope_options: Scope::ALL
+                .into_iter()
+                .map(|scope| ScopeOption {
+                    name: scope.as_str(),
+                    label: scope.label(),
+                    checked: form.scopes.iter().any(|name| name == scope.as_str()),
+                })
+                .collect(),
+            form,
+            ..Default::default()
+        }
+    }
+}
+
+pub async fn get_tokens_page(
+    State(app): State<AppState>,
+    auth_session: AuthSession,
+) -> Result<Response, AppError> {
+    let user = auth_session.user.ok_or(AppError::Unauthenticated)?;
+    let form = TokenForm {
+        expires_in_days: EXPIRY_CHOICES[0].to_string(),
+        ..Default::default()
+    };
+
+    Ok(TemplateResponse::new("user/tokens")
+        .content(TokensPage::load(&app, user.id(), form).await)
+        .into_response())
+}
+
+pub async fn post_create_token(
+    State(app): State<AppState>,
+    auth_session: AuthSession,
+    audit: AuditContext,
+    Form(pairs): Form<Vec<(String, String)>>,
+) -> Result<Response, AppError> {
+    let user = auth_session.user.ok_or(AppError::Unauthenticated)?;
+    let response = TemplateResponse::new("user/tokens");
+    let form = TokenForm::from_pairs(pairs);
+
+    let new_token = match form.parse(user.id()) {
+        Ok(new_token) => new_token,
+        Err(errors) => {
+            let mut page = TokensPage::load(&app, user.id(), form).await;
+            page.errors = Some(errors);
+            return Ok(response.content(page).into_response());
+        }
+    };
+    let details = format!("{} ({})", new_token.name, scope::join(&new_token.scopes));
+
+    match db_api_token::create_token(&app.database_connection, new_token).await {
+        Ok(secret) => {
+            let event = AuditEvent::new(Action::ApiTokenCreated)
+                .by_user(user.id())
+                .details(details);
+            audit.record(&app.database_connection, event).await;
+
+            let mut page = TokensPage::load(&app, user.id(), TokenForm::default()).await;
+            page.new_token = Some(secret);
+            Ok(response
+                .content(page)
+                .add_success_message("Token created, copy it now, it won't be shown again")
+                .hx_trigger("tokenCreated")
+                .into_response())
+        }
+        Err(e) => {
+            tracing::error!("Failed to create API token: {:?}", e);
+            Ok(response
+                .content(TokensPage::load(&app, user.id(), form).await)
+                .add_error_message("Failed to create the token, try again later")
+                .into_response())
+        }
+    }
+}
+
+pub async fn post_delete_token(
+    State(app): State<AppState>,
+    auth_session: AuthSession,
+    audit: AuditContext,
+    Path(token_id): Path<i32>,
+) -> Result<Response, AppError> {
+    let user = auth_session.user.ok_or(AppError::Unauthenticated)?;
+    let response = TemplateResponse::new("user/tokens").hx_push_url("/user/tokens");
+
+    let response =
+        match db_api_token::delete_token(&app.database_connection, user.id(), token_id).await {
+            Ok(true) => {
+                let event = AuditEvent::new(Action::ApiTokenRevoked)
+                    .by_user(user.id())
+                    .details(format!("token {}", token_id));
+                audit.record(&app.database_connection, event).await;
+                response.add_success_message("Token revoked")
+            }
+            Ok(false) => response.add_error_message("Token not found"),
+            Err(e) => {
+                tracing::error!("Failed to delete API token: {:?}", e);
+                response.add_error_message("Failed to revoke the token, try again later")
+            }
+        };
+
+    Ok(response
+        .content(TokensPage::load(&app, user.id(), TokenForm::default()).await)
+        .into_response())
+}